@@ -3,7 +3,10 @@ use std::future::Future;
 use wasm_bindgen::closure::WasmClosure;
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
-use web_sys::{CanvasRenderingContext2d, Document, HtmlCanvasElement, HtmlImageElement, Window};
+use web_sys::{
+    CanvasRenderingContext2d, Document, HtmlCanvasElement, HtmlImageElement, Storage,
+    WebGl2RenderingContext, WebGlContextAttributes, Window,
+};
 
 macro_rules! log {
     ( $( $t:tt )* ) => {
@@ -21,6 +24,24 @@ pub fn document() -> Result<Document> {
         .ok_or_else(|| anyhow!("no document found"))
 }
 
+// The value of `name` in the page's query string, like `?name=value`.
+pub fn query_parameter(name: &str) -> Option<String> {
+    let search = window().ok()?.location().search().ok()?;
+    search
+        .trim_start_matches('?')
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
+pub fn local_storage() -> Result<Storage> {
+    window()?
+        .local_storage()
+        .map_err(|err| anyhow!("error opening localStorage: {:#?}", err))?
+        .ok_or_else(|| anyhow!("no localStorage found"))
+}
+
 pub fn canvas() -> Result<HtmlCanvasElement> {
     document()?
         .get_element_by_id("canvas")
@@ -29,8 +50,20 @@ pub fn canvas() -> Result<HtmlCanvasElement> {
         .map_err(|err| anyhow!("element with id canvas not HtmlCanvasElement: {:#?}", err))
 }
 
-pub fn context() -> Result<CanvasRenderingContext2d> {
-    canvas()?
+// A canvas that is never attached to the document, used to prepare images once.
+pub fn new_canvas(width: u32, height: u32) -> Result<HtmlCanvasElement> {
+    let canvas = document()?
+        .create_element("canvas")
+        .map_err(|js_value| anyhow!("error creating canvas: {:#?}", js_value))?
+        .dyn_into::<HtmlCanvasElement>()
+        .map_err(|element| anyhow!("error converting {:#?} into HtmlCanvasElement", element))?;
+    canvas.set_width(width);
+    canvas.set_height(height);
+    Ok(canvas)
+}
+
+pub fn context_of(canvas: &HtmlCanvasElement) -> Result<CanvasRenderingContext2d> {
+    canvas
         .get_context("2d")
         .map_err(|js_value| anyhow!("error getting 2d context: {:#?}", js_value))?
         .ok_or_else(|| anyhow!("no 2d context found"))?
@@ -43,6 +76,24 @@ pub fn context() -> Result<CanvasRenderingContext2d> {
         })
 }
 
+// `None` when the browser or GPU has no WebGL2. A canvas only ever has one kind
+// of context, so this has to be decided before asking for a 2d one.
+pub fn webgl2_context_of(canvas: &HtmlCanvasElement) -> Result<Option<WebGl2RenderingContext>> {
+    let mut attributes = WebGlContextAttributes::new();
+    attributes.antialias(false).premultiplied_alpha(true);
+    canvas
+        .get_context_with_context_options("webgl2", &attributes)
+        .map_err(|js_value| anyhow!("error getting webgl2 context: {:#?}", js_value))?
+        .map(|object| {
+            object
+                .dyn_into::<WebGl2RenderingContext>()
+                .map_err(|object| {
+                    anyhow!("error converting {:#?} into WebGl2RenderingContext", object)
+                })
+        })
+        .transpose()
+}
+
 pub fn now() -> Result<f64> {
     Ok(window()?
         .performance()
@@ -62,6 +113,14 @@ pub fn new_image() -> Result<HtmlImageElement> {
         .map_err(|js_value| anyhow!("error creating new HtmlImageElement: {:#?}", js_value))
 }
 
+// Resolves once `image` can be drawn and its pixels read.
+pub async fn decode_image(image: &HtmlImageElement) -> Result<()> {
+    wasm_bindgen_futures::JsFuture::from(image.decode())
+        .await
+        .map_err(|err| anyhow!("error decoding image: {:#?}", err))?;
+    Ok(())
+}
+
 pub fn closure_wrap<T>(data: Box<T>) -> Closure<T>
 where
     T: ?Sized + WasmClosure,
@@ -1,8 +1,21 @@
+mod canvas;
+mod color;
+mod font;
 mod input;
+mod particles;
+mod random;
+mod render_queue;
 mod renderer;
+mod screen;
 mod sprite_sheet;
+pub mod storage;
+mod transition;
+mod ui;
+mod webgl;
 
 use crate::browser;
+use canvas::Canvas2d;
+use webgl::WebGl2;
 
 use anyhow::Result;
 use async_trait::async_trait;
@@ -10,17 +23,30 @@ use base64::{engine::general_purpose::STANDARD, Engine as _};
 use serde::{Deserialize, Serialize};
 use std::{cell::RefCell, rc::Rc};
 
-pub use input::{
-    KeyState, KEY_CODE_ARROW_DOWN, KEY_CODE_ARROW_LEFT, KEY_CODE_ARROW_RIGHT, KEY_CODE_ARROW_UP,
+pub use font::{Font, FontDescriptor, FontStore};
+pub use input::{Action, KeyState};
+pub use particles::{Emitter, EmitterSettings};
+pub use random::{Random, Rng, Stream};
+pub use render_queue::{RenderLayer, RenderQueue};
+pub use renderer::{
+    next_image_id, Align, DrawOptions, ImageId, RenderBackend, Renderer, Surface, TextStyle,
+    DEFAULT_FONT,
 };
-pub use renderer::Renderer;
-pub use sprite_sheet::{Sheet, SpriteSheet, SpriteSheetStore};
+pub use screen::{Screen, ScreenSettings};
+pub use sprite_sheet::{PaletteSwap, Sheet, SheetRect, SpriteSheet, SpriteSheetStore};
+pub use transition::{
+    Easing, Effect as TransitionEffect, Transition, TransitionSpec, TransitionStatus,
+};
+pub use ui::{draw_window, ListItem, Ui, UiEvent, WidgetId, LINE_HEIGHT, PADDING, TEXT_COLOR};
 
 #[async_trait(?Send)]
 pub trait Game {
     async fn initialize(&self) -> Result<Box<dyn Game>>;
     fn update(&mut self, key_state: &KeyState) -> Result<()>;
     fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()>;
+    // Called before the first update and whenever the logical screen changes
+    // size, which only happens without letterboxing.
+    fn on_resize(&mut self, _screen: &Rect) {}
 }
 
 const FRAME_SIZE: f32 = 1.0 / 60.0 * 1000.0;
@@ -30,16 +56,20 @@ pub struct GameLoop {
 }
 type SharedLoopClosure = Rc<RefCell<Option<browser::LoopClosure>>>;
 impl GameLoop {
-    pub async fn start(game: impl Game + 'static) -> Result<()> {
+    pub async fn start(game: impl Game + 'static, settings: ScreenSettings) -> Result<()> {
         let mut game = game.initialize().await?;
         let mut game_loop = Self {
             last_frame: browser::now()?,
             accumulated_delta: 0.0,
         };
-        let renderer = Renderer {
-            context: browser::context()?,
-        };
-        let sprite_sheet_store = load_sprite_sheet_store()?;
+        let renderer = Renderer::new(
+            browser::canvas()?,
+            settings.backend,
+            load_font_store()?,
+            Screen::fit(&settings)?,
+        )?;
+        game.on_resize(&renderer.screen().rect());
+        let sprite_sheet_store = load_sprite_sheet_store().await?;
         let mut key_state = KeyState::new();
         let mut keyevent_receiver = input::prepare_input()?;
 
@@ -47,14 +77,24 @@ impl GameLoop {
         let g = Rc::clone(&f);
         *g.borrow_mut() = Some(browser::create_request_animation_frame_closure(
             move |perf| {
-                input::process_input(&mut key_state, &mut keyevent_receiver);
+                // Polled rather than driven by resize events so that zooming,
+                // which changes devicePixelRatio, is caught too.
+                let screen = Screen::fit(&settings).expect("error fitting Screen");
+                if screen != renderer.screen() {
+                    renderer.resize(screen).expect("error resizing Screen");
+                    game.on_resize(&screen.rect());
+                }
+                input::process_input(&mut key_state, &mut keyevent_receiver, &screen);
                 game_loop.accumulated_delta += (perf - game_loop.last_frame) as f32;
                 while game_loop.accumulated_delta > FRAME_SIZE {
                     game.update(&key_state).expect("error GameLoop update");
+                    key_state.clear_just_pressed();
                     game_loop.accumulated_delta -= FRAME_SIZE;
                 }
                 game_loop.last_frame = perf;
-                game.draw(&renderer, &sprite_sheet_store).expect("error GameLoop draw");
+                game.draw(&renderer, &sprite_sheet_store)
+                    .expect("error GameLoop draw");
+                renderer.present().expect("error GameLoop present");
                 browser::request_animation_frame(
                     f.borrow()
                         .as_ref()
@@ -72,6 +112,7 @@ impl GameLoop {
     }
 }
 
+#[derive(Clone, Copy)]
 pub struct Rect {
     pub x: i16,
     pub y: i16,
@@ -79,17 +120,14 @@ pub struct Rect {
     pub h: i16,
 }
 
-#[derive(Clone, Copy, Deserialize, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
 pub struct Point {
     pub x: i16,
     pub y: i16,
 }
 
 // TODO I'd like to load files in `asset/*` automatically
-fn load_sprite_sheet() -> Result<SpriteSheet> {
-    let image = include_bytes!("asset/Sprite-0001.png");
-    let json = include_bytes!("asset/Sprite-0001.json");
-
+fn load_sprite_sheet(name: &str, image: &[u8], json: &[u8]) -> Result<SpriteSheet> {
     let src = format!(
         "data:image/{};base64,{}",
         "png",
@@ -100,15 +138,67 @@ fn load_sprite_sheet() -> Result<SpriteSheet> {
 
     let sheet: Sheet = serde_json::from_slice(json)?;
     Ok(SpriteSheet {
-        name: "Sprite-0001".to_string(),
+        name: name.to_string(),
+        id: next_image_id(),
         sheet: Some(sheet),
         image: Some(image_element),
     })
 }
 
-fn load_sprite_sheet_store() -> Result<SpriteSheetStore> {
-    let sprite_sheet = load_sprite_sheet()?;
+async fn load_sprite_sheet_store() -> Result<SpriteSheetStore> {
     let mut sprite_sheet_store = SpriteSheetStore::new();
-    sprite_sheet_store.add(sprite_sheet)?;
+    sprite_sheet_store.add(load_sprite_sheet(
+        "Sprite-0001",
+        include_bytes!("asset/Sprite-0001.png"),
+        include_bytes!("asset/Sprite-0001.json"),
+    )?)?;
+    sprite_sheet_store.add(load_sprite_sheet(
+        "Tileset-0001",
+        include_bytes!("asset/Tileset-0001.png"),
+        include_bytes!("asset/Tileset-0001.json"),
+    )?)?;
+    sprite_sheet_store.add(load_sprite_sheet(
+        "Effect-0001",
+        include_bytes!("asset/Effect-0001.png"),
+        include_bytes!("asset/Effect-0001.json"),
+    )?)?;
+    // Equipment drawn over characters, laid out like their sheet.
+    sprite_sheet_store.add(load_sprite_sheet(
+        "Equip-Shield-0001",
+        include_bytes!("asset/Equip-Shield-0001.png"),
+        include_bytes!("asset/Sprite-0001.json"),
+    )?)?;
+    sprite_sheet_store.add(load_sprite_sheet(
+        "Equip-Helm-0001",
+        include_bytes!("asset/Equip-Helm-0001.png"),
+        include_bytes!("asset/Sprite-0001.json"),
+    )?)?;
+    sprite_sheet_store.add(load_sprite_sheet(
+        "Window-0001",
+        include_bytes!("asset/Window-0001.png"),
+        include_bytes!("asset/Window-0001.json"),
+    )?)?;
+
+    // Variants read their source's pixels, so the source has to be decoded.
+    let swaps: Vec<PaletteSwap> = serde_json::from_slice(include_bytes!("asset/palettes.json"))?;
+    for swap in &swaps {
+        if let Some(image) = &sprite_sheet_store.get(&swap.source)?.image {
+            browser::decode_image(image).await?;
+        }
+        sprite_sheet_store.add_variant(swap)?;
+    }
     Ok(sprite_sheet_store)
 }
+
+fn load_font_store() -> Result<FontStore> {
+    let json = include_bytes!("asset/font.json");
+    let descriptor: FontDescriptor = serde_json::from_slice(json)?;
+    let mut font_store = FontStore::new();
+    let atlas = load_sprite_sheet(
+        "Font-0001",
+        include_bytes!("asset/Font-0001.png"),
+        include_bytes!("asset/Font-0001.json"),
+    )?;
+    font_store.add(Font::new(descriptor, atlas))?;
+    Ok(font_store)
+}
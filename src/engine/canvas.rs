@@ -0,0 +1,280 @@
+use super::renderer::{next_image_id, Backend, DrawOptions, ImageId, ImageSource};
+use super::{Point, Rect, Screen, SheetRect};
+use crate::browser;
+
+use anyhow::{anyhow, Result};
+use std::cell::Cell;
+use wasm_bindgen::JsValue;
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement};
+
+pub struct Canvas2d {
+    context: CanvasRenderingContext2d,
+    // Where tinted cells and pixelated frames are composed before being drawn.
+    scratch: HtmlCanvasElement,
+    scratch_id: ImageId,
+    scratch_context: CanvasRenderingContext2d,
+    screen: Cell<Screen>,
+}
+impl Canvas2d {
+    pub fn new(context: CanvasRenderingContext2d, screen: Screen) -> Result<Self> {
+        let scratch = browser::new_canvas(1, 1)?;
+        let scratch_context = browser::context_of(&scratch)?;
+        Ok(Self {
+            context,
+            scratch,
+            scratch_id: next_image_id(),
+            scratch_context,
+            screen: Cell::new(screen),
+        })
+    }
+
+    fn canvas(&self) -> Result<HtmlCanvasElement> {
+        self.context
+            .canvas()
+            .ok_or_else(|| anyhow!("no canvas for the 2d context"))
+    }
+
+    fn draw_transformed(
+        &self,
+        image: ImageSource,
+        source: &SheetRect,
+        destination: &Rect,
+        options: &DrawOptions,
+    ) -> Result<()> {
+        let pivot = options.pivot.unwrap_or(Point {
+            x: destination.w / 2,
+            y: destination.h / 2,
+        });
+        let flip = |flipped: bool| if flipped { -1.0 } else { 1.0 };
+        self.context
+            .translate(
+                (destination.x + pivot.x).into(),
+                (destination.y + pivot.y).into(),
+            )
+            .and_then(|_| self.context.rotate(options.rotation))
+            .and_then(|_| {
+                self.context.scale(
+                    options.scale_x * flip(options.flip_x),
+                    options.scale_y * flip(options.flip_y),
+                )
+            })
+            .and_then(|_| self.context.translate((-pivot.x).into(), (-pivot.y).into()))
+            .map_err(|err| anyhow!("error transforming image: {:#?}", err))?;
+        self.context
+            .set_global_alpha(self.context.global_alpha() * options.alpha);
+        let local = Rect {
+            x: 0,
+            y: 0,
+            ..*destination
+        };
+        let Some(color) = options.tint else {
+            return draw(&self.context, image, source, &local);
+        };
+
+        // Resizing also clears the scratch canvas and resets its composite
+        // operation.
+        self.scratch.set_width(source.w as u32);
+        self.scratch.set_height(source.h as u32);
+        draw(
+            &self.scratch_context,
+            image,
+            source,
+            &Rect {
+                x: 0,
+                y: 0,
+                w: source.w,
+                h: source.h,
+            },
+        )?;
+        self.scratch_context
+            .set_global_composite_operation("source-atop")
+            .map_err(|err| anyhow!("error tinting image: {:#?}", err))?;
+        self.scratch_context
+            .set_fill_style(&JsValue::from_str(color));
+        self.scratch_context
+            .fill_rect(0.0, 0.0, source.w.into(), source.h.into());
+        draw(
+            &self.context,
+            ImageSource::Canvas(&self.scratch, self.scratch_id, 0),
+            &SheetRect {
+                x: 0,
+                y: 0,
+                w: source.w,
+                h: source.h,
+            },
+            &local,
+        )
+    }
+}
+impl Backend for Canvas2d {
+    fn screen(&self) -> Screen {
+        self.screen.get()
+    }
+
+    fn resize(&self, screen: Screen) -> Result<()> {
+        screen.apply(&self.canvas()?)?;
+        self.screen.set(screen);
+        self.context.set_image_smoothing_enabled(false);
+        self.set_origin(&Point { x: 0, y: 0 })
+    }
+
+    fn set_origin(&self, origin: &Point) -> Result<()> {
+        let scale = self.screen.get().scale;
+        self.context
+            .set_transform(
+                scale,
+                0.0,
+                0.0,
+                scale,
+                -origin.x as f64 * scale,
+                -origin.y as f64 * scale,
+            )
+            .map_err(|err| anyhow!("error setting origin: {:#?}", err))
+    }
+
+    fn alpha(&self) -> f64 {
+        self.context.global_alpha()
+    }
+
+    fn set_alpha(&self, alpha: f64) {
+        self.context.set_global_alpha(alpha);
+    }
+
+    fn clear(&self, rect: &Rect) {
+        self.context
+            .clear_rect(rect.x.into(), rect.y.into(), rect.w.into(), rect.h.into());
+    }
+
+    fn fill_rect(&self, rect: &Rect, color: &str) {
+        self.context.set_fill_style(&JsValue::from_str(color));
+        self.context
+            .fill_rect(rect.x.into(), rect.y.into(), rect.w.into(), rect.h.into());
+    }
+
+    fn stroke_rect(&self, rect: &Rect, color: &str, line_width: f64) {
+        self.context.set_stroke_style(&JsValue::from_str(color));
+        self.context.set_line_width(line_width);
+        self.context
+            .stroke_rect(rect.x.into(), rect.y.into(), rect.w.into(), rect.h.into());
+    }
+
+    fn fill_outside_circle(
+        &self,
+        rect: &Rect,
+        center: &Point,
+        radius: f64,
+        color: &str,
+    ) -> Result<()> {
+        self.context.begin_path();
+        self.context
+            .rect(rect.x.into(), rect.y.into(), rect.w.into(), rect.h.into());
+        // Winding the circle the other way cuts it out of the rectangle.
+        self.context
+            .arc_with_anticlockwise(
+                center.x.into(),
+                center.y.into(),
+                radius.max(0.0),
+                0.0,
+                std::f64::consts::TAU,
+                true,
+            )
+            .map_err(|err| anyhow!("error drawing circle: {:#?}", err))?;
+        self.context.set_fill_style(&JsValue::from_str(color));
+        self.context.fill();
+        Ok(())
+    }
+
+    // Shrinks the frame onto the scratch canvas and stretches it back.
+    fn pixelate(&self, block: i16) -> Result<()> {
+        let canvas = self.canvas()?;
+        let screen = self.screen.get().rect();
+        let width = (screen.w + block - 1) / block;
+        let height = (screen.h + block - 1) / block;
+        self.scratch.set_width(width as u32);
+        self.scratch.set_height(height as u32);
+        self.scratch_context
+            .draw_image_with_html_canvas_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+                &canvas,
+                0.0,
+                0.0,
+                canvas.width().into(),
+                canvas.height().into(),
+                0.0,
+                0.0,
+                width.into(),
+                height.into(),
+            )
+            .map_err(|err| anyhow!("error pixelating: {:#?}", err))?;
+        self.context
+            .draw_image_with_html_canvas_element_and_dw_and_dh(
+                &self.scratch,
+                0.0,
+                0.0,
+                (width * block).into(),
+                (height * block).into(),
+            )
+            .map_err(|err| anyhow!("error pixelating: {:#?}", err))
+    }
+
+    fn draw_image(
+        &self,
+        image: ImageSource,
+        source: &SheetRect,
+        destination: &Rect,
+        options: &DrawOptions,
+    ) -> Result<()> {
+        let plain = !options.flip_x
+            && !options.flip_y
+            && options.scale_x == 1.0
+            && options.scale_y == 1.0
+            && options.rotation == 0.0
+            && options.alpha == 1.0
+            && options.tint.is_none();
+        if plain {
+            return draw(&self.context, image, source, destination);
+        }
+        self.context.save();
+        let result = self.draw_transformed(image, source, destination, options);
+        self.context.restore();
+        result
+    }
+
+    fn present(&self) -> Result<()> {
+        Ok(())
+    }
+}
+
+fn draw(
+    context: &CanvasRenderingContext2d,
+    image: ImageSource,
+    source: &SheetRect,
+    destination: &Rect,
+) -> Result<()> {
+    let result = match image {
+        ImageSource::Image(image, _) => context
+            .draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+                image,
+                source.x.into(),
+                source.y.into(),
+                source.w.into(),
+                source.h.into(),
+                destination.x.into(),
+                destination.y.into(),
+                destination.w.into(),
+                destination.h.into(),
+            ),
+        ImageSource::Canvas(canvas, _, _) => context
+            .draw_image_with_html_canvas_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+                canvas,
+                source.x.into(),
+                source.y.into(),
+                source.w.into(),
+                source.h.into(),
+                destination.x.into(),
+                destination.y.into(),
+                destination.w.into(),
+                destination.h.into(),
+            ),
+    };
+    result.map_err(|err| anyhow!("error drawing image: {:#?}", err))
+}
@@ -0,0 +1,47 @@
+// Reads the colour forms used in the game's data: "#rgb", "#rrggbb",
+// "#rrggbbaa" and "rgb()"/"rgba()", as red, green, blue and alpha bytes.
+pub fn parse_color(color: &str) -> Option<[u8; 4]> {
+    let color = color.trim();
+    if let Some(hex) = color.strip_prefix('#') {
+        let digit = |i: usize, len: usize| {
+            let value = u8::from_str_radix(hex.get(i..i + len)?, 16).ok()?;
+            Some(if len == 1 { value * 17 } else { value })
+        };
+        return match hex.len() {
+            3 => Some([digit(0, 1)?, digit(1, 1)?, digit(2, 1)?, 255]),
+            6 => Some([digit(0, 2)?, digit(2, 2)?, digit(4, 2)?, 255]),
+            8 => Some([digit(0, 2)?, digit(2, 2)?, digit(4, 2)?, digit(6, 2)?]),
+            _ => None,
+        };
+    }
+    let arguments = color
+        .strip_prefix("rgba(")
+        .or_else(|| color.strip_prefix("rgb("))?
+        .strip_suffix(')')?;
+    let values: Vec<f32> = arguments
+        .split(',')
+        .map(|value| value.trim().parse().ok())
+        .collect::<Option<_>>()?;
+    let byte = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+    match values[..] {
+        [r, g, b] => Some([byte(r), byte(g), byte(b), 255]),
+        [r, g, b, a] => Some([byte(r), byte(g), byte(b), byte(a * 255.0)]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_every_form() {
+        assert_eq!(parse_color("#fa0"), Some([255, 170, 0, 255]));
+        assert_eq!(parse_color("#ffaa00"), Some([255, 170, 0, 255]));
+        assert_eq!(parse_color("#ffaa0080"), Some([255, 170, 0, 128]));
+        assert_eq!(parse_color("rgb(255, 170, 0)"), Some([255, 170, 0, 255]));
+        assert_eq!(parse_color("rgba(0, 0, 0, 0.5)"), Some([0, 0, 0, 128]));
+        assert_eq!(parse_color("orange"), None);
+        assert_eq!(parse_color("#ffaa0"), None);
+    }
+}
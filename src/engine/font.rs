@@ -0,0 +1,246 @@
+use super::{next_image_id, ImageId, SheetRect, SpriteSheet};
+use crate::browser;
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::{cell::RefCell, collections::HashMap};
+use wasm_bindgen::JsValue;
+use web_sys::HtmlCanvasElement;
+
+pub struct FontStore {
+    fonts: HashMap<String, Font>,
+}
+impl FontStore {
+    pub fn new() -> Self {
+        Self {
+            fonts: HashMap::new(),
+        }
+    }
+    pub fn add(&mut self, font: Font) -> Result<()> {
+        match self.fonts.insert(font.descriptor.name.clone(), font) {
+            Some(font) => Err(anyhow!(
+                "Font with the same key has been inserted: {}",
+                font.descriptor.name
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Font> {
+        self.fonts
+            .get(name)
+            .ok_or_else(|| anyhow!("no Font found with name {}", name))
+    }
+}
+
+// Metrics for a glyph atlas, which is an ordinary sprite sheet. Glyphs, kerning
+// pairs and the atlas frames are keyed by the character itself, so multi-byte
+// characters like kana work the same way as ASCII.
+#[derive(Deserialize)]
+pub struct FontDescriptor {
+    pub name: String,
+    pub line_height: i16,
+    #[serde(default)]
+    pub glyphs: HashMap<String, Glyph>,
+    #[serde(default)]
+    pub kerning: HashMap<String, i16>,
+    pub fallback: Option<String>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+pub struct Glyph {
+    pub advance: i16,
+    #[serde(default)]
+    pub x_offset: i16,
+    #[serde(default)]
+    pub y_offset: i16,
+}
+
+pub struct Font {
+    pub descriptor: FontDescriptor,
+    pub atlas: SpriteSheet,
+    tinted: RefCell<HashMap<String, (ImageId, HtmlCanvasElement)>>,
+}
+impl Font {
+    pub fn new(descriptor: FontDescriptor, atlas: SpriteSheet) -> Self {
+        Self {
+            descriptor,
+            atlas,
+            tinted: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // Returns the atlas frame and metrics for `c`, falling back to the
+    // descriptor's fallback glyph for characters the font does not cover.
+    pub fn glyph(&self, c: char) -> Option<(&SheetRect, &Glyph)> {
+        let sheet = self.atlas.sheet.as_ref()?;
+        let lookup = |key: &str| {
+            Some((
+                &sheet.frames.get(key)?.frame,
+                self.descriptor.glyphs.get(key)?,
+            ))
+        };
+        lookup(c.encode_utf8(&mut [0; 4])).or_else(|| lookup(self.descriptor.fallback.as_deref()?))
+    }
+
+    pub fn kerning(&self, previous: char, c: char) -> i16 {
+        let pair: String = [previous, c].iter().collect();
+        self.descriptor.kerning.get(&pair).copied().unwrap_or(0)
+    }
+
+    pub fn measure(&self, line: &str) -> i16 {
+        let mut width = 0;
+        let mut previous = None;
+        for c in line.chars() {
+            if let Some(previous) = previous {
+                width += self.kerning(previous, c);
+            }
+            width += self.glyph(c).map(|(_, glyph)| glyph.advance).unwrap_or(0);
+            previous = Some(c);
+        }
+        width
+    }
+
+    // Splits `text` into lines no wider than `max_width`. Explicit newlines are
+    // kept, Latin text breaks between words and CJK text between characters.
+    pub fn wrap(&self, text: &str, max_width: Option<i16>) -> Vec<String> {
+        let Some(max_width) = max_width else {
+            return text.split('\n').map(str::to_string).collect();
+        };
+        let mut lines = Vec::new();
+        for paragraph in text.split('\n') {
+            let mut line = String::new();
+            for word in split_words(paragraph) {
+                let candidate = format!("{}{}", line, word);
+                if line.is_empty() || self.measure(candidate.trim_end()) <= max_width {
+                    line = candidate;
+                } else {
+                    lines.push(line.trim_end().to_string());
+                    line = word.trim_start().to_string();
+                }
+            }
+            lines.push(line.trim_end().to_string());
+        }
+        lines
+    }
+
+    // The atlas recoloured with `color`, built the first time that colour is
+    // used. Returns `None` until the atlas image has finished loading.
+    pub fn tinted(&self, color: &str) -> Result<Option<(ImageId, HtmlCanvasElement)>> {
+        if let Some(tinted) = self.tinted.borrow().get(color) {
+            return Ok(Some(tinted.clone()));
+        }
+        let image = self
+            .atlas
+            .image
+            .as_ref()
+            .ok_or_else(|| anyhow!("error getting HtmlImageElement"))?;
+        if !image.complete() || image.natural_width() == 0 {
+            return Ok(None);
+        }
+        let canvas = browser::new_canvas(image.natural_width(), image.natural_height())?;
+        let context = browser::context_of(&canvas)?;
+        context
+            .draw_image_with_html_image_element(image, 0.0, 0.0)
+            .map_err(|err| anyhow!("error tinting Font: {:#?}", err))?;
+        context
+            .set_global_composite_operation("source-in")
+            .map_err(|err| anyhow!("error tinting Font: {:#?}", err))?;
+        context.set_fill_style(&JsValue::from_str(color));
+        context.fill_rect(0.0, 0.0, canvas.width().into(), canvas.height().into());
+        let tinted = (next_image_id(), canvas);
+        self.tinted
+            .borrow_mut()
+            .insert(color.to_string(), tinted.clone());
+        Ok(Some(tinted))
+    }
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c as u32, 0x3000..=0x30ff | 0x3400..=0x9fff | 0xff00..=0xffef)
+}
+
+// Each word keeps the whitespace that follows it; CJK characters are words of
+// their own.
+fn split_words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            words.push(c.to_string());
+        } else if c.is_whitespace() {
+            word.push(c);
+            words.push(std::mem::take(&mut word));
+        } else {
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::Sheet;
+
+    fn font() -> Font {
+        let descriptor: FontDescriptor =
+            serde_json::from_slice(include_bytes!("../asset/font.json")).unwrap();
+        let sheet: Sheet =
+            serde_json::from_slice(include_bytes!("../asset/Font-0001.json")).unwrap();
+        Font::new(
+            descriptor,
+            SpriteSheet {
+                name: "Font-0001".to_string(),
+                id: next_image_id(),
+                sheet: Some(sheet),
+                image: None,
+            },
+        )
+    }
+
+    // Where a frame sits in the atlas, which is enough to tell frames apart.
+    fn position(rect: &SheetRect) -> (i16, i16) {
+        (rect.x, rect.y)
+    }
+
+    fn frame(font: &Font, key: &str) -> (i16, i16) {
+        position(&font.atlas.sheet.as_ref().unwrap().frames[key].frame)
+    }
+
+    #[test]
+    fn kana_have_their_own_glyphs() {
+        let font = font();
+        let fallback = frame(&font, "?");
+        for c in "あがぱァヴっ。「」ー！".chars() {
+            let (rect, glyph) = font.glyph(c).unwrap();
+            assert_eq!(position(rect), frame(&font, &c.to_string()), "{}", c);
+            assert_ne!(position(rect), fallback, "{}", c);
+            assert_eq!(glyph.advance, 13, "{}", c);
+        }
+    }
+
+    #[test]
+    fn uncovered_characters_use_the_fallback() {
+        let font = font();
+        let (rect, glyph) = font.glyph('漢').unwrap();
+        assert_eq!(position(rect), frame(&font, "?"));
+        assert_eq!(glyph.advance, font.glyph('?').unwrap().1.advance);
+    }
+
+    #[test]
+    fn kana_break_between_any_two_characters() {
+        let font = font();
+        assert_eq!(font.measure("むかし"), 39);
+        assert_eq!(
+            font.wrap("むかしむかし、あるところに", Some(52)),
+            ["むかしむ", "かし、あ", "るところ", "に"]
+        );
+    }
+}
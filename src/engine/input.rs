@@ -1,20 +1,57 @@
+use super::{Point, Screen};
 use crate::browser;
 
 use anyhow::Result;
 use futures::channel::mpsc::{unbounded, UnboundedReceiver};
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+};
 use wasm_bindgen::prelude::*;
-use web_sys::KeyboardEvent;
+use web_sys::{KeyboardEvent, PointerEvent};
 
 pub const KEY_CODE_ARROW_LEFT: &str = "ArrowLeft";
 pub const KEY_CODE_ARROW_UP: &str = "ArrowUp";
 pub const KEY_CODE_ARROW_RIGHT: &str = "ArrowRight";
 pub const KEY_CODE_ARROW_DOWN: &str = "ArrowDown";
+pub const KEY_CODE_ENTER: &str = "Enter";
+pub const KEY_CODE_SPACE: &str = "Space";
+pub const KEY_CODE_ESCAPE: &str = "Escape";
+pub const KEY_CODE_KEY_Z: &str = "KeyZ";
+pub const KEY_CODE_KEY_X: &str = "KeyX";
+pub const KEY_CODE_KEY_M: &str = "KeyM";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Left,
+    Up,
+    Right,
+    Down,
+    Confirm,
+    Cancel,
+    Map,
+}
+impl Action {
+    fn key_codes(&self) -> &'static [&'static str] {
+        match self {
+            Action::Left => &[KEY_CODE_ARROW_LEFT],
+            Action::Up => &[KEY_CODE_ARROW_UP],
+            Action::Right => &[KEY_CODE_ARROW_RIGHT],
+            Action::Down => &[KEY_CODE_ARROW_DOWN],
+            Action::Confirm => &[KEY_CODE_ENTER, KEY_CODE_SPACE, KEY_CODE_KEY_Z],
+            Action::Cancel => &[KEY_CODE_ESCAPE, KEY_CODE_KEY_X],
+            Action::Map => &[KEY_CODE_KEY_M],
+        }
+    }
+}
 
 pub fn prepare_input() -> Result<UnboundedReceiver<KeyPress>> {
     let (keydown_sender, keyevent_receiver) = unbounded();
     let keydown_sender = Rc::new(RefCell::new(keydown_sender));
     let keyup_sender = Rc::clone(&keydown_sender);
+    let pointermove_sender = Rc::clone(&keydown_sender);
+    let pointerdown_sender = Rc::clone(&keydown_sender);
 
     let onkeydown = browser::closure_wrap(Box::new(move |keycode: web_sys::KeyboardEvent| {
         keydown_sender
@@ -29,14 +66,35 @@ pub fn prepare_input() -> Result<UnboundedReceiver<KeyPress>> {
             .expect("error sending keyup event");
     }) as Box<dyn FnMut(web_sys::KeyboardEvent)>);
 
+    let onpointermove = browser::closure_wrap(Box::new(move |event: PointerEvent| {
+        pointermove_sender
+            .borrow_mut()
+            .start_send(KeyPress::PointerMove(event))
+            .expect("error sending pointermove event");
+    }) as Box<dyn FnMut(PointerEvent)>);
+    let onpointerdown = browser::closure_wrap(Box::new(move |event: PointerEvent| {
+        pointerdown_sender
+            .borrow_mut()
+            .start_send(KeyPress::PointerDown(event))
+            .expect("error sending pointerdown event");
+    }) as Box<dyn FnMut(PointerEvent)>);
+
     browser::canvas()?.set_onkeydown(Some(onkeydown.as_ref().unchecked_ref()));
     browser::canvas()?.set_onkeyup(Some(onkeyup.as_ref().unchecked_ref()));
+    browser::canvas()?.set_onpointermove(Some(onpointermove.as_ref().unchecked_ref()));
+    browser::canvas()?.set_onpointerdown(Some(onpointerdown.as_ref().unchecked_ref()));
     onkeydown.forget();
     onkeyup.forget();
+    onpointermove.forget();
+    onpointerdown.forget();
     Ok(keyevent_receiver)
 }
 
-pub fn process_input(state: &mut KeyState, keyevent_receiver: &mut UnboundedReceiver<KeyPress>) {
+pub fn process_input(
+    state: &mut KeyState,
+    keyevent_receiver: &mut UnboundedReceiver<KeyPress>,
+    screen: &Screen,
+) {
     loop {
         match keyevent_receiver.try_next() {
             Ok(None) => break,
@@ -44,6 +102,14 @@ pub fn process_input(state: &mut KeyState, keyevent_receiver: &mut UnboundedRece
             Ok(Some(evt)) => match evt {
                 KeyPress::KeyUp(evt) => state.set_released(&evt.code()),
                 KeyPress::KeyDown(evt) => state.set_pressed(&evt.code(), evt),
+                KeyPress::PointerMove(evt) => state.set_pointer(
+                    screen.logical_position(evt.client_x().into(), evt.client_y().into()),
+                    false,
+                ),
+                KeyPress::PointerDown(evt) => state.set_pointer(
+                    screen.logical_position(evt.client_x().into(), evt.client_y().into()),
+                    true,
+                ),
             },
         };
     }
@@ -52,14 +118,25 @@ pub fn process_input(state: &mut KeyState, keyevent_receiver: &mut UnboundedRece
 pub enum KeyPress {
     KeyUp(web_sys::KeyboardEvent),
     KeyDown(web_sys::KeyboardEvent),
+    PointerMove(PointerEvent),
+    PointerDown(PointerEvent),
 }
 pub struct KeyState {
     pressed_keys: HashMap<String, KeyboardEvent>,
+    just_pressed_keys: HashSet<String>,
+    // In logical pixels, so it can be compared with what was drawn.
+    pointer: Option<Point>,
+    pointer_moved: bool,
+    pointer_clicked: bool,
 }
 impl KeyState {
     pub fn new() -> Self {
         Self {
             pressed_keys: HashMap::new(),
+            just_pressed_keys: HashSet::new(),
+            pointer: None,
+            pointer_moved: false,
+            pointer_clicked: false,
         }
     }
 
@@ -67,11 +144,51 @@ impl KeyState {
         self.pressed_keys.contains_key(code)
     }
 
+    pub fn is_action_pressed(&self, action: Action) -> bool {
+        action.key_codes().iter().any(|code| self.is_pressed(code))
+    }
+
+    // True only for the first update after the key went down; held keys that
+    // auto-repeat do not count again.
+    pub fn is_action_just_pressed(&self, action: Action) -> bool {
+        action
+            .key_codes()
+            .iter()
+            .any(|code| self.just_pressed_keys.contains(*code))
+    }
+
+    pub fn pointer(&self) -> Option<Point> {
+        self.pointer
+    }
+
+    pub fn is_pointer_just_moved(&self) -> bool {
+        self.pointer_moved
+    }
+
+    pub fn is_pointer_just_clicked(&self) -> bool {
+        self.pointer_clicked
+    }
+
+    pub fn clear_just_pressed(&mut self) {
+        self.just_pressed_keys.clear();
+        self.pointer_moved = false;
+        self.pointer_clicked = false;
+    }
+
+    fn set_pointer(&mut self, position: Point, clicked: bool) {
+        self.pointer = Some(position);
+        self.pointer_moved = true;
+        self.pointer_clicked |= clicked;
+    }
+
     fn set_released(&mut self, code: &str) {
         self.pressed_keys.remove(code.into());
     }
 
     fn set_pressed(&mut self, code: &str, event: KeyboardEvent) {
+        if !self.is_pressed(code) {
+            self.just_pressed_keys.insert(code.into());
+        }
         self.pressed_keys.insert(code.into(), event);
     }
 }
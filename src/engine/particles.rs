@@ -0,0 +1,218 @@
+use super::color::parse_color;
+use super::{random::Rng, DrawOptions, Point, Rect, RenderLayer, RenderQueue, SpriteSheetStore};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Space {
+    // Moves with the camera and is y-sorted among the entities.
+    World,
+    // Fixed to the screen, drawn with the UI.
+    Screen,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ParticleLook {
+    Rect {
+        w: i16,
+        h: i16,
+    },
+    Sprite {
+        sprite_source: String,
+        frame: String,
+    },
+}
+
+// Velocities and gravity are in pixels per update. `colors` are blended
+// across each particle's life; sprites are tinted with them.
+#[derive(Clone, Debug, Deserialize)]
+pub struct EmitterSettings {
+    pub look: ParticleLook,
+    #[serde(default)]
+    pub rate: f32,
+    pub lifetime: (u16, u16),
+    pub velocity_x: (f32, f32),
+    pub velocity_y: (f32, f32),
+    #[serde(default)]
+    pub gravity: f32,
+    #[serde(default)]
+    pub fade: bool,
+    #[serde(default)]
+    pub colors: Vec<String>,
+    pub space: Space,
+}
+
+struct Particle {
+    x: f32,
+    y: f32,
+    velocity_x: f32,
+    velocity_y: f32,
+    age: u16,
+    lifetime: u16,
+}
+
+// Spawns `rate` particles per update around `position` until stopped.
+pub struct Emitter {
+    settings: EmitterSettings,
+    pub position: Point,
+    particles: Vec<Particle>,
+    emitting: bool,
+    pending: f32,
+    rng: Rng,
+}
+impl Emitter {
+    // `rng` should come from the cosmetic stream.
+    pub fn new(settings: EmitterSettings, position: Point, rng: Rng) -> Self {
+        Self {
+            settings,
+            position,
+            particles: Vec::new(),
+            emitting: true,
+            pending: 0.0,
+            rng,
+        }
+    }
+
+    // Spawns `count` particles at once and emits no more, for one-off puffs.
+    pub fn burst(settings: EmitterSettings, position: Point, count: usize, rng: Rng) -> Self {
+        let mut emitter = Self::new(settings, position, rng);
+        emitter.emitting = false;
+        for _ in 0..count {
+            emitter.spawn();
+        }
+        emitter
+    }
+
+    // Particles already alive play out.
+    pub fn stop(&mut self) {
+        self.emitting = false;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.emitting && self.particles.is_empty()
+    }
+
+    fn spawn(&mut self) {
+        let (min_lifetime, max_lifetime) = self.settings.lifetime;
+        let (min_x, max_x) = self.settings.velocity_x;
+        let (min_y, max_y) = self.settings.velocity_y;
+        let particle = Particle {
+            x: self.position.x as f32,
+            y: self.position.y as f32,
+            velocity_x: self.rng.range_f32(min_x, max_x),
+            velocity_y: self.rng.range_f32(min_y, max_y),
+            age: 0,
+            lifetime: self.rng.range(min_lifetime, max_lifetime).max(1),
+        };
+        self.particles.push(particle);
+    }
+
+    pub fn update(&mut self) {
+        if self.emitting {
+            self.pending += self.settings.rate;
+            while self.pending >= 1.0 {
+                self.spawn();
+                self.pending -= 1.0;
+            }
+        }
+        for particle in &mut self.particles {
+            particle.velocity_y += self.settings.gravity;
+            particle.x += particle.velocity_x;
+            particle.y += particle.velocity_y;
+            particle.age += 1;
+        }
+        self.particles
+            .retain(|particle| particle.age < particle.lifetime);
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        queue: &mut RenderQueue<'a>,
+        sprite_sheet_store: &'a SpriteSheetStore,
+        sort_key: i16,
+    ) -> Result<()> {
+        if self.particles.is_empty() {
+            return Ok(());
+        }
+        let layer = match self.settings.space {
+            Space::World => RenderLayer::Entities,
+            Space::Screen => RenderLayer::Ui,
+        };
+        // Particles are centred on their position.
+        let (sprite_sheet, size) = match &self.settings.look {
+            ParticleLook::Sprite {
+                sprite_source,
+                frame,
+            } => {
+                let sprite_sheet = sprite_sheet_store.get(sprite_source)?;
+                let cell = sprite_sheet
+                    .sheet
+                    .as_ref()
+                    .and_then(|sheet| sheet.frames.get(frame))
+                    .ok_or_else(|| anyhow!("invalid frame_name: {}", frame))?;
+                (Some(sprite_sheet), (cell.frame.w, cell.frame.h))
+            }
+            ParticleLook::Rect { w, h } => (None, (*w, *h)),
+        };
+        queue.submit(layer, sort_key, move |renderer| {
+            for particle in &self.particles {
+                let life = particle.age as f32 / particle.lifetime as f32;
+                let alpha = if self.settings.fade { 1.0 - life } else { 1.0 };
+                let color = blend(&self.settings.colors, life);
+                let rect = Rect {
+                    x: particle.x.round() as i16 - size.0 / 2,
+                    y: particle.y.round() as i16 - size.1 / 2,
+                    w: size.0,
+                    h: size.1,
+                };
+                renderer.with_alpha(alpha.into(), |renderer| {
+                    match (&self.settings.look, sprite_sheet) {
+                        (ParticleLook::Sprite { frame, .. }, Some(sprite_sheet)) => renderer
+                            .draw_image_with(
+                                sprite_sheet,
+                                frame,
+                                &Point {
+                                    x: rect.x,
+                                    y: rect.y,
+                                },
+                                &DrawOptions {
+                                    tint: color.as_deref(),
+                                    ..DrawOptions::default()
+                                },
+                            ),
+                        _ => {
+                            renderer.fill_rect(&rect, color.as_deref().unwrap_or("#ffffff"));
+                            Ok(())
+                        }
+                    }
+                })?;
+            }
+            Ok(())
+        });
+        Ok(())
+    }
+}
+
+// Interpolates between evenly spaced "#rrggbb" colours; `t` runs from 0 to 1.
+fn blend(colors: &[String], t: f32) -> Option<String> {
+    let last = colors.len().checked_sub(1)?;
+    let position = t.clamp(0.0, 1.0) * last as f32;
+    let index = (position as usize).min(last);
+    let (Some(from), Some(to)) = (
+        parse_color(&colors[index]),
+        parse_color(&colors[(index + 1).min(last)]),
+    ) else {
+        return Some(colors[index].clone());
+    };
+    let fraction = position - index as f32;
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * fraction).round() as u8;
+    Some(format!(
+        "#{:02x}{:02x}{:02x}",
+        mix(from[0], to[0]),
+        mix(from[1], to[1]),
+        mix(from[2], to[2])
+    ))
+}
@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+// PCG32: small, fast, and only integer maths, so a seed gives the same
+// numbers in the browser and in native tests.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Rng {
+    state: u64,
+    increment: u64,
+}
+impl Rng {
+    // Generators with the same seed but another `sequence` are unrelated.
+    pub fn new(seed: u64, sequence: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            increment: (sequence << 1) | 1,
+        };
+        rng.next();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next();
+        rng
+    }
+
+    pub fn next(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        xorshifted.rotate_right((old >> 59) as u32)
+    }
+
+    // Uniform in `min..max`; `min` when the range is empty.
+    pub fn range(&mut self, min: u16, max: u16) -> u16 {
+        if max <= min {
+            return min;
+        }
+        min + ((self.next() as u64 * (max - min) as u64) >> 32) as u16
+    }
+
+    // Uniform in `min..max`. 24 bits fit an f32 exactly, which keeps this
+    // reproducible too.
+    pub fn range_f32(&mut self, min: f32, max: f32) -> f32 {
+        min + (self.next() >> 8) as f32 / (1 << 24) as f32 * (max - min)
+    }
+}
+
+// What the numbers are for. Each has its own generator, so that, say, a
+// particle effect never changes the outcome of a fight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Stream {
+    World,
+    Combat,
+    Ai,
+    Cosmetic,
+}
+const STREAMS: [Stream; 4] = [Stream::World, Stream::Combat, Stream::Ai, Stream::Cosmetic];
+
+// All of a run's randomness, from one recorded seed. Saved with the game so
+// that a loaded game carries on exactly as it would have.
+#[derive(Deserialize, Serialize)]
+pub struct Random {
+    seed: u32,
+    streams: Vec<Rng>,
+}
+impl Random {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            seed,
+            streams: STREAMS
+                .iter()
+                .map(|stream| Rng::new(seed as u64, *stream as u64))
+                .collect(),
+        }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn stream(&mut self, stream: Stream) -> &mut Rng {
+        &mut self.streams[stream as usize]
+    }
+
+    // A generator of its own for something long-lived, such as an NPC, seeded
+    // from `stream`.
+    pub fn fork(&mut self, stream: Stream) -> Rng {
+        let rng = self.stream(stream);
+        let seed = (rng.next() as u64) << 32 | rng.next() as u64;
+        Rng::new(seed, stream as u64)
+    }
+
+    // Depends on the seed, `stream` and `key` only, never on what was drawn
+    // before; for content that everyone sharing a seed should see, such as
+    // dungeon floors.
+    pub fn derive(&self, stream: Stream, key: u32) -> Rng {
+        Rng::new(
+            (self.seed as u64) << 32 | key as u64,
+            (STREAMS.len() + stream as usize) as u64,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_the_reference_pcg32() {
+        // The first outputs of `pcg32_srandom_r(42, 54)` in the PCG reference code.
+        let mut rng = Rng::new(42, 54);
+        let outputs: Vec<u32> = (0..6).map(|_| rng.next()).collect();
+        assert_eq!(
+            outputs,
+            [0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e]
+        );
+    }
+
+    #[test]
+    fn streams_do_not_affect_each_other() {
+        let mut quiet = Random::new(7);
+        let mut busy = Random::new(7);
+        for _ in 0..100 {
+            busy.stream(Stream::Cosmetic).next();
+        }
+        busy.fork(Stream::Cosmetic);
+        for _ in 0..10 {
+            assert_eq!(
+                quiet.stream(Stream::Combat).next(),
+                busy.stream(Stream::Combat).next()
+            );
+        }
+    }
+
+    #[test]
+    fn streams_differ() {
+        let mut random = Random::new(7);
+        let combat = random.stream(Stream::Combat).next();
+        let cosmetic = random.stream(Stream::Cosmetic).next();
+        assert_ne!(combat, cosmetic);
+    }
+
+    #[test]
+    fn fork_is_repeatable() {
+        let mut a = Random::new(99);
+        let mut b = Random::new(99);
+        let mut fork_a = a.fork(Stream::Ai);
+        let mut fork_b = b.fork(Stream::Ai);
+        for _ in 0..10 {
+            assert_eq!(fork_a.next(), fork_b.next());
+        }
+        // Forking draws from the stream, so the next fork is another generator.
+        assert_ne!(
+            a.fork(Stream::Ai).next(),
+            Random::new(99).fork(Stream::Ai).next()
+        );
+    }
+
+    #[test]
+    fn derive_ignores_what_was_drawn() {
+        let fresh = Random::new(1234);
+        let mut used = Random::new(1234);
+        for stream in STREAMS {
+            used.stream(stream).next();
+            used.fork(stream);
+        }
+        let mut a = fresh.derive(Stream::World, 3);
+        let mut b = used.derive(Stream::World, 3);
+        for _ in 0..10 {
+            assert_eq!(a.next(), b.next());
+        }
+        assert_ne!(
+            fresh.derive(Stream::World, 3).next(),
+            fresh.derive(Stream::World, 4).next()
+        );
+    }
+
+    #[test]
+    fn survives_a_save() {
+        let mut random = Random::new(5);
+        random.stream(Stream::Combat).next();
+        random.stream(Stream::World).next();
+        let json = serde_json::to_string(&random).unwrap();
+        let mut loaded: Random = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.seed(), 5);
+        for stream in STREAMS {
+            for _ in 0..5 {
+                assert_eq!(random.stream(stream).next(), loaded.stream(stream).next());
+            }
+        }
+    }
+
+    #[test]
+    fn range_stays_in_bounds() {
+        let mut rng = Rng::new(3, 0);
+        for _ in 0..1000 {
+            assert!((10..20).contains(&rng.range(10, 20)));
+            let f = rng.range_f32(-1.0, 1.0);
+            assert!((-1.0..1.0).contains(&f));
+        }
+        assert_eq!(rng.range(5, 6), 5);
+    }
+
+    #[test]
+    fn empty_range_gives_min() {
+        let mut rng = Rng::new(3, 0);
+        let before = rng.clone();
+        assert_eq!(rng.range(4, 4), 4);
+        assert_eq!(rng.range(9, 2), 9);
+        // Nothing is drawn for an empty range.
+        assert_eq!(rng.next(), before.clone().next());
+    }
+}
@@ -0,0 +1,60 @@
+use super::{Point, Renderer};
+
+use anyhow::Result;
+
+// Back to front. Layers before `Ui` are in world space.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RenderLayer {
+    Ground,
+    Entities,
+    Overhead,
+    Ui,
+    Transition,
+}
+
+type DrawCommand<'a> = Box<dyn FnOnce(&Renderer) -> Result<()> + 'a>;
+
+// Draw calls collected during a frame and replayed once, ordered by layer and
+// then by sort key, so the order they are submitted in does not matter.
+// Commands with equal keys keep their submission order.
+pub struct RenderQueue<'a> {
+    origin: Point,
+    commands: Vec<(RenderLayer, i16, DrawCommand<'a>)>,
+}
+impl<'a> RenderQueue<'a> {
+    // World layers are drawn with `origin` at the top-left of the canvas.
+    pub fn new(origin: Point) -> Self {
+        Self {
+            origin,
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn submit(
+        &mut self,
+        layer: RenderLayer,
+        sort_key: i16,
+        command: impl FnOnce(&Renderer) -> Result<()> + 'a,
+    ) {
+        self.commands.push((layer, sort_key, Box::new(command)));
+    }
+
+    pub fn flush(mut self, renderer: &Renderer) -> Result<()> {
+        self.commands
+            .sort_by_key(|(layer, sort_key, _)| (*layer, *sort_key));
+        let mut in_world = None;
+        for (layer, _, command) in self.commands {
+            let world = layer < RenderLayer::Ui;
+            if in_world != Some(world) {
+                if world {
+                    renderer.set_origin(&self.origin)?;
+                } else {
+                    renderer.reset_origin()?;
+                }
+                in_world = Some(world);
+            }
+            command(renderer)?;
+        }
+        renderer.reset_origin()
+    }
+}
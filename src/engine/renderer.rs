@@ -1,15 +1,264 @@
-use super::{Point, Rect, SpriteSheet};
+use super::{Canvas2d, FontStore, Point, Rect, Screen, SheetRect, SpriteSheet, WebGl2};
+use crate::browser;
 
 use anyhow::{anyhow, Result};
-use web_sys::CanvasRenderingContext2d;
+use std::{
+    cell::Cell,
+    sync::atomic::{AtomicU32, Ordering},
+};
+use wasm_bindgen::JsValue;
+use web_sys::{CanvasRenderingContext2d, HtmlCanvasElement, HtmlImageElement};
+
+pub const DEFAULT_FONT: &str = "default";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+pub struct TextStyle<'a> {
+    pub font: &'a str,
+    pub color: &'a str,
+    pub align: Align,
+    // Wraps to this width; with `Center` and `Right` lines are aligned inside it.
+    pub max_width: Option<i16>,
+}
+impl<'a> TextStyle<'a> {
+    pub fn new(color: &'a str) -> Self {
+        Self {
+            font: DEFAULT_FONT,
+            color,
+            align: Align::Left,
+            max_width: None,
+        }
+    }
+}
+
+// How `Renderer::draw_image_with` transforms a cell. Scale, flips and rotation
+// are applied around `pivot`, relative to the cell's top-left corner, which
+// defaults to the centre of the cell.
+#[derive(Clone, Copy)]
+pub struct DrawOptions<'a> {
+    pub flip_x: bool,
+    pub flip_y: bool,
+    pub scale_x: f64,
+    pub scale_y: f64,
+    // Clockwise, in radians.
+    pub rotation: f64,
+    pub pivot: Option<Point>,
+    pub alpha: f64,
+    // Painted over the cell's opaque pixels; an opaque colour gives a flat
+    // silhouette, a translucent one a wash.
+    pub tint: Option<&'a str>,
+}
+impl Default for DrawOptions<'_> {
+    fn default() -> Self {
+        Self {
+            flip_x: false,
+            flip_y: false,
+            scale_x: 1.0,
+            scale_y: 1.0,
+            rotation: 0.0,
+            pivot: None,
+            alpha: 1.0,
+            tint: None,
+        }
+    }
+}
+
+// Names an image for backends that keep a copy of it, since comparing the
+// elements themselves means a trip into JavaScript.
+pub type ImageId = u32;
+
+static NEXT_IMAGE_ID: AtomicU32 = AtomicU32::new(0);
+
+pub fn next_image_id() -> ImageId {
+    NEXT_IMAGE_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// Where the pixels of a draw come from. Offscreen canvases can be redrawn,
+// so they carry a version that changes along with their contents.
+#[derive(Clone, Copy)]
+pub enum ImageSource<'a> {
+    Image(&'a HtmlImageElement, ImageId),
+    Canvas(&'a HtmlCanvasElement, ImageId, u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RenderBackend {
+    Canvas2d,
+    WebGl2,
+}
+
+// The drawing operations a graphics API has to provide. `Renderer` builds
+// sprites, text and nine-slices on top of them.
+pub trait Backend {
+    fn screen(&self) -> Screen;
+    fn resize(&self, screen: Screen) -> Result<()>;
+    fn set_origin(&self, origin: &Point) -> Result<()>;
+    fn alpha(&self) -> f64;
+    fn set_alpha(&self, alpha: f64);
+    fn clear(&self, rect: &Rect);
+    fn fill_rect(&self, rect: &Rect, color: &str);
+    fn stroke_rect(&self, rect: &Rect, color: &str, line_width: f64);
+    fn fill_outside_circle(
+        &self,
+        rect: &Rect,
+        center: &Point,
+        radius: f64,
+        color: &str,
+    ) -> Result<()>;
+    fn pixelate(&self, block: i16) -> Result<()>;
+    // Stretches `source` over `destination`, then applies `options` with the
+    // pivot relative to the destination's top-left corner.
+    fn draw_image(
+        &self,
+        image: ImageSource,
+        source: &SheetRect,
+        destination: &Rect,
+        options: &DrawOptions,
+    ) -> Result<()>;
+    // Called once everything in a frame has been drawn.
+    fn present(&self) -> Result<()>;
+}
 
 pub struct Renderer {
-    pub context: CanvasRenderingContext2d,
+    backend: Box<dyn Backend>,
+    pub font_store: FontStore,
 }
 impl Renderer {
+    // Falls back to Canvas2D when the browser has no WebGL2.
+    pub fn new(
+        canvas: HtmlCanvasElement,
+        backend: RenderBackend,
+        font_store: FontStore,
+        screen: Screen,
+    ) -> Result<Self> {
+        let backend: Box<dyn Backend> = match backend {
+            RenderBackend::WebGl2 => match browser::webgl2_context_of(&canvas)? {
+                Some(context) => Box::new(WebGl2::new(context, screen)?),
+                None => {
+                    log!("WebGL2 is unavailable, falling back to Canvas2D");
+                    Box::new(Canvas2d::new(browser::context_of(&canvas)?, screen)?)
+                }
+            },
+            RenderBackend::Canvas2d => {
+                Box::new(Canvas2d::new(browser::context_of(&canvas)?, screen)?)
+            }
+        };
+        backend.resize(screen)?;
+        Ok(Self {
+            backend,
+            font_store,
+        })
+    }
+
+    pub fn screen(&self) -> Screen {
+        self.backend.screen()
+    }
+
+    // Everything is drawn in logical pixels; the backend scales them up to
+    // device pixels.
+    pub fn resize(&self, screen: Screen) -> Result<()> {
+        self.backend.resize(screen)
+    }
+
     pub fn clear(&self, rect: &Rect) {
-        self.context
-            .clear_rect(rect.x.into(), rect.y.into(), rect.w.into(), rect.h.into());
+        self.backend.clear(rect);
+    }
+
+    pub fn fill_rect(&self, rect: &Rect, color: &str) {
+        self.backend.fill_rect(rect, color);
+    }
+
+    pub fn stroke_rect(&self, rect: &Rect, color: &str, line_width: f64) {
+        self.backend.stroke_rect(rect, color, line_width);
+    }
+
+    // Runs `draw` with everything it draws made `alpha` times as opaque.
+    pub fn with_alpha(&self, alpha: f64, draw: impl FnOnce(&Self) -> Result<()>) -> Result<()> {
+        let previous = self.backend.alpha();
+        self.backend.set_alpha(previous * alpha);
+        let result = draw(self);
+        self.backend.set_alpha(previous);
+        result
+    }
+
+    // Fills `rect` except for a circle, leaving a hole to see through.
+    pub fn fill_outside_circle(
+        &self,
+        rect: &Rect,
+        center: &Point,
+        radius: f64,
+        color: &str,
+    ) -> Result<()> {
+        self.backend
+            .fill_outside_circle(rect, center, radius, color)
+    }
+
+    // Redraws what is on screen so far in `block`-sized squares of colour.
+    pub fn pixelate(&self, block: i16) -> Result<()> {
+        if block <= 1 {
+            return Ok(());
+        }
+        self.backend.pixelate(block)
+    }
+
+    // `position` is the top-left corner of the first line, or of the box the
+    // lines are aligned in when `style.max_width` is set.
+    pub fn draw_text(&self, text: &str, position: &Point, style: &TextStyle) -> Result<()> {
+        let font = self.font_store.get(style.font)?;
+        let Some((atlas_id, atlas)) = font.tinted(style.color)? else {
+            return Ok(());
+        };
+        for (row, line) in font.wrap(text, style.max_width).iter().enumerate() {
+            let width = font.measure(line);
+            let area = style.max_width.unwrap_or(0);
+            let mut x = match style.align {
+                Align::Left => position.x,
+                Align::Center => position.x + (area - width) / 2,
+                Align::Right => position.x + area - width,
+            };
+            let y = position.y + row as i16 * font.descriptor.line_height;
+            let mut previous = None;
+            for c in line.chars() {
+                if let Some(previous) = previous {
+                    x += font.kerning(previous, c);
+                }
+                previous = Some(c);
+                let Some((frame, glyph)) = font.glyph(c) else {
+                    continue;
+                };
+                self.backend.draw_image(
+                    ImageSource::Canvas(&atlas, atlas_id, 0),
+                    frame,
+                    &Rect {
+                        x: x + glyph.x_offset,
+                        y: y + glyph.y_offset,
+                        w: frame.w,
+                        h: frame.h,
+                    },
+                    &DrawOptions::default(),
+                )?;
+                x += glyph.advance;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn wrap_text(&self, text: &str, style: &TextStyle) -> Result<Vec<String>> {
+        Ok(self.font_store.get(style.font)?.wrap(text, style.max_width))
+    }
+
+    pub fn measure_text(&self, text: &str, font: &str) -> Result<i16> {
+        let font = self.font_store.get(font)?;
+        Ok(text
+            .split('\n')
+            .map(|line| font.measure(line))
+            .max()
+            .unwrap_or(0))
     }
 
     pub fn draw_image(
@@ -17,33 +266,263 @@ impl Renderer {
         sprite_sheet: &SpriteSheet,
         frame_name: &str,
         destination: &Point,
+    ) -> Result<()> {
+        self.draw_image_with(
+            sprite_sheet,
+            frame_name,
+            destination,
+            &DrawOptions::default(),
+        )
+    }
+
+    pub fn draw_image_with(
+        &self,
+        sprite_sheet: &SpriteSheet,
+        frame_name: &str,
+        destination: &Point,
+        options: &DrawOptions,
+    ) -> Result<()> {
+        let (image, frame) = frame_of(sprite_sheet, frame_name)?;
+        self.backend.draw_image(
+            ImageSource::Image(image, sprite_sheet.id),
+            frame,
+            &Rect {
+                x: destination.x,
+                y: destination.y,
+                w: frame.w,
+                h: frame.h,
+            },
+            options,
+        )
+    }
+
+    // Draws the same frame of each sheet in turn, such as equipment over a
+    // character; the sheets have to share their frame names.
+    pub fn draw_layers(
+        &self,
+        sprite_sheets: &[&SpriteSheet],
+        frame_name: &str,
+        destination: &Point,
+        options: &DrawOptions,
+    ) -> Result<()> {
+        for sprite_sheet in sprite_sheets {
+            self.draw_image_with(sprite_sheet, frame_name, destination, options)?;
+        }
+        Ok(())
+    }
+
+    pub fn draw_surface(
+        &self,
+        surface: &Surface,
+        source: &Rect,
+        destination: &Point,
+    ) -> Result<()> {
+        self.draw_surface_scaled(
+            surface,
+            source,
+            &Rect {
+                x: destination.x,
+                y: destination.y,
+                w: source.w,
+                h: source.h,
+            },
+        )
+    }
+
+    // Stretches `source` over `destination`; pixels stay sharp.
+    pub fn draw_surface_scaled(
+        &self,
+        surface: &Surface,
+        source: &Rect,
+        destination: &Rect,
+    ) -> Result<()> {
+        self.backend.draw_image(
+            ImageSource::Canvas(&surface.canvas, surface.id, surface.version.get()),
+            &SheetRect {
+                x: source.x,
+                y: source.y,
+                w: source.w,
+                h: source.h,
+            },
+            destination,
+            &DrawOptions::default(),
+        )
+    }
+
+    // Everything drawn until `reset_origin` is shifted so that `origin` lands
+    // on the top-left corner of the canvas.
+    pub fn set_origin(&self, origin: &Point) -> Result<()> {
+        self.backend.set_origin(origin)
+    }
+
+    pub fn reset_origin(&self) -> Result<()> {
+        self.set_origin(&Point { x: 0, y: 0 })
+    }
+
+    pub fn present(&self) -> Result<()> {
+        self.backend.present()
+    }
+
+    // Corners keep their size, edges stretch along one axis and the centre
+    // stretches to fill `destination`.
+    pub fn draw_nine_slice(
+        &self,
+        sprite_sheet: &SpriteSheet,
+        slice_name: &str,
+        destination: &Rect,
     ) -> Result<()> {
         let sheet = sprite_sheet
             .sheet
             .as_ref()
             .ok_or_else(|| anyhow!("error getting SpriteSheet"))?;
-        let cell = sheet
-            .frames
-            .get(frame_name)
-            .ok_or_else(|| anyhow!("invalid frame_name: {}", frame_name))?;
-
+        let slice = sheet.slice(slice_name)?;
+        let bounds = &slice.bounds;
+        let center = slice
+            .center
+            .as_ref()
+            .ok_or_else(|| anyhow!("slice {} is not a nine-slice", slice_name))?;
         let image = sprite_sheet
             .image
             .as_ref()
             .ok_or_else(|| anyhow!("error getting HtmlImageElement"))?;
-        self.context
-            .draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
-                image,
-                cell.frame.x.into(),
-                cell.frame.y.into(),
-                cell.frame.w.into(),
-                cell.frame.h.into(),
-                destination.x.into(),
-                destination.y.into(),
-                cell.frame.w.into(),
-                cell.frame.h.into(),
-            )
-            .map_err(|err| anyhow!("error drawing image: {:#?}", err))?;
+
+        let source_xs = [
+            bounds.x,
+            bounds.x + center.x,
+            bounds.x + center.x + center.w,
+            bounds.x + bounds.w,
+        ];
+        let source_ys = [
+            bounds.y,
+            bounds.y + center.y,
+            bounds.y + center.y + center.h,
+            bounds.y + bounds.h,
+        ];
+        let destination_xs = [
+            destination.x,
+            destination.x + center.x,
+            destination.x + destination.w - (bounds.w - center.x - center.w),
+            destination.x + destination.w,
+        ];
+        let destination_ys = [
+            destination.y,
+            destination.y + center.y,
+            destination.y + destination.h - (bounds.h - center.y - center.h),
+            destination.y + destination.h,
+        ];
+        for row in 0..3 {
+            for column in 0..3 {
+                let sw = source_xs[column + 1] - source_xs[column];
+                let sh = source_ys[row + 1] - source_ys[row];
+                let dw = destination_xs[column + 1] - destination_xs[column];
+                let dh = destination_ys[row + 1] - destination_ys[row];
+                if sw <= 0 || sh <= 0 || dw <= 0 || dh <= 0 {
+                    continue;
+                }
+                self.backend.draw_image(
+                    ImageSource::Image(image, sprite_sheet.id),
+                    &SheetRect {
+                        x: source_xs[column],
+                        y: source_ys[row],
+                        w: sw,
+                        h: sh,
+                    },
+                    &Rect {
+                        x: destination_xs[column],
+                        y: destination_ys[row],
+                        w: dw,
+                        h: dh,
+                    },
+                    &DrawOptions::default(),
+                )?;
+            }
+        }
         Ok(())
     }
 }
+
+// An offscreen canvas for images that are expensive to draw and rarely change.
+pub struct Surface {
+    canvas: HtmlCanvasElement,
+    id: ImageId,
+    context: CanvasRenderingContext2d,
+    // Bumped on every change so backends that copy the pixels know to again.
+    version: Cell<u32>,
+}
+impl Surface {
+    pub fn new(width: i16, height: i16) -> Result<Self> {
+        let canvas = browser::new_canvas(width as u32, height as u32)?;
+        let context = browser::context_of(&canvas)?;
+        Ok(Self {
+            canvas,
+            id: next_image_id(),
+            context,
+            version: Cell::new(0),
+        })
+    }
+
+    pub fn clear(&self, rect: &Rect) {
+        self.context
+            .clear_rect(rect.x.into(), rect.y.into(), rect.w.into(), rect.h.into());
+        self.version.set(self.version.get().wrapping_add(1));
+    }
+
+    pub fn fill_rect(&self, rect: &Rect, color: &str) {
+        self.context.set_fill_style(&JsValue::from_str(color));
+        self.context
+            .fill_rect(rect.x.into(), rect.y.into(), rect.w.into(), rect.h.into());
+        self.version.set(self.version.get().wrapping_add(1));
+    }
+
+    pub fn draw_image(
+        &self,
+        sprite_sheet: &SpriteSheet,
+        frame_name: &str,
+        destination: &Point,
+    ) -> Result<()> {
+        self.version.set(self.version.get().wrapping_add(1));
+        draw_frame(&self.context, sprite_sheet, frame_name, destination)
+    }
+}
+
+fn frame_of<'a>(
+    sprite_sheet: &'a SpriteSheet,
+    frame_name: &str,
+) -> Result<(&'a HtmlImageElement, &'a SheetRect)> {
+    let sheet = sprite_sheet
+        .sheet
+        .as_ref()
+        .ok_or_else(|| anyhow!("error getting SpriteSheet"))?;
+    let cell = sheet
+        .frames
+        .get(frame_name)
+        .ok_or_else(|| anyhow!("invalid frame_name: {}", frame_name))?;
+    let image = sprite_sheet
+        .image
+        .as_ref()
+        .ok_or_else(|| anyhow!("error getting HtmlImageElement"))?;
+    Ok((image, &cell.frame))
+}
+
+fn draw_frame(
+    context: &CanvasRenderingContext2d,
+    sprite_sheet: &SpriteSheet,
+    frame_name: &str,
+    destination: &Point,
+) -> Result<()> {
+    let (image, frame) = frame_of(sprite_sheet, frame_name)?;
+    context
+        .draw_image_with_html_image_element_and_sw_and_sh_and_dx_and_dy_and_dw_and_dh(
+            image,
+            frame.x.into(),
+            frame.y.into(),
+            frame.w.into(),
+            frame.h.into(),
+            destination.x.into(),
+            destination.y.into(),
+            frame.w.into(),
+            frame.h.into(),
+        )
+        .map_err(|err| anyhow!("error drawing image: {:#?}", err))?;
+    Ok(())
+}
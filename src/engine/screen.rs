@@ -0,0 +1,115 @@
+use super::{Point, Rect, RenderBackend};
+use crate::browser;
+
+use anyhow::{anyhow, Result};
+use web_sys::HtmlCanvasElement;
+
+// The resolution the game is drawn at. With `letterbox` the screen keeps this
+// size and is centred between black bars; without it the screen grows to
+// cover the window at the same scale and the game sees more of the world.
+// `backend` is only a preference; without WebGL2 the game draws with Canvas2D.
+#[derive(Clone, Copy)]
+pub struct ScreenSettings {
+    pub width: i16,
+    pub height: i16,
+    pub letterbox: bool,
+    pub backend: RenderBackend,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct Screen {
+    pub width: i16,
+    pub height: i16,
+    // Device pixels per logical pixel; always a whole number so every pixel
+    // of the art is the same size.
+    pub scale: f64,
+    pub pixel_ratio: f64,
+    // Where the canvas is placed in the window, in CSS pixels.
+    left: f64,
+    top: f64,
+}
+impl Screen {
+    pub fn fit(settings: &ScreenSettings) -> Result<Self> {
+        let window = browser::window()?;
+        let css_size = |value: Result<wasm_bindgen::JsValue, wasm_bindgen::JsValue>| {
+            value
+                .ok()
+                .and_then(|value| value.as_f64())
+                .ok_or_else(|| anyhow!("error getting window size"))
+        };
+        let window_width = css_size(window.inner_width())?;
+        let window_height = css_size(window.inner_height())?;
+        let pixel_ratio = window.device_pixel_ratio().max(1.0);
+
+        let device_width = window_width * pixel_ratio;
+        let device_height = window_height * pixel_ratio;
+        let scale = (device_width / settings.width as f64)
+            .min(device_height / settings.height as f64)
+            .floor()
+            .max(1.0);
+        let (width, height) = if settings.letterbox {
+            (settings.width, settings.height)
+        } else {
+            (
+                ((device_width / scale) as i16).max(settings.width),
+                ((device_height / scale) as i16).max(settings.height),
+            )
+        };
+        let css_width = width as f64 * scale / pixel_ratio;
+        let css_height = height as f64 * scale / pixel_ratio;
+        Ok(Self {
+            width,
+            height,
+            scale,
+            pixel_ratio,
+            left: ((window_width - css_width) / 2.0).max(0.0).floor(),
+            top: ((window_height - css_height) / 2.0).max(0.0).floor(),
+        })
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            w: self.width,
+            h: self.height,
+        }
+    }
+
+    // Sizes the canvas backing store in device pixels and its element in CSS
+    // pixels. Resizing resets the context, so callers set it up again after.
+    pub fn apply(&self, canvas: &HtmlCanvasElement) -> Result<()> {
+        canvas.set_width((self.width as f64 * self.scale) as u32);
+        canvas.set_height((self.height as f64 * self.scale) as u32);
+        let style = canvas.style();
+        for (property, value) in [
+            ("position", "absolute".to_string()),
+            ("left", format!("{}px", self.left)),
+            ("top", format!("{}px", self.top)),
+            (
+                "width",
+                format!("{}px", self.width as f64 * self.scale / self.pixel_ratio),
+            ),
+            (
+                "height",
+                format!("{}px", self.height as f64 * self.scale / self.pixel_ratio),
+            ),
+            ("image-rendering", "pixelated".to_string()),
+        ] {
+            style
+                .set_property(property, &value)
+                .map_err(|err| anyhow!("error styling canvas {}: {:#?}", property, err))?;
+        }
+        Ok(())
+    }
+
+    // Maps a position in window CSS pixels, as found on pointer events, back to
+    // logical pixels.
+    pub fn logical_position(&self, client_x: f64, client_y: f64) -> Point {
+        let css_scale = self.scale / self.pixel_ratio;
+        Point {
+            x: ((client_x - self.left) / css_scale).floor() as i16,
+            y: ((client_y - self.top) / css_scale).floor() as i16,
+        }
+    }
+}
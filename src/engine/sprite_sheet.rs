@@ -1,7 +1,12 @@
+use super::color::parse_color;
+use super::renderer::{next_image_id, ImageId};
+use crate::browser;
+
+use anyhow::{anyhow, Result};
 use serde::Deserialize;
 use std::collections::HashMap;
-use web_sys::HtmlImageElement;
-use anyhow::{Result, anyhow};
+use wasm_bindgen::Clamped;
+use web_sys::{HtmlImageElement, ImageData};
 
 pub struct SpriteSheetStore {
     sprite_sheets: HashMap<String, SpriteSheet>,
@@ -13,34 +18,148 @@ impl SpriteSheetStore {
         }
     }
     pub fn add(&mut self, sprite_sheet: SpriteSheet) -> Result<()> {
-        match self.sprite_sheets.insert(sprite_sheet.name.clone(), sprite_sheet) {
-            Some(sprite_sheet) => Err(anyhow!("SpriteSheet with the same key has been inserted: {}", sprite_sheet.name)),
+        match self
+            .sprite_sheets
+            .insert(sprite_sheet.name.clone(), sprite_sheet)
+        {
+            Some(sprite_sheet) => Err(anyhow!(
+                "SpriteSheet with the same key has been inserted: {}",
+                sprite_sheet.name
+            )),
             None => Ok(()),
         }
     }
 
     pub fn get(&self, name: &str) -> Result<&SpriteSheet> {
-        self.sprite_sheets.get(name).ok_or_else(|| anyhow!("no SpriteSheet found with name {}", name))
+        self.sprite_sheets
+            .get(name)
+            .ok_or_else(|| anyhow!("no SpriteSheet found with name {}", name))
+    }
+
+    // Registers `swap.name` as a recoloured copy of `swap.source`, which has to
+    // have finished loading.
+    pub fn add_variant(&mut self, swap: &PaletteSwap) -> Result<()> {
+        let variant = self.get(&swap.source)?.remapped(&swap.name, &swap.colors)?;
+        self.add(variant)
     }
 }
 
+// A sheet drawn with some colours of another one replaced, such as a monster
+// in a stronger colour. `colors` maps "#rrggbb" in the source to "#rrggbb".
+#[derive(Deserialize)]
+pub struct PaletteSwap {
+    pub name: String,
+    pub source: String,
+    pub colors: HashMap<String, String>,
+}
+
 pub struct SpriteSheet {
     pub name: String,
+    pub id: ImageId,
     pub sheet: Option<Sheet>,
     pub image: Option<HtmlImageElement>,
 }
-#[derive(Deserialize)]
+impl SpriteSheet {
+    // Images decode asynchronously, so anything copying pixels out of the
+    // sheet has to wait for this.
+    pub fn is_loaded(&self) -> bool {
+        self.image
+            .as_ref()
+            .is_some_and(|image| image.complete() && image.natural_width() > 0)
+    }
+
+    // Copies the sheet with every pixel of a colour in `colors` replaced,
+    // keeping its alpha. Frames and slices are shared with the source.
+    pub fn remapped(&self, name: &str, colors: &HashMap<String, String>) -> Result<SpriteSheet> {
+        let image = self
+            .image
+            .as_ref()
+            .filter(|_| self.is_loaded())
+            .ok_or_else(|| anyhow!("SpriteSheet {} is not loaded", self.name))?;
+        let mut table = HashMap::new();
+        for (from, to) in colors {
+            let rgb = |color: &str| {
+                parse_color(color)
+                    .map(|[r, g, b, _]| [r, g, b])
+                    .ok_or_else(|| anyhow!("invalid color {} in palette {}", color, name))
+            };
+            table.insert(rgb(from)?, rgb(to)?);
+        }
+
+        let (width, height) = (image.natural_width(), image.natural_height());
+        let canvas = browser::new_canvas(width, height)?;
+        let context = browser::context_of(&canvas)?;
+        context
+            .draw_image_with_html_image_element(image, 0.0, 0.0)
+            .map_err(|err| anyhow!("error copying SpriteSheet {}: {:#?}", self.name, err))?;
+        let Clamped(mut pixels) = context
+            .get_image_data(0.0, 0.0, width.into(), height.into())
+            .map_err(|err| anyhow!("error reading SpriteSheet {}: {:#?}", self.name, err))?
+            .data();
+        for pixel in pixels.chunks_exact_mut(4) {
+            if let Some(to) = table.get(&[pixel[0], pixel[1], pixel[2]]) {
+                pixel[..3].copy_from_slice(to);
+            }
+        }
+        ImageData::new_with_u8_clamped_array_and_sh(Clamped(&pixels), width, height)
+            .and_then(|data| context.put_image_data(&data, 0.0, 0.0))
+            .map_err(|err| anyhow!("error remapping SpriteSheet {}: {:#?}", name, err))?;
+
+        let variant = browser::new_image()?;
+        variant.set_src(
+            &canvas
+                .to_data_url()
+                .map_err(|err| anyhow!("error exporting SpriteSheet {}: {:#?}", name, err))?,
+        );
+        Ok(SpriteSheet {
+            name: name.to_string(),
+            id: next_image_id(),
+            sheet: self.sheet.clone(),
+            image: Some(variant),
+        })
+    }
+}
+#[derive(Clone, Deserialize)]
 pub struct Sheet {
     pub frames: HashMap<String, Cell>,
+    #[serde(default)]
+    pub meta: Meta,
 }
-#[derive(Deserialize)]
+impl Sheet {
+    pub fn slice(&self, name: &str) -> Result<&SliceKey> {
+        self.meta
+            .slices
+            .iter()
+            .find(|slice| slice.name == name)
+            .and_then(|slice| slice.keys.first())
+            .ok_or_else(|| anyhow!("invalid slice name: {}", name))
+    }
+}
+#[derive(Clone, Default, Deserialize)]
+pub struct Meta {
+    #[serde(default)]
+    pub slices: Vec<Slice>,
+}
+// Slices as exported by Aseprite; `center` marks the stretchable middle of a
+// nine-slice.
+#[derive(Clone, Deserialize)]
+pub struct Slice {
+    pub name: String,
+    pub keys: Vec<SliceKey>,
+}
+#[derive(Clone, Deserialize)]
+pub struct SliceKey {
+    pub bounds: SheetRect,
+    pub center: Option<SheetRect>,
+}
+#[derive(Clone, Deserialize)]
 pub struct SheetRect {
     pub x: i16,
     pub y: i16,
     pub w: i16,
     pub h: i16,
 }
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct Cell {
     pub frame: SheetRect,
 }
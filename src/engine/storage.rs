@@ -0,0 +1,16 @@
+use crate::browser;
+
+use anyhow::{anyhow, Result};
+
+// Small strings kept by the browser between visits, such as saves.
+pub fn load(key: &str) -> Result<Option<String>> {
+    browser::local_storage()?
+        .get_item(key)
+        .map_err(|err| anyhow!("error reading {}: {:#?}", key, err))
+}
+
+pub fn store(key: &str, value: &str) -> Result<()> {
+    browser::local_storage()?
+        .set_item(key, value)
+        .map_err(|err| anyhow!("error writing {}: {:#?}", key, err))
+}
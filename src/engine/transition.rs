@@ -0,0 +1,190 @@
+use super::{Point, Rect, Renderer};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+const MAX_MOSAIC_BLOCK: f32 = 24.0;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Effect {
+    Fade { color: String },
+    // Closes in on the transition's focus, or the middle of the screen.
+    Iris { color: String },
+    // Sweeps across from the left, then uncovers towards the right.
+    Wipe { color: String },
+    // The screen breaks into growing blocks while darkening; for battles.
+    Mosaic { color: String },
+}
+
+// How a transition is described in data files. `duration` is in updates and
+// covers both halves.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TransitionSpec {
+    pub effect: Effect,
+    pub duration: u32,
+    pub easing: Easing,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransitionStatus {
+    Running,
+    Finished,
+}
+
+type Midpoint<C> = Box<dyn FnOnce(&mut C) -> Result<()>>;
+
+// Covers the screen, runs the midpoint callback while it is fully covered,
+// then uncovers it again. `C` is whatever the callback needs to change, such
+// as the game swapping maps.
+pub struct Transition<C> {
+    effect: Effect,
+    duration: u32,
+    easing: Easing,
+    elapsed: u32,
+    focus: Option<Point>,
+    midpoint: Option<Midpoint<C>>,
+}
+impl<C> Transition<C> {
+    pub fn new(effect: Effect, duration: u32, easing: Easing) -> Self {
+        Self {
+            effect,
+            duration: duration.max(2),
+            easing,
+            elapsed: 0,
+            focus: None,
+            midpoint: None,
+        }
+    }
+
+    pub fn from_spec(spec: &TransitionSpec) -> Self {
+        Self::new(spec.effect.clone(), spec.duration, spec.easing)
+    }
+
+    // Starts fully covered and only plays the second half, for revealing a
+    // scene that was not on screen before.
+    pub fn revealing(mut self) -> Self {
+        self.elapsed = self.duration / 2;
+        self
+    }
+
+    // A point in world space for the iris to close on.
+    pub fn focused_on(mut self, focus: Point) -> Self {
+        self.focus = Some(focus);
+        self
+    }
+
+    pub fn on_midpoint(mut self, callback: impl FnOnce(&mut C) -> Result<()> + 'static) -> Self {
+        self.midpoint = Some(Box::new(callback));
+        self
+    }
+
+    pub fn update(&mut self, context: &mut C) -> Result<TransitionStatus> {
+        self.elapsed += 1;
+        if self.elapsed >= self.duration / 2 {
+            if let Some(midpoint) = self.midpoint.take() {
+                midpoint(context)?;
+            }
+        }
+        Ok(if self.elapsed >= self.duration {
+            TransitionStatus::Finished
+        } else {
+            TransitionStatus::Running
+        })
+    }
+
+    // True during the first half, while the effect closes over the screen;
+    // false once it has started to open again.
+    pub fn is_covering(&self) -> bool {
+        self.elapsed < self.duration / 2
+    }
+
+    // 0 when the screen is clear, 1 when it is fully covered.
+    fn coverage(&self) -> f32 {
+        let half = (self.duration / 2) as f32;
+        let elapsed = self.elapsed as f32;
+        if elapsed < half {
+            self.easing.apply(elapsed / half)
+        } else {
+            1.0 - self
+                .easing
+                .apply((elapsed - half) / (self.duration as f32 - half))
+        }
+    }
+
+    // Draws in screen space; `origin` is the world position of the screen's
+    // top-left corner, used to place the iris.
+    pub fn draw(&self, renderer: &Renderer, origin: &Point) -> Result<()> {
+        let screen = renderer.screen().rect();
+        let coverage = self.coverage();
+        match &self.effect {
+            Effect::Fade { color } => {
+                renderer.with_alpha(coverage.into(), |renderer| {
+                    renderer.fill_rect(&screen, color);
+                    Ok(())
+                })?;
+            }
+            Effect::Iris { color } => {
+                let center = match self.focus {
+                    Some(focus) => Point {
+                        x: focus.x - origin.x,
+                        y: focus.y - origin.y,
+                    },
+                    None => Point {
+                        x: screen.w / 2,
+                        y: screen.h / 2,
+                    },
+                };
+                // Far enough to clear the corner furthest from the centre.
+                let reach = |a: i16, size: i16| a.max(size - a) as f64;
+                let max_radius = reach(center.x, screen.w).hypot(reach(center.y, screen.h));
+                let radius = max_radius * (1.0 - coverage as f64);
+                renderer.fill_outside_circle(&screen, &center, radius, color)?;
+            }
+            Effect::Wipe { color } => {
+                let covered = (screen.w as f32 * coverage).ceil() as i16;
+                let x = if self.is_covering() {
+                    0
+                } else {
+                    screen.w - covered
+                };
+                renderer.fill_rect(
+                    &Rect {
+                        x,
+                        w: covered,
+                        ..screen
+                    },
+                    color,
+                );
+            }
+            Effect::Mosaic { color } => {
+                renderer.pixelate((1.0 + coverage * (MAX_MOSAIC_BLOCK - 1.0)) as i16)?;
+                renderer.with_alpha((coverage * coverage).into(), |renderer| {
+                    renderer.fill_rect(&screen, color);
+                    Ok(())
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
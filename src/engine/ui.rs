@@ -0,0 +1,452 @@
+use super::{Action, Align, KeyState, Point, Rect, Renderer, SpriteSheetStore, TextStyle};
+
+use anyhow::{anyhow, Result};
+use std::cell::Cell;
+
+pub const WINDOW_SKIN: &str = "Window-0001";
+const WINDOW_SLICE: &str = "window";
+const CURSOR_FRAME: &str = "cursor.png";
+pub const PADDING: i16 = 12;
+pub const LINE_HEIGHT: i16 = 24;
+const CURSOR_WIDTH: i16 = 16;
+pub const TEXT_COLOR: &str = "#ffffff";
+const DISABLED_COLOR: &str = "#808080";
+const GAUGE_BACKGROUND_COLOR: &str = "#202020";
+const GAUGE_BORDER_COLOR: &str = "#ffffff";
+
+pub type WidgetId = usize;
+
+pub enum Widget {
+    Window,
+    Label(Label),
+    List(List),
+    Gauge(Gauge),
+}
+
+pub struct Label {
+    pub text: String,
+    pub color: String,
+    pub align: Align,
+}
+
+pub struct ListItem {
+    pub text: String,
+    pub enabled: bool,
+}
+impl ListItem {
+    pub fn new(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            enabled: true,
+        }
+    }
+    pub fn disabled(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            enabled: false,
+        }
+    }
+}
+
+// Items are laid out row by row in `columns` columns; only the rows that fit
+// in the widget are drawn and the list scrolls to keep the cursor visible.
+pub struct List {
+    pub items: Vec<ListItem>,
+    pub columns: usize,
+    pub cursor: usize,
+    scroll: usize,
+}
+impl List {
+    fn move_cursor(&mut self, action: Action, rows: usize) {
+        let len = self.items.len();
+        if len == 0 {
+            return;
+        }
+        let columns = self.columns.max(1);
+        let column = self.cursor % columns;
+        self.cursor = match action {
+            Action::Up if self.cursor >= columns => self.cursor - columns,
+            // A single column wraps around, like most command windows.
+            Action::Up if columns == 1 => len - 1,
+            Action::Down if self.cursor + columns < len => self.cursor + columns,
+            Action::Down if columns == 1 => 0,
+            Action::Left if column > 0 => self.cursor - 1,
+            Action::Right if column + 1 < columns && self.cursor + 1 < len => self.cursor + 1,
+            _ => self.cursor,
+        };
+        let row = self.cursor / columns;
+        if row < self.scroll {
+            self.scroll = row;
+        } else if row >= self.scroll + rows {
+            self.scroll = row + 1 - rows;
+        }
+    }
+
+    pub fn selected(&self) -> Option<&ListItem> {
+        self.items.get(self.cursor)
+    }
+}
+
+pub struct Gauge {
+    pub value: i32,
+    pub max: i32,
+    pub color: String,
+}
+
+pub enum UiEvent {
+    Selected(WidgetId, usize),
+    Cancelled(WidgetId),
+}
+
+struct Node {
+    widget: Widget,
+    // Relative to the parent's top-left corner.
+    rect: Rect,
+    parent: Option<WidgetId>,
+    visible: bool,
+}
+
+// A retained tree of widgets. Input goes to the widget on top of the focus
+// stack; focusing a nested list and blurring it again gives the usual
+// "submenu then back" navigation.
+pub struct Ui {
+    nodes: Vec<Node>,
+    focus: Vec<WidgetId>,
+    // Where the tree was last drawn, for matching the pointer against it.
+    origin: Cell<Point>,
+}
+impl Ui {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            focus: Vec::new(),
+            origin: Cell::new(Point { x: 0, y: 0 }),
+        }
+    }
+
+    pub fn add(&mut self, parent: Option<WidgetId>, rect: Rect, widget: Widget) -> WidgetId {
+        self.nodes.push(Node {
+            widget,
+            rect,
+            parent,
+            visible: true,
+        });
+        self.nodes.len() - 1
+    }
+
+    pub fn window(&mut self, parent: Option<WidgetId>, rect: Rect) -> WidgetId {
+        self.add(parent, rect, Widget::Window)
+    }
+
+    pub fn label(&mut self, parent: Option<WidgetId>, rect: Rect, text: &str) -> WidgetId {
+        self.add(
+            parent,
+            rect,
+            Widget::Label(Label {
+                text: text.to_string(),
+                color: TEXT_COLOR.to_string(),
+                align: Align::Left,
+            }),
+        )
+    }
+
+    pub fn list(
+        &mut self,
+        parent: Option<WidgetId>,
+        rect: Rect,
+        items: Vec<ListItem>,
+        columns: usize,
+    ) -> WidgetId {
+        self.add(
+            parent,
+            rect,
+            Widget::List(List {
+                items,
+                columns,
+                cursor: 0,
+                scroll: 0,
+            }),
+        )
+    }
+
+    pub fn gauge(
+        &mut self,
+        parent: Option<WidgetId>,
+        rect: Rect,
+        value: i32,
+        max: i32,
+        color: &str,
+    ) -> WidgetId {
+        self.add(
+            parent,
+            rect,
+            Widget::Gauge(Gauge {
+                value,
+                max,
+                color: color.to_string(),
+            }),
+        )
+    }
+
+    pub fn set_visible(&mut self, id: WidgetId, visible: bool) {
+        if let Some(node) = self.nodes.get_mut(id) {
+            node.visible = visible;
+        }
+    }
+
+    pub fn label_mut(&mut self, id: WidgetId) -> Result<&mut Label> {
+        match self.nodes.get_mut(id).map(|node| &mut node.widget) {
+            Some(Widget::Label(label)) => Ok(label),
+            _ => Err(anyhow!("widget {} is not a Label", id)),
+        }
+    }
+
+    pub fn set_text(&mut self, id: WidgetId, text: &str) -> Result<()> {
+        self.label_mut(id)?.text = text.to_string();
+        Ok(())
+    }
+
+    pub fn list_mut(&mut self, id: WidgetId) -> Result<&mut List> {
+        match self.nodes.get_mut(id).map(|node| &mut node.widget) {
+            Some(Widget::List(list)) => Ok(list),
+            _ => Err(anyhow!("widget {} is not a List", id)),
+        }
+    }
+
+    pub fn gauge_mut(&mut self, id: WidgetId) -> Result<&mut Gauge> {
+        match self.nodes.get_mut(id).map(|node| &mut node.widget) {
+            Some(Widget::Gauge(gauge)) => Ok(gauge),
+            _ => Err(anyhow!("widget {} is not a Gauge", id)),
+        }
+    }
+
+    pub fn set_gauge(&mut self, id: WidgetId, value: i32, max: i32) -> Result<()> {
+        let gauge = self.gauge_mut(id)?;
+        gauge.value = value;
+        gauge.max = max;
+        Ok(())
+    }
+
+    // Replaces the items and puts the cursor back on the first one.
+    pub fn set_items(&mut self, id: WidgetId, items: Vec<ListItem>) -> Result<()> {
+        let list = self.list_mut(id)?;
+        list.items = items;
+        list.cursor = 0;
+        list.scroll = 0;
+        Ok(())
+    }
+
+    pub fn focus(&mut self, id: WidgetId) {
+        self.focus.push(id);
+    }
+
+    // Gives focus back to the previously focused widget.
+    pub fn blur(&mut self) -> Option<WidgetId> {
+        self.focus.pop()
+    }
+
+    pub fn focused(&self) -> Option<WidgetId> {
+        self.focus.last().copied()
+    }
+
+    pub fn update(&mut self, key_state: &KeyState) -> Option<UiEvent> {
+        let id = self.focused()?;
+        let rows = (self.nodes[id].rect.h / LINE_HEIGHT).max(1) as usize;
+        let pointed = key_state
+            .pointer()
+            .filter(|_| key_state.is_pointer_just_moved())
+            .and_then(|pointer| self.item_at(id, &pointer));
+        let Widget::List(list) = &mut self.nodes[id].widget else {
+            return None;
+        };
+        if let Some(index) = pointed {
+            list.cursor = index;
+            if key_state.is_pointer_just_clicked() && list.items[index].enabled {
+                return Some(UiEvent::Selected(id, index));
+            }
+        }
+        for action in [Action::Up, Action::Down, Action::Left, Action::Right] {
+            if key_state.is_action_just_pressed(action) {
+                list.move_cursor(action, rows);
+            }
+        }
+        if key_state.is_action_just_pressed(Action::Confirm) {
+            return match list.selected() {
+                Some(item) if item.enabled => Some(UiEvent::Selected(id, list.cursor)),
+                _ => None,
+            };
+        }
+        if key_state.is_action_just_pressed(Action::Cancel) {
+            return Some(UiEvent::Cancelled(id));
+        }
+        None
+    }
+
+    pub fn is_visible(&self, id: WidgetId) -> bool {
+        let node = &self.nodes[id];
+        node.visible && node.parent.is_none_or(|parent| self.is_visible(parent))
+    }
+
+    // The list item drawn under `point` the last time the list was visible.
+    fn item_at(&self, id: WidgetId, point: &Point) -> Option<usize> {
+        let Widget::List(list) = &self.nodes[id].widget else {
+            return None;
+        };
+        if !self.is_visible(id) {
+            return None;
+        }
+        let rect = self.absolute_rect(id);
+        let (x, y) = (point.x - rect.x, point.y - rect.y);
+        if x < 0 || y < 0 || x >= rect.w || y >= rect.h {
+            return None;
+        }
+        let columns = list.columns.max(1);
+        let column = (x / (rect.w / columns as i16).max(1)) as usize;
+        let index = (list.scroll + (y / LINE_HEIGHT) as usize) * columns + column;
+        (column < columns && index < list.items.len()).then_some(index)
+    }
+
+    fn absolute_rect(&self, id: WidgetId) -> Rect {
+        let node = &self.nodes[id];
+        let origin = match node.parent {
+            Some(parent) => {
+                let rect = self.absolute_rect(parent);
+                Point {
+                    x: rect.x,
+                    y: rect.y,
+                }
+            }
+            None => self.origin.get(),
+        };
+        Rect {
+            x: origin.x + node.rect.x,
+            y: origin.y + node.rect.y,
+            w: node.rect.w,
+            h: node.rect.h,
+        }
+    }
+
+    pub fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
+        self.draw_at(renderer, sprite_sheet_store, &Point { x: 0, y: 0 })
+    }
+
+    // Draws the tree with top-level widgets positioned relative to `origin`.
+    pub fn draw_at(
+        &self,
+        renderer: &Renderer,
+        sprite_sheet_store: &SpriteSheetStore,
+        origin: &Point,
+    ) -> Result<()> {
+        self.origin.set(*origin);
+        for id in 0..self.nodes.len() {
+            if !self.is_visible(id) {
+                continue;
+            }
+            let rect = self.absolute_rect(id);
+            match &self.nodes[id].widget {
+                Widget::Window => draw_window(renderer, sprite_sheet_store, &rect)?,
+                Widget::Label(label) => renderer.draw_text(
+                    &label.text,
+                    &Point {
+                        x: rect.x,
+                        y: rect.y,
+                    },
+                    &TextStyle {
+                        color: &label.color,
+                        align: label.align,
+                        max_width: Some(rect.w),
+                        ..TextStyle::new(TEXT_COLOR)
+                    },
+                )?,
+                Widget::List(list) => {
+                    self.draw_list(renderer, sprite_sheet_store, id, list, &rect)?
+                }
+                Widget::Gauge(gauge) => draw_gauge(renderer, gauge, &rect),
+            }
+        }
+        Ok(())
+    }
+
+    fn draw_list(
+        &self,
+        renderer: &Renderer,
+        sprite_sheet_store: &SpriteSheetStore,
+        id: WidgetId,
+        list: &List,
+        rect: &Rect,
+    ) -> Result<()> {
+        let columns = list.columns.max(1);
+        let rows = (rect.h / LINE_HEIGHT).max(1) as usize;
+        let column_width = rect.w / columns as i16;
+        let first = list.scroll * columns;
+        let last = (first + rows * columns).min(list.items.len());
+        for index in first..last {
+            let item = &list.items[index];
+            let x = rect.x + (index % columns) as i16 * column_width;
+            let y = rect.y + ((index / columns - list.scroll) as i16) * LINE_HEIGHT;
+            if index == list.cursor && self.focus.contains(&id) {
+                renderer.draw_image(
+                    sprite_sheet_store.get(WINDOW_SKIN)?,
+                    CURSOR_FRAME,
+                    &Point { x, y: y + 2 },
+                )?;
+            }
+            renderer.draw_text(
+                &item.text,
+                &Point {
+                    x: x + CURSOR_WIDTH,
+                    y,
+                },
+                &TextStyle::new(if item.enabled {
+                    TEXT_COLOR
+                } else {
+                    DISABLED_COLOR
+                }),
+            )?;
+        }
+        let more_above = list.scroll > 0;
+        let more_below = last < list.items.len();
+        for (shown, arrow, y) in [
+            (more_above, "▲", rect.y - LINE_HEIGHT / 2),
+            (more_below, "▼", rect.y + rect.h - LINE_HEIGHT / 2),
+        ] {
+            if shown {
+                renderer.draw_text(
+                    arrow,
+                    &Point { x: rect.x, y },
+                    &TextStyle {
+                        align: Align::Center,
+                        max_width: Some(rect.w),
+                        ..TextStyle::new(TEXT_COLOR)
+                    },
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn draw_window(
+    renderer: &Renderer,
+    sprite_sheet_store: &SpriteSheetStore,
+    rect: &Rect,
+) -> Result<()> {
+    renderer.draw_nine_slice(sprite_sheet_store.get(WINDOW_SKIN)?, WINDOW_SLICE, rect)
+}
+
+fn draw_gauge(renderer: &Renderer, gauge: &Gauge, rect: &Rect) {
+    renderer.fill_rect(rect, GAUGE_BACKGROUND_COLOR);
+    let ratio = if gauge.max > 0 {
+        gauge.value.clamp(0, gauge.max) as f32 / gauge.max as f32
+    } else {
+        0.0
+    };
+    renderer.fill_rect(
+        &Rect {
+            w: (rect.w as f32 * ratio) as i16,
+            ..*rect
+        },
+        &gauge.color,
+    );
+    renderer.stroke_rect(rect, GAUGE_BORDER_COLOR, 1.0);
+}
@@ -0,0 +1,728 @@
+use super::color;
+use super::renderer::{Backend, DrawOptions, ImageId, ImageSource};
+use super::{Point, Rect, Screen, SheetRect};
+
+use anyhow::{anyhow, Result};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+};
+use wasm_bindgen::JsCast;
+use web_sys::{
+    HtmlCanvasElement, WebGl2RenderingContext as Gl, WebGlBuffer, WebGlProgram, WebGlShader,
+    WebGlTexture, WebGlUniformLocation, WebGlVertexArrayObject,
+};
+
+// corner, pivot, scale, rotation, texcoord, tint, alpha
+const FLOATS_PER_VERTEX: usize = 14;
+const MAX_VERTICES: usize = 6 * 4096;
+const CIRCLE_SEGMENTS: usize = 64;
+// Textures nobody has drawn for this many frames are deleted.
+const TEXTURE_LIFETIME: u64 = 600;
+
+// Every quad is placed by rotating and scaling its corners around a pivot on
+// the GPU, so sprites never need a transform set on the CPU side.
+const SPRITE_VERTEX_SHADER: &str = r#"#version 300 es
+layout(location = 0) in vec2 a_corner;
+layout(location = 1) in vec2 a_pivot;
+layout(location = 2) in vec2 a_scale;
+layout(location = 3) in float a_rotation;
+layout(location = 4) in vec2 a_texcoord;
+layout(location = 5) in vec4 a_tint;
+layout(location = 6) in float a_alpha;
+uniform vec2 u_origin;
+uniform vec2 u_resolution;
+out vec2 v_texcoord;
+out vec4 v_tint;
+out float v_alpha;
+void main() {
+    vec2 scaled = a_corner * a_scale;
+    float c = cos(a_rotation);
+    float s = sin(a_rotation);
+    vec2 world = a_pivot + vec2(scaled.x * c - scaled.y * s, scaled.x * s + scaled.y * c);
+    vec2 clip = (world - u_origin) / u_resolution * 2.0 - 1.0;
+    gl_Position = vec4(clip.x, -clip.y, 0.0, 1.0);
+    v_texcoord = a_texcoord;
+    v_tint = a_tint;
+    v_alpha = a_alpha;
+}
+"#;
+
+// Textures are premultiplied, so the tint is scaled by each texel's coverage
+// to only colour the opaque pixels, like "source-atop" does on a 2d canvas.
+const SPRITE_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision mediump float;
+uniform sampler2D u_texture;
+in vec2 v_texcoord;
+in vec4 v_tint;
+in float v_alpha;
+out vec4 color;
+void main() {
+    vec4 texel = texture(u_texture, v_texcoord);
+    vec3 rgb = mix(texel.rgb, v_tint.rgb * texel.a, v_tint.a);
+    color = vec4(rgb, texel.a) * v_alpha;
+}
+"#;
+
+const PIXELATE_VERTEX_SHADER: &str = r#"#version 300 es
+layout(location = 0) in vec2 a_position;
+void main() {
+    gl_Position = vec4(a_position, 0.0, 1.0);
+}
+"#;
+
+// Blocks are measured from the top so they line up with the 2d backend.
+const PIXELATE_FRAGMENT_SHADER: &str = r#"#version 300 es
+precision mediump float;
+uniform sampler2D u_frame;
+uniform vec2 u_size;
+uniform float u_block;
+out vec4 color;
+void main() {
+    vec2 pixel = vec2(gl_FragCoord.x, u_size.y - gl_FragCoord.y);
+    vec2 center = (floor(pixel / u_block) + 0.5) * u_block;
+    color = texture(u_frame, vec2(center.x, u_size.y - center.y) / u_size);
+}
+"#;
+
+struct Texture {
+    version: u32,
+    texture: WebGlTexture,
+    width: f32,
+    height: f32,
+    last_used: u64,
+}
+
+// Vertices waiting to be drawn with `texture` in a single call.
+struct Batch {
+    texture: Option<WebGlTexture>,
+    vertices: Vec<f32>,
+}
+
+struct Vertex {
+    corner: (f32, f32),
+    pivot: (f32, f32),
+    scale: (f32, f32),
+    rotation: f32,
+    texcoord: (f32, f32),
+    tint: [f32; 4],
+    alpha: f32,
+}
+
+pub struct WebGl2 {
+    context: Gl,
+    canvas: HtmlCanvasElement,
+    sprite_program: WebGlProgram,
+    sprite_vertex_array: WebGlVertexArrayObject,
+    sprite_buffer: WebGlBuffer,
+    pixelate_program: WebGlProgram,
+    pixelate_vertex_array: WebGlVertexArrayObject,
+    // Solid shapes are drawn as sprites of this 1x1 white texture.
+    white: WebGlTexture,
+    // What is on screen so far, copied for `pixelate`.
+    frame_copy: WebGlTexture,
+    textures: RefCell<HashMap<ImageId, Texture>>,
+    batch: RefCell<Batch>,
+    screen: Cell<Screen>,
+    origin: Cell<Point>,
+    alpha: Cell<f64>,
+    frame: Cell<u64>,
+}
+impl WebGl2 {
+    pub fn new(context: Gl, screen: Screen) -> Result<Self> {
+        let canvas = context
+            .canvas()
+            .ok_or_else(|| anyhow!("no canvas for the webgl2 context"))?
+            .dyn_into::<HtmlCanvasElement>()
+            .map_err(|object| anyhow!("error converting {:#?} into HtmlCanvasElement", object))?;
+        let sprite_program = link(&context, SPRITE_VERTEX_SHADER, SPRITE_FRAGMENT_SHADER)?;
+        let pixelate_program = link(&context, PIXELATE_VERTEX_SHADER, PIXELATE_FRAGMENT_SHADER)?;
+
+        let sprite_vertex_array = create_vertex_array(&context)?;
+        let sprite_buffer = create_buffer(&context)?;
+        context.bind_vertex_array(Some(&sprite_vertex_array));
+        context.bind_buffer(Gl::ARRAY_BUFFER, Some(&sprite_buffer));
+        let stride = (FLOATS_PER_VERTEX * 4) as i32;
+        let mut offset = 0;
+        for (location, size) in [2, 2, 2, 1, 2, 4, 1].into_iter().enumerate() {
+            context.enable_vertex_attrib_array(location as u32);
+            context.vertex_attrib_pointer_with_i32(
+                location as u32,
+                size,
+                Gl::FLOAT,
+                false,
+                stride,
+                offset,
+            );
+            offset += size * 4;
+        }
+
+        // Two triangles covering the whole viewport.
+        let pixelate_vertex_array = create_vertex_array(&context)?;
+        let pixelate_buffer = create_buffer(&context)?;
+        context.bind_vertex_array(Some(&pixelate_vertex_array));
+        context.bind_buffer(Gl::ARRAY_BUFFER, Some(&pixelate_buffer));
+        context.buffer_data_with_u8_array(
+            Gl::ARRAY_BUFFER,
+            &to_bytes(&[
+                -1.0, -1.0, 1.0, -1.0, -1.0, 1.0, -1.0, 1.0, 1.0, -1.0, 1.0, 1.0,
+            ]),
+            Gl::STATIC_DRAW,
+        );
+        context.enable_vertex_attrib_array(0);
+        context.vertex_attrib_pointer_with_i32(0, 2, Gl::FLOAT, false, 0, 0);
+        context.bind_vertex_array(None);
+
+        let white = create_texture(&context)?;
+        context
+            .tex_image_2d_with_i32_and_i32_and_i32_and_format_and_type_and_opt_u8_array(
+                Gl::TEXTURE_2D,
+                0,
+                Gl::RGBA as i32,
+                1,
+                1,
+                0,
+                Gl::RGBA,
+                Gl::UNSIGNED_BYTE,
+                Some(&[255, 255, 255, 255]),
+            )
+            .map_err(|err| anyhow!("error creating white texture: {:#?}", err))?;
+        let frame_copy = create_texture(&context)?;
+
+        context.pixel_storei(Gl::UNPACK_PREMULTIPLY_ALPHA_WEBGL, 1);
+        context.blend_func(Gl::ONE, Gl::ONE_MINUS_SRC_ALPHA);
+        Ok(Self {
+            context,
+            canvas,
+            sprite_program,
+            sprite_vertex_array,
+            sprite_buffer,
+            pixelate_program,
+            pixelate_vertex_array,
+            white,
+            frame_copy,
+            textures: RefCell::new(HashMap::new()),
+            batch: RefCell::new(Batch {
+                texture: None,
+                vertices: Vec::new(),
+            }),
+            screen: Cell::new(screen),
+            origin: Cell::new(Point { x: 0, y: 0 }),
+            alpha: Cell::new(1.0),
+            frame: Cell::new(0),
+        })
+    }
+
+    fn uniform(&self, program: &WebGlProgram, name: &str) -> Option<WebGlUniformLocation> {
+        self.context.get_uniform_location(program, name)
+    }
+
+    // Draws everything batched so far.
+    fn flush(&self) {
+        let mut batch = self.batch.borrow_mut();
+        let Some(texture) = &batch.texture else {
+            return;
+        };
+        if batch.vertices.is_empty() {
+            return;
+        }
+        let context = &self.context;
+        let screen = self.screen.get();
+        let origin = self.origin.get();
+        context.use_program(Some(&self.sprite_program));
+        context.bind_vertex_array(Some(&self.sprite_vertex_array));
+        context.bind_buffer(Gl::ARRAY_BUFFER, Some(&self.sprite_buffer));
+        context.buffer_data_with_u8_array(
+            Gl::ARRAY_BUFFER,
+            &to_bytes(&batch.vertices),
+            Gl::STREAM_DRAW,
+        );
+        context.active_texture(Gl::TEXTURE0);
+        context.bind_texture(Gl::TEXTURE_2D, Some(texture));
+        context.uniform1i(self.uniform(&self.sprite_program, "u_texture").as_ref(), 0);
+        context.uniform2f(
+            self.uniform(&self.sprite_program, "u_origin").as_ref(),
+            origin.x.into(),
+            origin.y.into(),
+        );
+        context.uniform2f(
+            self.uniform(&self.sprite_program, "u_resolution").as_ref(),
+            screen.width.into(),
+            screen.height.into(),
+        );
+        context.enable(Gl::BLEND);
+        context.draw_arrays(
+            Gl::TRIANGLES,
+            0,
+            (batch.vertices.len() / FLOATS_PER_VERTEX) as i32,
+        );
+        context.bind_vertex_array(None);
+        batch.vertices.clear();
+    }
+
+    // Makes `texture` the one being batched, flushing whatever came before
+    // when it differs or the batch is full.
+    fn begin(&self, texture: &WebGlTexture, vertices: usize) {
+        let full = {
+            let batch = self.batch.borrow();
+            batch.texture.as_ref() != Some(texture)
+                || batch.vertices.len() / FLOATS_PER_VERTEX + vertices > MAX_VERTICES
+        };
+        if full {
+            self.flush();
+            self.batch.borrow_mut().texture = Some(texture.clone());
+        }
+    }
+
+    fn push(&self, vertex: Vertex) {
+        self.batch.borrow_mut().vertices.extend_from_slice(&[
+            vertex.corner.0,
+            vertex.corner.1,
+            vertex.pivot.0,
+            vertex.pivot.1,
+            vertex.scale.0,
+            vertex.scale.1,
+            vertex.rotation,
+            vertex.texcoord.0,
+            vertex.texcoord.1,
+            vertex.tint[0],
+            vertex.tint[1],
+            vertex.tint[2],
+            vertex.tint[3],
+            vertex.alpha,
+        ]);
+    }
+
+    // An untransformed, solid-coloured triangle list in world coordinates.
+    fn fill_triangles(&self, points: &[(f32, f32)], color: &str) {
+        let Some([r, g, b, a]) = parse_color(color) else {
+            return;
+        };
+        self.begin(&self.white, points.len());
+        for &corner in points {
+            self.push(Vertex {
+                corner,
+                pivot: (0.0, 0.0),
+                scale: (1.0, 1.0),
+                rotation: 0.0,
+                texcoord: (0.5, 0.5),
+                tint: [r, g, b, 1.0],
+                alpha: a * self.alpha.get() as f32,
+            });
+        }
+    }
+
+    fn fill_area(&self, x: f32, y: f32, w: f32, h: f32, color: &str) {
+        self.fill_triangles(
+            &[
+                (x, y),
+                (x + w, y),
+                (x, y + h),
+                (x, y + h),
+                (x + w, y),
+                (x + w, y + h),
+            ],
+            color,
+        );
+    }
+
+    // Limits drawing to `rect`, or lifts the limit with `None`. Scissor boxes
+    // are in device pixels counted from the bottom of the canvas.
+    fn scissor(&self, rect: Option<&Rect>) {
+        let Some(rect) = rect else {
+            self.context.disable(Gl::SCISSOR_TEST);
+            return;
+        };
+        let scale = self.screen.get().scale;
+        let origin = self.origin.get();
+        let x = ((rect.x - origin.x) as f64 * scale) as i32;
+        let y = ((rect.y - origin.y) as f64 * scale) as i32;
+        let w = (rect.w as f64 * scale) as i32;
+        let h = (rect.h as f64 * scale) as i32;
+        self.context.enable(Gl::SCISSOR_TEST);
+        self.context
+            .scissor(x, self.canvas.height() as i32 - y - h, w.max(0), h.max(0));
+    }
+
+    // Uploads `image` the first time it is drawn and again whenever its
+    // version changes. `None` while an image is still loading.
+    fn texture(&self, image: ImageSource) -> Result<Option<(WebGlTexture, f32, f32)>> {
+        let (id, version) = match image {
+            ImageSource::Image(image, id) => {
+                if !image.complete() || image.natural_width() == 0 {
+                    return Ok(None);
+                }
+                (id, 0)
+            }
+            ImageSource::Canvas(_, id, version) => (id, version),
+        };
+        let frame = self.frame.get();
+        let stale = match self.textures.borrow_mut().get_mut(&id) {
+            Some(texture) => {
+                texture.last_used = frame;
+                if texture.version == version {
+                    return Ok(Some((
+                        texture.texture.clone(),
+                        texture.width,
+                        texture.height,
+                    )));
+                }
+                Some(texture.texture.clone())
+            }
+            None => None,
+        };
+        if let Some(stale) = stale {
+            // Pending quads were meant to show the old pixels.
+            self.flush();
+            self.context.bind_texture(Gl::TEXTURE_2D, Some(&stale));
+            let (width, height) = self.upload(image)?;
+            if let Some(texture) = self.textures.borrow_mut().get_mut(&id) {
+                texture.version = version;
+                texture.width = width;
+                texture.height = height;
+            }
+            return Ok(Some((stale, width, height)));
+        }
+        let texture = create_texture(&self.context)?;
+        let (width, height) = self.upload(image)?;
+        self.textures.borrow_mut().insert(
+            id,
+            Texture {
+                version,
+                texture: texture.clone(),
+                width,
+                height,
+                last_used: frame,
+            },
+        );
+        Ok(Some((texture, width, height)))
+    }
+
+    // Copies `image` into the bound texture.
+    fn upload(&self, image: ImageSource) -> Result<(f32, f32)> {
+        let (result, width, height) = match image {
+            ImageSource::Image(image, _) => (
+                self.context
+                    .tex_image_2d_with_u32_and_u32_and_html_image_element(
+                        Gl::TEXTURE_2D,
+                        0,
+                        Gl::RGBA as i32,
+                        Gl::RGBA,
+                        Gl::UNSIGNED_BYTE,
+                        image,
+                    ),
+                image.natural_width(),
+                image.natural_height(),
+            ),
+            ImageSource::Canvas(canvas, _, _) => (
+                self.context
+                    .tex_image_2d_with_u32_and_u32_and_html_canvas_element(
+                        Gl::TEXTURE_2D,
+                        0,
+                        Gl::RGBA as i32,
+                        Gl::RGBA,
+                        Gl::UNSIGNED_BYTE,
+                        canvas,
+                    ),
+                canvas.width(),
+                canvas.height(),
+            ),
+        };
+        result.map_err(|err| anyhow!("error uploading texture: {:#?}", err))?;
+        Ok((width as f32, height as f32))
+    }
+}
+impl Backend for WebGl2 {
+    fn screen(&self) -> Screen {
+        self.screen.get()
+    }
+
+    fn resize(&self, screen: Screen) -> Result<()> {
+        self.flush();
+        screen.apply(&self.canvas)?;
+        self.screen.set(screen);
+        self.context.viewport(
+            0,
+            0,
+            self.canvas.width() as i32,
+            self.canvas.height() as i32,
+        );
+        self.set_origin(&Point { x: 0, y: 0 })
+    }
+
+    fn set_origin(&self, origin: &Point) -> Result<()> {
+        if self.origin.get() != *origin {
+            self.flush();
+            self.origin.set(*origin);
+        }
+        Ok(())
+    }
+
+    fn alpha(&self) -> f64 {
+        self.alpha.get()
+    }
+
+    fn set_alpha(&self, alpha: f64) {
+        self.alpha.set(alpha);
+    }
+
+    fn clear(&self, rect: &Rect) {
+        self.flush();
+        self.scissor(Some(rect));
+        self.context.clear_color(0.0, 0.0, 0.0, 0.0);
+        self.context.clear(Gl::COLOR_BUFFER_BIT);
+        self.scissor(None);
+    }
+
+    fn fill_rect(&self, rect: &Rect, color: &str) {
+        self.fill_area(
+            rect.x.into(),
+            rect.y.into(),
+            rect.w.into(),
+            rect.h.into(),
+            color,
+        );
+    }
+
+    // Like a 2d canvas, the line is centred on the edge of `rect`.
+    fn stroke_rect(&self, rect: &Rect, color: &str, line_width: f64) {
+        let half = line_width as f32 / 2.0;
+        let (x, y) = (rect.x as f32 - half, rect.y as f32 - half);
+        let (w, h) = (
+            rect.w as f32 + line_width as f32,
+            rect.h as f32 + line_width as f32,
+        );
+        let line = line_width as f32;
+        self.fill_area(x, y, w, line, color);
+        self.fill_area(x, y + h - line, w, line, color);
+        self.fill_area(x, y + line, line, h - line * 2.0, color);
+        self.fill_area(x + w - line, y + line, line, h - line * 2.0, color);
+    }
+
+    // A ring from the circle out past the furthest corner, clipped to `rect`.
+    fn fill_outside_circle(
+        &self,
+        rect: &Rect,
+        center: &Point,
+        radius: f64,
+        color: &str,
+    ) -> Result<()> {
+        let (cx, cy) = (center.x as f32, center.y as f32);
+        let inner = radius.max(0.0) as f32;
+        let corners = [
+            (rect.x, rect.y),
+            (rect.x + rect.w, rect.y),
+            (rect.x, rect.y + rect.h),
+            (rect.x + rect.w, rect.y + rect.h),
+        ];
+        let outer = corners
+            .iter()
+            .map(|&(x, y)| (x as f32 - cx).hypot(y as f32 - cy))
+            .fold(0.0, f32::max)
+            + 1.0;
+        if inner >= outer {
+            return Ok(());
+        }
+        let mut points = Vec::with_capacity(CIRCLE_SEGMENTS * 6);
+        let at = |angle: f32, radius: f32| (cx + angle.cos() * radius, cy + angle.sin() * radius);
+        for segment in 0..CIRCLE_SEGMENTS {
+            let from = segment as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            let to = (segment + 1) as f32 / CIRCLE_SEGMENTS as f32 * std::f32::consts::TAU;
+            let (a, b) = (at(from, inner), at(to, inner));
+            let (c, d) = (at(from, outer), at(to, outer));
+            points.extend_from_slice(&[a, c, b, b, c, d]);
+        }
+        self.flush();
+        self.scissor(Some(rect));
+        self.fill_triangles(&points, color);
+        self.flush();
+        self.scissor(None);
+        Ok(())
+    }
+
+    fn pixelate(&self, block: i16) -> Result<()> {
+        self.flush();
+        let context = &self.context;
+        let (width, height) = (self.canvas.width() as i32, self.canvas.height() as i32);
+        context.active_texture(Gl::TEXTURE0);
+        context.bind_texture(Gl::TEXTURE_2D, Some(&self.frame_copy));
+        context.copy_tex_image_2d(Gl::TEXTURE_2D, 0, Gl::RGBA, 0, 0, width, height, 0);
+        context.use_program(Some(&self.pixelate_program));
+        context.uniform1i(self.uniform(&self.pixelate_program, "u_frame").as_ref(), 0);
+        context.uniform2f(
+            self.uniform(&self.pixelate_program, "u_size").as_ref(),
+            width as f32,
+            height as f32,
+        );
+        context.uniform1f(
+            self.uniform(&self.pixelate_program, "u_block").as_ref(),
+            (block as f64 * self.screen.get().scale) as f32,
+        );
+        context.disable(Gl::BLEND);
+        context.bind_vertex_array(Some(&self.pixelate_vertex_array));
+        context.draw_arrays(Gl::TRIANGLES, 0, 6);
+        context.bind_vertex_array(None);
+        context.enable(Gl::BLEND);
+        Ok(())
+    }
+
+    fn draw_image(
+        &self,
+        image: ImageSource,
+        source: &SheetRect,
+        destination: &Rect,
+        options: &DrawOptions,
+    ) -> Result<()> {
+        let Some((texture, texture_width, texture_height)) = self.texture(image)? else {
+            return Ok(());
+        };
+        let tint = match options.tint {
+            Some(color) => parse_color(color).unwrap_or([0.0; 4]),
+            None => [0.0; 4],
+        };
+        let pivot = options.pivot.unwrap_or(Point {
+            x: destination.w / 2,
+            y: destination.h / 2,
+        });
+        let flip = |flipped: bool| if flipped { -1.0 } else { 1.0 };
+        let scale = (
+            (options.scale_x * flip(options.flip_x)) as f32,
+            (options.scale_y * flip(options.flip_y)) as f32,
+        );
+        let alpha = (self.alpha.get() * options.alpha) as f32;
+        let left = -pivot.x as f32;
+        let top = -pivot.y as f32;
+        let right = left + destination.w as f32;
+        let bottom = top + destination.h as f32;
+        let u0 = source.x as f32 / texture_width;
+        let v0 = source.y as f32 / texture_height;
+        let u1 = (source.x + source.w) as f32 / texture_width;
+        let v1 = (source.y + source.h) as f32 / texture_height;
+
+        self.begin(&texture, 6);
+        for (corner, texcoord) in [
+            ((left, top), (u0, v0)),
+            ((right, top), (u1, v0)),
+            ((left, bottom), (u0, v1)),
+            ((left, bottom), (u0, v1)),
+            ((right, top), (u1, v0)),
+            ((right, bottom), (u1, v1)),
+        ] {
+            self.push(Vertex {
+                corner,
+                pivot: (
+                    (destination.x + pivot.x) as f32,
+                    (destination.y + pivot.y) as f32,
+                ),
+                scale,
+                rotation: options.rotation as f32,
+                texcoord,
+                tint,
+                alpha,
+            });
+        }
+        Ok(())
+    }
+
+    fn present(&self) -> Result<()> {
+        self.flush();
+        let frame = self.frame.get();
+        self.textures.borrow_mut().retain(|_, texture| {
+            let alive = frame - texture.last_used < TEXTURE_LIFETIME;
+            if !alive {
+                self.context.delete_texture(Some(&texture.texture));
+            }
+            alive
+        });
+        self.frame.set(frame + 1);
+        Ok(())
+    }
+}
+
+fn compile(context: &Gl, kind: u32, source: &str) -> Result<WebGlShader> {
+    let shader = context
+        .create_shader(kind)
+        .ok_or_else(|| anyhow!("error creating shader"))?;
+    context.shader_source(&shader, source);
+    context.compile_shader(&shader);
+    if context
+        .get_shader_parameter(&shader, Gl::COMPILE_STATUS)
+        .as_bool()
+        .unwrap_or(false)
+    {
+        Ok(shader)
+    } else {
+        Err(anyhow!(
+            "error compiling shader: {}",
+            context.get_shader_info_log(&shader).unwrap_or_default()
+        ))
+    }
+}
+
+fn link(context: &Gl, vertex_source: &str, fragment_source: &str) -> Result<WebGlProgram> {
+    let program = context
+        .create_program()
+        .ok_or_else(|| anyhow!("error creating program"))?;
+    context.attach_shader(
+        &program,
+        &compile(context, Gl::VERTEX_SHADER, vertex_source)?,
+    );
+    context.attach_shader(
+        &program,
+        &compile(context, Gl::FRAGMENT_SHADER, fragment_source)?,
+    );
+    context.link_program(&program);
+    if context
+        .get_program_parameter(&program, Gl::LINK_STATUS)
+        .as_bool()
+        .unwrap_or(false)
+    {
+        Ok(program)
+    } else {
+        Err(anyhow!(
+            "error linking program: {}",
+            context.get_program_info_log(&program).unwrap_or_default()
+        ))
+    }
+}
+
+fn create_vertex_array(context: &Gl) -> Result<WebGlVertexArrayObject> {
+    context
+        .create_vertex_array()
+        .ok_or_else(|| anyhow!("error creating vertex array"))
+}
+
+fn create_buffer(context: &Gl) -> Result<WebGlBuffer> {
+    context
+        .create_buffer()
+        .ok_or_else(|| anyhow!("error creating buffer"))
+}
+
+// Creates a texture for pixel art and leaves it bound.
+fn create_texture(context: &Gl) -> Result<WebGlTexture> {
+    let texture = context
+        .create_texture()
+        .ok_or_else(|| anyhow!("error creating texture"))?;
+    context.bind_texture(Gl::TEXTURE_2D, Some(&texture));
+    for (parameter, value) in [
+        (Gl::TEXTURE_MIN_FILTER, Gl::NEAREST),
+        (Gl::TEXTURE_MAG_FILTER, Gl::NEAREST),
+        (Gl::TEXTURE_WRAP_S, Gl::CLAMP_TO_EDGE),
+        (Gl::TEXTURE_WRAP_T, Gl::CLAMP_TO_EDGE),
+    ] {
+        context.tex_parameteri(Gl::TEXTURE_2D, parameter, value as i32);
+    }
+    Ok(texture)
+}
+
+fn to_bytes(floats: &[f32]) -> Vec<u8> {
+    floats
+        .iter()
+        .flat_map(|float| float.to_le_bytes())
+        .collect()
+}
+
+// The colour as floats from 0 to 1, as the shaders take it.
+fn parse_color(color: &str) -> Option<[f32; 4]> {
+    color::parse_color(color).map(|rgba| rgba.map(|channel| channel as f32 / 255.0))
+}
@@ -1,24 +1,610 @@
+mod battle;
+mod battle_scene;
+mod bestiary;
+mod dialogue;
+mod dungeon;
+mod effects;
+mod encounter;
+mod equip_menu;
+mod equipment;
+mod field_menu;
+mod flags;
 mod floor;
+mod inn;
+mod interaction;
+mod item;
+mod item_menu;
+mod lighting;
+mod map;
+mod message_window;
+mod minimap;
+mod monster;
+mod npc;
+mod object;
+mod party;
 mod player;
+mod save;
+mod shop;
+mod state;
 
-use crate::engine::{Game, KeyState, Rect, Renderer, SpriteSheetStore};
+use crate::engine::{
+    Action, DrawOptions, Easing, Emitter, Game, KeyState, Point, Rect, RenderLayer, RenderQueue,
+    Renderer, SpriteSheetStore, Stream, Transition, TransitionEffect, TransitionStatus,
+};
+use battle::{BattleSettings, Outcome};
+use battle_scene::BattleScene;
+use dialogue::Dialogues;
+use effects::Effects;
+use encounter::REPEL_FLAG;
+use field_menu::FieldMenu;
+use flags::Effect;
+use floor::{Floor, DOOR_CLOSED, DOOR_OPEN, GROUND};
+use inn::Inn;
+use interaction::{Immediate, Interaction, InteractionHandler, InteractionStatus, Message, Target};
+use lighting::{Lighting, TORCH_FLAG};
+use map::{MapState, Warp, WarpKind};
+use minimap::{Hero, Marker, Minimap};
+use npc::Npc;
+use object::{FieldObject, ObjectKind};
 use player::{Player, PlayerStateContext, PlayerStateMachine};
+use save::Save;
+use shop::Shop;
+use state::GameState;
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
-const CANVAS_WIDTH: i16 = 480;
-const CANVAS_HEIGHT: i16 = 480;
 const TILE_WIDTH: i16 = 32;
 const TILE_HEIGHT: i16 = 32;
+const DUST_PARTICLES: usize = 4;
+const FIRST_MAP: &str = "village";
+const WARP_DURATION: u32 = 40;
+const CHEST_MARKER_COLOR: &str = "#fbf236";
+const STAIRS_MARKER_COLOR: &str = "#5fcde4";
+const DOOR_MARKER_COLOR: &str = "#d9a066";
 
 pub struct RQ {
     player: Player,
+    floor: Option<Floor>,
+    npcs: Vec<Npc>,
+    objects: Vec<FieldObject>,
+    interaction: Option<Interaction>,
+    transition: Option<Transition<RQ>>,
+    dialogues: Option<Dialogues>,
+    effects: Option<Effects>,
+    battles: Option<Rc<BattleSettings>>,
+    // One-off effects in world space; removed once they play out.
+    particles: Vec<Emitter>,
+    // Only on dark floors.
+    lighting: Option<Lighting>,
+    minimap: Option<Minimap>,
+    // Maps the player has left, by name.
+    maps: HashMap<String, MapState>,
+    state: GameState,
+    screen: Rect,
+    // Whether to start from the last save, if there is one.
+    resume: bool,
 }
 impl RQ {
-    pub fn new() -> Self {
+    pub fn new(seed: u32, resume: bool) -> Self {
         Self {
             player: Player::new(),
+            floor: None,
+            npcs: Vec::new(),
+            objects: Vec::new(),
+            interaction: None,
+            transition: None,
+            dialogues: None,
+            effects: None,
+            battles: None,
+            particles: Vec::new(),
+            lighting: None,
+            minimap: None,
+            maps: HashMap::new(),
+            state: GameState::new(seed),
+            screen: Rect {
+                x: 0,
+                y: 0,
+                w: 0,
+                h: 0,
+            },
+            resume,
+        }
+    }
+
+    // Tiles covered by every entity except the NPC at `except`; the player is
+    // included only when `except` is `Some`.
+    fn occupied(&self, except: Option<usize>) -> HashSet<Point> {
+        let mut tiles = HashSet::new();
+        if except.is_some() {
+            if let Some(state_machine) = &self.player.state_machine {
+                occupy(&mut tiles, state_machine.context().position());
+            }
+        }
+        for (index, npc) in self.npcs.iter().enumerate() {
+            if Some(index) != except {
+                occupy(&mut tiles, npc.position());
+            }
+        }
+        for object in self.objects.iter().filter(|object| object.blocks()) {
+            occupy(
+                &mut tiles,
+                &Point {
+                    x: object.tile.x * TILE_WIDTH,
+                    y: object.tile.y * TILE_HEIGHT,
+                },
+            );
+        }
+        tiles
+    }
+
+    fn target_at(&self, tile: &Point) -> Option<Target> {
+        self.npcs
+            .iter()
+            .position(|npc| !npc.is_moving() && npc.tile() == *tile)
+            .map(Target::Npc)
+            .or_else(|| {
+                self.objects
+                    .iter()
+                    .position(|object| object.tile == *tile)
+                    .map(Target::Object)
+            })
+    }
+
+    // Looks at the tile the player faces and starts the matching handler; the
+    // player goes straight back to `Stopped` when there is nothing there.
+    fn begin_interaction(&mut self) -> Result<()> {
+        let Some(state_machine) = self.player.state_machine.take() else {
+            return Ok(());
+        };
+        let context = state_machine.context();
+        let direction = context.direction();
+        let Some(target) = self.target_at(&context.tile_in_front()) else {
+            self.player
+                .state_machine
+                .replace(state_machine.finish_interaction());
+            return Ok(());
+        };
+        self.player.state_machine.replace(state_machine);
+
+        let handler: Box<dyn InteractionHandler> = match target {
+            Target::Npc(index) => {
+                let npc = &mut self.npcs[index];
+                npc.talk_to(direction);
+                if let Some(formation) = npc.battle.clone() {
+                    return self.start_battle(&formation, Some(target));
+                }
+                if let (Some(shop), Some(battles)) = (&npc.shop, &self.battles) {
+                    Box::new(Shop::new(
+                        &npc.name,
+                        shop,
+                        battles.items.clone(),
+                        &self.state,
+                    )?)
+                } else if let Some(inn) = &npc.inn {
+                    Box::new(Inn::new(&npc.name, inn, &self.state)?)
+                } else {
+                    match (&npc.dialogue, &self.dialogues) {
+                        (Some(name), Some(dialogues)) => {
+                            Box::new(dialogues.start(name, &mut self.state)?)
+                        }
+                        _ => Box::new(Message::new(vec![format!("{}: ...", npc.name)])),
+                    }
+                }
+            }
+            Target::Object(index) => {
+                let object = &mut self.objects[index];
+                let flag = self.floor.as_ref().map(|floor| object.flag(&floor.name));
+                match &mut object.kind {
+                    ObjectKind::Chest { item, opened } => {
+                        if *opened {
+                            Box::new(Message::new(vec!["The chest is empty.".to_string()]))
+                        } else {
+                            let items = self.battles.as_ref().map(|battles| &battles.items);
+                            let found = items.map_or(item.clone(), |items| items.a(item));
+                            match items.map(|items| items.stow(&mut self.state.party, item)) {
+                                Some(None) => Box::new(Message::new(vec![format!(
+                                    "You found {}, but nobody has room for it.",
+                                    found
+                                )])),
+                                _ => {
+                                    *opened = true;
+                                    if let Some(flag) = flag {
+                                        self.state.flags.apply(&Effect::Set(flag, 1));
+                                    }
+                                    if let Some(sparkles) = &mut object.sparkles {
+                                        sparkles.stop();
+                                    }
+                                    Box::new(Message::new(vec![format!("You found {}!", found)]))
+                                }
+                            }
+                        }
+                    }
+                    ObjectKind::Sign { text } => Box::new(Message::new(vec![text.clone()])),
+                    ObjectKind::Door {
+                        key: Some(name), ..
+                    } => {
+                        let holder = self
+                            .state
+                            .party
+                            .iter_mut()
+                            .find(|member| member.bag.count(name) > 0);
+                        match holder {
+                            Some(member) => {
+                                member.bag.remove(name);
+                                let text = format!(
+                                    "{} unlocks the door with the {}.",
+                                    member.fighter.name, name
+                                );
+                                object.kind = ObjectKind::Door {
+                                    open: false,
+                                    key: None,
+                                };
+                                if let Some(flag) = flag {
+                                    self.state.flags.apply(&Effect::Set(flag, 1));
+                                }
+                                Box::new(Message::new(vec![text]))
+                            }
+                            None => Box::new(Message::new(vec!["The door is locked.".to_string()])),
+                        }
+                    }
+                    ObjectKind::Door { open, .. } => {
+                        *open = !*open;
+                        if let Some(floor) = &mut self.floor {
+                            let key = if *open { DOOR_OPEN } else { DOOR_CLOSED };
+                            floor.set_tile(GROUND, &object.tile, key)?;
+                            if let Some(minimap) = &self.minimap {
+                                minimap.repaint(floor, &object.tile);
+                            }
+                        }
+                        Box::new(Immediate)
+                    }
+                }
+            }
+        };
+        self.interaction = Some(Interaction {
+            target: Some(target),
+            handler,
+        });
+        Ok(())
+    }
+
+    // Called each time the player finishes walking onto `tile`, with the
+    // number of steps walked so far.
+    fn finish_step(&mut self, tile: &Point, steps: u32) -> Result<()> {
+        for flag in [TORCH_FLAG, REPEL_FLAG] {
+            if self.state.flags.get(flag) > 0 {
+                self.state.flags.apply(&Effect::Add(flag.to_string(), -1));
+            }
+        }
+        let warp = self
+            .floor
+            .as_ref()
+            .and_then(|floor| floor.warp_at(tile))
+            .filter(|warp| warp.kind != WarpKind::Edge)
+            .cloned();
+        match warp {
+            Some(warp) => {
+                self.start_warp(warp);
+                Ok(())
+            }
+            None => self.check_encounter(tile, steps),
+        }
+    }
+
+    fn check_encounter(&mut self, tile: &Point, steps: u32) -> Result<()> {
+        let (Some(floor), Some(settings)) = (&self.floor, &self.battles) else {
+            return Ok(());
+        };
+        let Some(encounters) = &floor.encounters else {
+            return Ok(());
+        };
+        if self.state.in_grace(steps, encounters.grace) || !floor.allows_encounters(tile) {
+            return Ok(());
+        }
+        // Repel keeps away whatever is weaker than the party.
+        let repel = self.state.flags.get(REPEL_FLAG) > 0;
+        let level = self
+            .state
+            .party
+            .iter()
+            .map(|member| member.fighter.level)
+            .max()
+            .unwrap_or(0);
+        let formation = encounters
+            .roll(
+                tile,
+                |formation| repel && settings.formation_level(formation) < level,
+                self.state.random.stream(Stream::Combat),
+            )
+            .map(str::to_string);
+        match formation {
+            Some(formation) => self.start_battle(&formation, None),
+            None => Ok(()),
+        }
+    }
+
+    // Edge warps are taken by pushing against the edge of the floor.
+    fn check_edge(&mut self, key_state: &KeyState) {
+        let Some(state_machine) = &self.player.state_machine else {
+            return;
+        };
+        if state_machine.is_moving() || state_machine.is_interacting() {
+            return;
+        }
+        let context = state_machine.context();
+        if !key_state.is_action_pressed(context.direction().action()) {
+            return;
+        }
+        let warp = self
+            .floor
+            .as_ref()
+            .and_then(|floor| floor.warp_at(&context.tile_in_front()))
+            .filter(|warp| warp.kind == WarpKind::Edge)
+            .cloned();
+        if let Some(warp) = warp {
+            self.start_warp(warp);
+        }
+    }
+
+    // The maps are swapped while the transition covers the screen.
+    fn start_warp(&mut self, warp: Warp) {
+        let transition = match &warp.transition {
+            Some(spec) => Transition::from_spec(spec),
+            None => Transition::new(
+                TransitionEffect::Fade {
+                    color: "#000000".to_string(),
+                },
+                WARP_DURATION,
+                Easing::EaseInOut,
+            ),
+        };
+        // An iris closes on the player, wherever the camera has them.
+        let transition = match &self.player.state_machine {
+            Some(state_machine) => {
+                let position = state_machine.context().position();
+                transition.focused_on(Point {
+                    x: position.x + TILE_WIDTH / 2,
+                    y: position.y + TILE_HEIGHT / 2,
+                })
+            }
+            None => transition,
+        };
+        self.transition = Some(transition.on_midpoint(move |rq: &mut RQ| rq.enter_map(&warp)));
+    }
+
+    fn enter_map(&mut self, warp: &Warp) -> Result<()> {
+        let map = match self.maps.remove(&warp.target) {
+            Some(map) => map,
+            None => MapState::load(
+                &warp.target,
+                self.effects.as_ref(),
+                &mut self.state.random,
+                &self.state.flags,
+            )?,
+        };
+        let spawn = warp
+            .spawn
+            .or_else(|| map.arrival(warp.kind, warp.facing))
+            .ok_or_else(|| anyhow!("nowhere to arrive on map {}", warp.target))?;
+        if let Some(floor) = self.floor.take() {
+            let left = MapState {
+                npcs: std::mem::take(&mut self.npcs),
+                objects: std::mem::take(&mut self.objects),
+                lighting: self.lighting.take(),
+                minimap: self.minimap.take(),
+                floor,
+            };
+            self.maps.insert(left.floor.name.clone(), left);
+        }
+        self.floor = Some(map.floor);
+        self.npcs = map.npcs;
+        self.objects = map.objects;
+        self.lighting = map.lighting;
+        self.minimap = map.minimap;
+        self.particles.clear();
+        if let Some(state_machine) = self.player.state_machine.take() {
+            self.player
+                .state_machine
+                .replace(state_machine.warp(&spawn, warp.facing));
+        }
+        // The floor's own entrance uncovers the screen in place of the rest of
+        // the warp's transition, which is dropped for the one set here.
+        if let Some(spec) = self
+            .floor
+            .as_ref()
+            .and_then(|floor| floor.entrance.as_ref())
+        {
+            self.transition = Some(Transition::from_spec(spec).revealing().focused_on(Point {
+                x: spawn.x * TILE_WIDTH + TILE_WIDTH / 2,
+                y: spawn.y * TILE_HEIGHT + TILE_HEIGHT / 2,
+            }));
+        }
+        Ok(())
+    }
+
+    // The battle takes over the screen while the transition covers it.
+    fn start_battle(&mut self, formation: &str, target: Option<Target>) -> Result<()> {
+        let Some(settings) = self.battles.clone() else {
+            return Ok(());
+        };
+        let spec = settings
+            .formations
+            .get(formation)
+            .ok_or_else(|| anyhow!("no Formation found with name {}", formation))?
+            .transition
+            .clone()
+            .unwrap_or_else(|| settings.transition.clone());
+        let formation = formation.to_string();
+        self.transition = Some(
+            Transition::from_spec(&spec).on_midpoint(move |rq: &mut RQ| {
+                let scene = BattleScene::new(
+                    settings,
+                    &formation,
+                    &mut rq.state,
+                    rq.effects.clone(),
+                    rq.screen,
+                )?;
+                rq.interaction = Some(Interaction {
+                    target,
+                    handler: Box::new(scene),
+                });
+                Ok(())
+            }),
+        );
+        Ok(())
+    }
+
+    fn end_battle(&mut self, outcome: Outcome) -> Result<()> {
+        let target = self
+            .interaction
+            .as_ref()
+            .and_then(|interaction| interaction.target);
+        self.end_interaction();
+        if let Some(state_machine) = &self.player.state_machine {
+            self.state.last_battle = state_machine.context().steps();
+        }
+        if outcome == Outcome::Lost {
+            return self.revive();
+        }
+        // Monsters that lose are gone for good.
+        if let (Outcome::Won, Some(Target::Npc(index))) = (outcome, target) {
+            self.npcs.remove(index);
+        }
+        self.transition = Some(
+            Transition::new(
+                TransitionEffect::Fade {
+                    color: "#000000".to_string(),
+                },
+                WARP_DURATION,
+                Easing::EaseInOut,
+            )
+            .revealing(),
+        );
+        Ok(())
+    }
+
+    // After a wipe-out the party wakes up somewhere safe, healed but poorer.
+    fn revive(&mut self) -> Result<()> {
+        let Some(settings) = self.battles.clone() else {
+            return Ok(());
+        };
+        let defeat = &settings.defeat;
+        for member in &mut self.state.party {
+            member.fighter.restore();
+        }
+        let gold = self.state.gold;
+        let kept = gold * defeat.gold_kept / 100;
+        self.state.gold = kept;
+        let here = self
+            .floor
+            .as_ref()
+            .is_some_and(|floor| floor.name == defeat.map);
+        if here {
+            if let Some(state_machine) = self.player.state_machine.take() {
+                self.player
+                    .state_machine
+                    .replace(state_machine.warp(&defeat.tile, defeat.facing));
+            }
+        } else {
+            self.enter_map(&Warp {
+                tile: defeat.tile,
+                kind: WarpKind::Door,
+                target: defeat.map.clone(),
+                spawn: Some(defeat.tile),
+                facing: defeat.facing,
+                transition: None,
+            })?;
+        }
+        self.transition = Some(
+            Transition::new(
+                TransitionEffect::Fade {
+                    color: "#000000".to_string(),
+                },
+                WARP_DURATION * 2,
+                Easing::EaseInOut,
+            )
+            .revealing(),
+        );
+        let mut pages = vec![format!("You come to in the {}.", defeat.map)];
+        if kept < gold {
+            pages.push(format!("You have lost {} gold.", gold - kept));
+        }
+        self.interaction = Some(Interaction {
+            target: None,
+            handler: Box::new(Message::new(pages)),
+        });
+        Ok(())
+    }
+
+    // Dark floors hide whatever the hero cannot see.
+    fn is_seen(&self, tile: &Point) -> bool {
+        self.lighting
+            .as_ref()
+            .is_none_or(|lighting| lighting.is_visible(tile))
+    }
+
+    // Unopened chests, stairs and doors to other maps.
+    fn markers(&self) -> Vec<Marker> {
+        let chests = self
+            .objects
+            .iter()
+            .filter(|object| matches!(object.kind, ObjectKind::Chest { opened: false, .. }))
+            .map(|object| Marker {
+                tile: object.tile,
+                color: CHEST_MARKER_COLOR,
+            });
+        let warps = self
+            .floor
+            .iter()
+            .flat_map(|floor| &floor.warps)
+            .filter_map(|warp| {
+                let color = match warp.kind {
+                    WarpKind::StairsUp | WarpKind::StairsDown => STAIRS_MARKER_COLOR,
+                    WarpKind::Door => DOOR_MARKER_COLOR,
+                    WarpKind::Edge => return None,
+                };
+                Some(Marker {
+                    tile: warp.tile,
+                    color,
+                })
+            });
+        chests.chain(warps).collect()
+    }
+
+    // Writes where the party stands; a failed save is not worth ending the game over.
+    fn save(&self) {
+        let (Some(floor), Some(state_machine)) = (&self.floor, &self.player.state_machine) else {
+            return;
+        };
+        let context = state_machine.context();
+        let written = save::write(&Save {
+            map: floor.name.clone(),
+            tile: tile_of(context.position()),
+            facing: context.direction(),
+            steps: context.steps(),
+            state: &self.state,
+        });
+        if let Err(error) = written {
+            log!("error saving: {:#}", error);
+        }
+    }
+
+    fn end_interaction(&mut self) {
+        if let Some(Interaction {
+            target: Some(Target::Npc(index)),
+            ..
+        }) = self.interaction.take()
+        {
+            self.npcs[index].finish_talking();
+        }
+        if let Some(state_machine) = self.player.state_machine.take() {
+            self.player
+                .state_machine
+                .replace(state_machine.finish_interaction());
         }
     }
 }
@@ -26,31 +612,391 @@ impl RQ {
 #[async_trait(?Send)]
 impl Game for RQ {
     async fn initialize(&self) -> Result<Box<dyn Game>> {
-        let player_state_context = PlayerStateContext::new("Sprite-0001");
+        let effects = Effects::load()?;
+        let battles = BattleSettings::load()?;
+        // A save that cannot be read is left alone for a new game.
+        let save = match self.resume.then(save::read).transpose() {
+            Ok(save) => save.flatten(),
+            Err(error) => {
+                log!("not resuming: {:#}", error);
+                None
+            }
+        };
+        let (mut state, map_name, start, steps) = match save {
+            Some(save) => (
+                save.state,
+                save.map,
+                Some((save.tile, save.facing)),
+                save.steps,
+            ),
+            None => {
+                let mut state = GameState::new(self.state.random.seed());
+                state.party = battles.party.starting_party(&battles.items);
+                (state, FIRST_MAP.to_string(), None, 0)
+            }
+        };
+        let map = MapState::load(&map_name, Some(&effects), &mut state.random, &state.flags)?;
+        let mut state_machine =
+            PlayerStateMachine::new(PlayerStateContext::new("Sprite-0001").with_steps(steps));
+        if let Some((tile, facing)) = start {
+            state_machine = state_machine.warp(&tile, facing);
+        }
+        let spawn = state_machine.context().position();
+        let transition = map.floor.entrance.as_ref().map(|spec| {
+            Transition::from_spec(spec).revealing().focused_on(Point {
+                x: spawn.x + TILE_WIDTH / 2,
+                y: spawn.y + TILE_HEIGHT / 2,
+            })
+        });
         Ok(Box::new(Self {
             player: Player {
-                state_machine: Some(PlayerStateMachine::new(player_state_context)),
+                state_machine: Some(state_machine),
             },
+            floor: Some(map.floor),
+            npcs: map.npcs,
+            objects: map.objects,
+            interaction: None,
+            transition,
+            dialogues: Some(Dialogues::load()?),
+            effects: Some(effects),
+            battles: Some(Rc::new(battles)),
+            particles: Vec::new(),
+            lighting: map.lighting,
+            minimap: map.minimap,
+            maps: HashMap::new(),
+            state,
+            screen: Rect {
+                x: 0,
+                y: 0,
+                w: 0,
+                h: 0,
+            },
+            resume: self.resume,
         }))
     }
     fn update(&mut self, key_state: &KeyState) -> Result<()> {
-        if let Some(state_machine) = self.player.state_machine.take() {
-            self.player
+        // The player waits for transitions to finish; the world keeps moving.
+        if let Some(mut transition) = self.transition.take() {
+            if transition.update(self)? == TransitionStatus::Running {
+                self.transition.get_or_insert(transition);
+            }
+        } else if let Some(interaction) = self.interaction.as_mut() {
+            match interaction.handler.update(key_state, &mut self.state)? {
+                InteractionStatus::Continue => {}
+                InteractionStatus::Finished => self.end_interaction(),
+                InteractionStatus::Battle(outcome) => self.end_battle(outcome)?,
+                InteractionStatus::Save => self.save(),
+            }
+        } else if let Some(minimap) = self.minimap.as_mut().filter(|minimap| minimap.is_open()) {
+            minimap.update(key_state, &self.screen);
+        } else if key_state.is_action_just_pressed(Action::Map)
+            && self.minimap.is_some()
+            && self
+                .player
                 .state_machine
-                .replace(state_machine.update(key_state));
+                .as_ref()
+                .is_some_and(|state_machine| !state_machine.is_moving())
+        {
+            if let (Some(minimap), Some(state_machine)) =
+                (&mut self.minimap, &self.player.state_machine)
+            {
+                minimap.open(state_machine.context().position(), &self.screen);
+            }
+        } else {
+            let occupied = self.occupied(None);
+            if let Some(state_machine) = self.player.state_machine.take() {
+                let was_moving = state_machine.is_moving();
+                let occupancy = Occupancy::new(self.floor.as_ref(), occupied);
+                let state_machine = state_machine.update(key_state, &occupancy);
+                if was_moving && !state_machine.is_moving() {
+                    let context = state_machine.context();
+                    self.finish_step(&tile_of(context.position()), context.steps())?;
+                }
+                if !was_moving && state_machine.is_moving() {
+                    if let Some(effects) = &self.effects {
+                        let position = state_machine.context().position();
+                        self.particles.push(effects.burst(
+                            "dust",
+                            Point {
+                                x: position.x + TILE_WIDTH / 2,
+                                y: feet(position) - 4,
+                            },
+                            DUST_PARTICLES,
+                            self.state.random.fork(Stream::Cosmetic),
+                        )?);
+                    }
+                }
+                self.player.state_machine.replace(state_machine);
+            }
+            if self.transition.is_none() {
+                self.check_edge(key_state);
+            }
+            if let Some(state_machine) = &self.player.state_machine {
+                if state_machine.is_interacting() {
+                    if key_state.is_action_just_pressed(Action::Confirm) {
+                        self.begin_interaction()?;
+                    } else {
+                        self.interaction = Some(Interaction {
+                            target: None,
+                            handler: Box::new(FieldMenu::new(self.battles.clone(), &self.state)),
+                        });
+                    }
+                }
+            }
+        }
+        for index in 0..self.npcs.len() {
+            let occupied = self.occupied(Some(index));
+            let occupancy = Occupancy::new(self.floor.as_ref(), occupied);
+            self.npcs[index].update(&occupancy);
+        }
+        for object in &mut self.objects {
+            if let Some(sparkles) = &mut object.sparkles {
+                sparkles.update();
+            }
+        }
+        for emitter in &mut self.particles {
+            emitter.update();
+        }
+        self.particles.retain(|emitter| !emitter.is_finished());
+        if let (Some(lighting), Some(floor), Some(state_machine)) =
+            (&mut self.lighting, &self.floor, &self.player.state_machine)
+        {
+            let torch = self.state.flags.get(TORCH_FLAG) > 0;
+            lighting.update(floor, state_machine.context().position(), torch);
+        }
+        // Dark floors are explored as far as the hero sees, lit ones as far
+        // as the screen shows.
+        if let (Some(minimap), Some(floor), Some(state_machine)) =
+            (&mut self.minimap, &self.floor, &self.player.state_machine)
+        {
+            match &self.lighting {
+                Some(lighting) => minimap.explore(floor, lighting.visible()),
+                None => {
+                    let camera = floor.camera(state_machine.context().position(), &self.screen);
+                    minimap.explore(floor, &tiles_in(&camera));
+                }
+            }
         }
         Ok(())
     }
     fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
-        renderer.clear(&Rect {
-            x: 0,
-            y: 0,
-            w: CANVAS_WIDTH,
-            h: CANVAS_HEIGHT,
+        renderer.clear(&self.screen);
+        let camera = match (&self.floor, &self.player.state_machine) {
+            (Some(floor), Some(state_machine)) => {
+                floor.camera(state_machine.context().position(), &self.screen)
+            }
+            _ => self.screen,
+        };
+        let mut queue = RenderQueue::new(Point {
+            x: camera.x,
+            y: camera.y,
         });
+        if let Some(floor) = &self.floor {
+            queue.submit(RenderLayer::Ground, 0, move |renderer| {
+                floor.draw_below(renderer, sprite_sheet_store, &camera)
+            });
+            queue.submit(RenderLayer::Overhead, 0, move |renderer| {
+                floor.draw_above(renderer, sprite_sheet_store, &camera)
+            });
+        }
+        for object in self
+            .objects
+            .iter()
+            .filter(|object| self.is_seen(&object.tile))
+        {
+            object.draw(&mut queue, sprite_sheet_store)?;
+        }
+        for emitter in &self.particles {
+            emitter.draw(&mut queue, sprite_sheet_store, emitter.position.y)?;
+        }
+        for npc in self.npcs.iter().filter(|npc| self.is_seen(&npc.tile())) {
+            npc.draw(&mut queue, sprite_sheet_store)?;
+        }
+        if let Some(lighting) = &self.lighting {
+            queue.submit(RenderLayer::Overhead, i16::MAX, move |renderer| {
+                lighting.draw(renderer, &camera)
+            });
+        }
         if let Some(state_machine) = &self.player.state_machine {
-            state_machine.draw(renderer, sprite_sheet_store)?;
+            // The one walking around wears the leader's equipment.
+            let layers: Vec<&str> = match (&self.battles, self.state.party.first()) {
+                (Some(battles), Some(leader)) => leader.equipment.layers(&battles.items).collect(),
+                _ => Vec::new(),
+            };
+            state_machine.draw(&mut queue, sprite_sheet_store, &layers)?;
+        }
+        if let (Some(minimap), Some(state_machine)) = (&self.minimap, &self.player.state_machine) {
+            let context = state_machine.context();
+            let markers = self.markers();
+            let screen = self.screen;
+            queue.submit(RenderLayer::Ui, -1, move |renderer| {
+                let hero = Hero {
+                    position: context.position(),
+                    direction: context.direction(),
+                };
+                if minimap.is_open() {
+                    minimap.draw_full(renderer, sprite_sheet_store, &screen, &hero, &markers)
+                } else {
+                    minimap.draw(renderer, &screen, &hero, &markers)
+                }
+            });
         }
+        if let Some(interaction) = &self.interaction {
+            queue.submit(RenderLayer::Ui, 0, move |renderer| {
+                interaction.handler.draw(renderer, sprite_sheet_store)
+            });
+        }
+        if let Some(transition) = &self.transition {
+            let origin = Point {
+                x: camera.x,
+                y: camera.y,
+            };
+            queue.submit(RenderLayer::Transition, 0, move |renderer| {
+                transition.draw(renderer, &origin)
+            });
+        }
+        queue.flush(renderer)?;
         Ok(())
     }
+    fn on_resize(&mut self, screen: &Rect) {
+        self.screen = *screen;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Direction {
+    Left,
+    Up,
+    Right,
+    Down,
+}
+impl Direction {
+    pub fn offset(&self) -> Point {
+        match self {
+            Direction::Left => Point { x: -1, y: 0 },
+            Direction::Up => Point { x: 0, y: -1 },
+            Direction::Right => Point { x: 1, y: 0 },
+            Direction::Down => Point { x: 0, y: 1 },
+        }
+    }
+    pub fn action(&self) -> Action {
+        match self {
+            Direction::Left => Action::Left,
+            Direction::Up => Action::Up,
+            Direction::Right => Action::Right,
+            Direction::Down => Action::Down,
+        }
+    }
+    pub fn opposite(&self) -> Self {
+        match self {
+            Direction::Left => Direction::Right,
+            Direction::Up => Direction::Down,
+            Direction::Right => Direction::Left,
+            Direction::Down => Direction::Up,
+        }
+    }
+}
+
+// Entities walk 4 pixels per update, so crossing a tile takes 8 updates.
+fn step(position: &mut Point, direction: Direction) {
+    let offset = direction.offset();
+    position.x += offset.x * 4;
+    position.y += offset.y * 4;
+}
+
+// Sort key for y-sorting: whoever stands lower on the screen is drawn on top.
+fn feet(position: &Point) -> i16 {
+    position.y + TILE_HEIGHT
+}
+
+fn fit(position: &Point) -> bool {
+    position.x % TILE_WIDTH == 0 && position.y % TILE_HEIGHT == 0
+}
+
+fn tile_of(position: &Point) -> Point {
+    Point {
+        x: position.x.div_euclid(TILE_WIDTH),
+        y: position.y.div_euclid(TILE_HEIGHT),
+    }
+}
+
+fn tiles_in(rect: &Rect) -> Vec<Point> {
+    let left = rect.x.div_euclid(TILE_WIDTH);
+    let top = rect.y.div_euclid(TILE_HEIGHT);
+    let right = (rect.x + rect.w - 1).div_euclid(TILE_WIDTH);
+    let bottom = (rect.y + rect.h - 1).div_euclid(TILE_HEIGHT);
+    (top..=bottom)
+        .flat_map(|y| (left..=right).map(move |x| Point { x, y }))
+        .collect()
+}
+
+fn tile_in_front(position: &Point, direction: Direction) -> Point {
+    let tile = tile_of(position);
+    let offset = direction.offset();
+    Point {
+        x: tile.x + offset.x,
+        y: tile.y + offset.y,
+    }
+}
+
+// Facing right reuses the left frames mirrored.
+fn walk_frame(direction: Direction, frame: u8) -> Result<(String, DrawOptions<'static>)> {
+    let frame_name = match direction {
+        Direction::Left | Direction::Right => "left",
+        Direction::Up => "up",
+        Direction::Down => "down",
+    };
+    let frame: u8 = match frame / 4 {
+        0 | 2 => 2,
+        1 => 3,
+        3 => 1,
+        _ => return Err(anyhow!("invalid frame logic")),
+    };
+    Ok((
+        format!("{}0{}.png", frame_name, frame),
+        DrawOptions {
+            flip_x: direction == Direction::Right,
+            ..DrawOptions::default()
+        },
+    ))
+}
+
+// The floor's solid tiles, borrowed, plus the tiles entities stand on.
+pub struct Occupancy<'a> {
+    solid: Option<&'a HashSet<Point>>,
+    tiles: HashSet<Point>,
+    // Width and height in tiles; everything outside is blocked.
+    bounds: Option<Point>,
+}
+impl<'a> Occupancy<'a> {
+    pub fn new(floor: Option<&'a Floor>, tiles: HashSet<Point>) -> Self {
+        Self {
+            solid: floor.map(Floor::solid_tiles),
+            tiles,
+            bounds: floor.map(|floor| Point {
+                x: floor.width,
+                y: floor.height,
+            }),
+        }
+    }
+
+    pub fn is_free(&self, tile: &Point) -> bool {
+        let inside = self.bounds.is_none_or(|bounds| {
+            (0..bounds.x).contains(&tile.x) && (0..bounds.y).contains(&tile.y)
+        });
+        inside
+            && !self.tiles.contains(tile)
+            && !self.solid.is_some_and(|solid| solid.contains(tile))
+    }
+}
+
+// A walking entity covers both the tile it left and the tile it is entering.
+fn occupy(tiles: &mut HashSet<Point>, position: &Point) {
+    let from = tile_of(position);
+    let to = Point {
+        x: (position.x + TILE_WIDTH - 1).div_euclid(TILE_WIDTH),
+        y: (position.y + TILE_HEIGHT - 1).div_euclid(TILE_HEIGHT),
+    };
+    tiles.insert(from);
+    tiles.insert(to);
 }
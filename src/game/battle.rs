@@ -0,0 +1,1205 @@
+use super::flags::Effect;
+use super::item::{self, Bag, ItemEffect, Items, Restored};
+use super::monster::{Ai, Monsters};
+use super::party::PartySettings;
+use super::Direction;
+use crate::engine::{Point, Rng, TransitionSpec};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+const BATTLE_FILE: &str = "battle.json";
+// As many as fit across the screen.
+const MAX_ENEMIES: usize = 5;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Stats {
+    pub max_hp: i32,
+    pub max_mp: i32,
+    pub attack: i32,
+    pub defense: i32,
+    pub agility: i32,
+}
+
+// Anyone taking part in a battle. The party's fighters are kept between
+// battles, hurt or not.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Fighter {
+    pub name: String,
+    pub sprite_source: String,
+    pub level: u16,
+    pub stats: Stats,
+    pub hp: i32,
+    pub mp: i32,
+    #[serde(default)]
+    pub spells: Vec<String>,
+}
+impl Fighter {
+    pub fn is_alive(&self) -> bool {
+        self.hp > 0
+    }
+
+    pub fn restore(&mut self) {
+        self.hp = self.stats.max_hp;
+        self.mp = self.stats.max_mp;
+    }
+
+    // Both return how much was actually restored.
+    pub fn heal(&mut self, amount: i32) -> i32 {
+        let amount = amount.min(self.stats.max_hp - self.hp).max(0);
+        self.hp += amount;
+        amount
+    }
+
+    pub fn recover_mp(&mut self, amount: i32) -> i32 {
+        let amount = amount.min(self.stats.max_mp - self.mp).max(0);
+        self.mp += amount;
+        amount
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpellKind {
+    Damage,
+    Heal,
+}
+
+// Damage is aimed at the other side and healing at the caster's own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reach {
+    Single,
+    All,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Spell {
+    pub mp: i32,
+    pub power: i32,
+    pub kind: SpellKind,
+    pub reach: Reach,
+}
+
+// A percentage that grows with how much faster the one trying is than the
+// one resisting.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Chance {
+    pub base: f32,
+    pub per_agility: f32,
+    pub min: f32,
+    pub max: f32,
+}
+impl Chance {
+    pub fn percent(&self, agility: i32, against: i32) -> f32 {
+        (self.base + (agility - against) as f32 * self.per_agility).clamp(self.min, self.max)
+    }
+}
+
+// `attack * attack - defense * defense`, scaled by a random `variance`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DamageFormula {
+    pub attack: f32,
+    pub defense: f32,
+    pub variance: (f32, f32),
+    pub min: i32,
+}
+
+// Critical hits ignore defense.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Critical {
+    pub chance: Chance,
+    pub multiplier: f32,
+}
+
+// Each failed attempt makes the next one likelier.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Flee {
+    pub chance: Chance,
+    pub per_attempt: f32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Formulas {
+    pub damage: DamageFormula,
+    pub hit: Chance,
+    pub critical: Critical,
+    pub flee: Flee,
+    // Up to this fraction of a fighter's agility is added at random when
+    // deciding who goes first.
+    pub turn_spread: f32,
+}
+impl Formulas {
+    pub fn roll(rng: &mut Rng, percent: f32) -> bool {
+        rng.range_f32(0.0, 100.0) < percent
+    }
+
+    pub fn damage(&self, rng: &mut Rng, attack: i32, defense: i32, critical: bool) -> i32 {
+        let base = if critical {
+            attack as f32 * self.damage.attack * self.critical.multiplier
+        } else {
+            attack as f32 * self.damage.attack - defense as f32 * self.damage.defense
+        };
+        self.vary(rng, base).max(self.damage.min)
+    }
+
+    // Spells hit for their power, whatever the target's defense.
+    pub fn spell(&self, rng: &mut Rng, power: i32) -> i32 {
+        self.vary(rng, power as f32).max(1)
+    }
+
+    fn vary(&self, rng: &mut Rng, amount: f32) -> i32 {
+        let (min, max) = self.damage.variance;
+        (amount * rng.range_f32(min, max)).round() as i32
+    }
+
+    pub fn initiative(&self, rng: &mut Rng, agility: i32) -> f32 {
+        agility as f32 + rng.range_f32(0.0, agility.max(1) as f32 * self.turn_spread)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Drop {
+    pub item: String,
+    // In percent.
+    pub chance: f32,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    Start,
+    // Before anyone acts in that round, counting from 1.
+    Turn(u32),
+    // The enemy at that index drops below that percentage of its HP.
+    HpBelow(usize, i32),
+    Victory,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptAction {
+    Say(String),
+    // The enemy at that index casts the spell on its next turn.
+    Cast(usize, String),
+    Summon(String),
+    Flag(Effect),
+}
+
+// Each event fires at most once per battle.
+#[derive(Clone, Deserialize)]
+pub struct ScriptEvent {
+    pub when: Trigger,
+    pub actions: Vec<ScriptAction>,
+}
+
+#[derive(Clone, Deserialize)]
+pub struct Formation {
+    pub enemies: Vec<String>,
+    #[serde(default)]
+    pub no_escape: bool,
+    // Replaces the usual transition into the battle.
+    pub transition: Option<TransitionSpec>,
+    #[serde(default)]
+    pub script: Vec<ScriptEvent>,
+}
+
+// Where the party wakes up after being wiped out, and the share of its gold
+// it keeps, in percent.
+#[derive(Clone, Deserialize)]
+pub struct Defeat {
+    pub map: String,
+    pub tile: Point,
+    pub facing: Direction,
+    pub gold_kept: i32,
+}
+
+#[derive(Deserialize)]
+pub struct BattleSettings {
+    pub transition: TransitionSpec,
+    pub formulas: Formulas,
+    pub spells: HashMap<String, Spell>,
+    // Loaded from items.json, monsters.json and party.json.
+    #[serde(skip)]
+    pub items: Rc<Items>,
+    #[serde(skip)]
+    pub monsters: Rc<Monsters>,
+    #[serde(skip)]
+    pub party: Rc<PartySettings>,
+    pub formations: HashMap<String, Formation>,
+    pub defeat: Defeat,
+}
+impl BattleSettings {
+    pub fn load() -> Result<Self> {
+        let json = include_bytes!("../asset/battle.json");
+        let mut settings: BattleSettings =
+            serde_json::from_slice(json).map_err(|error| anyhow!("{}: {}", BATTLE_FILE, error))?;
+        let items = Items::load()?;
+        settings.monsters = Rc::new(Monsters::load(&settings.spells, &items)?);
+        settings.party = Rc::new(PartySettings::load(&settings.spells, &items)?);
+        settings.items = Rc::new(items);
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    // A formation is as strong as its strongest enemy.
+    pub fn formation_level(&self, name: &str) -> u16 {
+        self.formations
+            .get(name)
+            .iter()
+            .flat_map(|formation| &formation.enemies)
+            .filter_map(|enemy| self.monsters.get(enemy))
+            .map(|enemy| enemy.level)
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let error = |field: String, message: String| {
+            Err(anyhow!("{}: {}: {}", BATTLE_FILE, field, message))
+        };
+        for (name, formation) in &self.formations {
+            let field = |field: &str| format!("formations.{}.{}", name, field);
+            let count = formation.enemies.len();
+            if count == 0 || count > MAX_ENEMIES {
+                return error(
+                    field("enemies"),
+                    format!("must have 1 to {} enemies, not {}", MAX_ENEMIES, count),
+                );
+            }
+            for (index, enemy) in formation.enemies.iter().enumerate() {
+                if !self.monsters.contains(enemy) {
+                    return error(
+                        field(&format!("enemies[{}]", index)),
+                        format!("unknown monster {}", enemy),
+                    );
+                }
+            }
+            for (event_index, event) in formation.script.iter().enumerate() {
+                if let Trigger::HpBelow(index, _) = event.when {
+                    if index >= count {
+                        return error(
+                            field(&format!("script[{}].when", event_index)),
+                            format!("no enemy {}", index),
+                        );
+                    }
+                }
+                for (action_index, action) in event.actions.iter().enumerate() {
+                    let field = || {
+                        field(&format!(
+                            "script[{}].actions[{}]",
+                            event_index, action_index
+                        ))
+                    };
+                    match action {
+                        ScriptAction::Cast(_, spell) if !self.spells.contains_key(spell) => {
+                            return error(field(), format!("unknown spell {}", spell))
+                        }
+                        ScriptAction::Summon(enemy) if !self.monsters.contains(enemy) => {
+                            return error(field(), format!("unknown monster {}", enemy))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Side {
+    Party,
+    Enemies,
+}
+impl Side {
+    fn other(&self) -> Self {
+        match self {
+            Side::Party => Side::Enemies,
+            Side::Enemies => Side::Party,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Actor {
+    pub side: Side,
+    pub index: usize,
+}
+
+#[derive(Clone, Debug)]
+pub enum Command {
+    Fight(Actor),
+    // No target for spells that reach everyone.
+    Spell(String, Option<Actor>),
+    // No target for items that affect no one in particular.
+    Item(String, Option<Actor>),
+    Run,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Won,
+    Fled,
+    Lost,
+}
+
+#[derive(Clone, Debug)]
+pub struct Rewards {
+    pub exp: i32,
+    pub gold: i32,
+    pub drops: Vec<String>,
+}
+
+// What happened, in order, for the scene to show.
+#[derive(Clone)]
+pub enum BattleEvent {
+    Appeared(usize),
+    Attacked(Actor),
+    Cast(Actor, String),
+    NotEnoughMp,
+    UsedItem(Actor, String),
+    Missed(Actor),
+    Damaged {
+        target: Actor,
+        amount: i32,
+        critical: bool,
+    },
+    Healed {
+        target: Actor,
+        amount: i32,
+    },
+    RecoveredMp {
+        target: Actor,
+        amount: i32,
+    },
+    Defeated(Actor),
+    Said(String),
+    Summoned(usize),
+    Fled,
+    FleeFailed,
+    NoEscape,
+    Flag(Effect),
+    Won(Rewards),
+    Lost,
+}
+
+pub struct Enemy {
+    pub kind: String,
+    pub fighter: Fighter,
+}
+
+// The rules of a fight, without any drawing, so that a battle can be played
+// out with nothing but a generator. `round` takes the party's commands and
+// returns what happened, one list of events per action.
+pub struct Battle {
+    settings: Rc<BattleSettings>,
+    formation: String,
+    pub party: Vec<Fighter>,
+    // What each member carries, used up as items are.
+    pub bags: Vec<Bag>,
+    pub enemies: Vec<Enemy>,
+    round: u32,
+    flee_attempts: u32,
+    fired: HashSet<usize>,
+    // Spells the script told enemies to cast, by enemy index.
+    forced: HashMap<usize, String>,
+    outcome: Option<Outcome>,
+}
+impl Battle {
+    pub fn new(
+        settings: Rc<BattleSettings>,
+        formation: &str,
+        party: Vec<Fighter>,
+        bags: Vec<Bag>,
+    ) -> Result<Self> {
+        let kinds = settings
+            .formations
+            .get(formation)
+            .ok_or_else(|| anyhow!("no Formation found with name {}", formation))?
+            .enemies
+            .clone();
+        let mut battle = Self {
+            settings,
+            formation: formation.to_string(),
+            party,
+            bags,
+            enemies: Vec::new(),
+            round: 0,
+            flee_attempts: 0,
+            fired: HashSet::new(),
+            forced: HashMap::new(),
+            outcome: None,
+        };
+        for kind in &kinds {
+            let several = kinds.iter().filter(|other| *other == kind).count() > 1;
+            battle.add_enemy(kind, several);
+        }
+        Ok(battle)
+    }
+
+    // Enemies of a kind that appears more than once are told apart by letter.
+    fn add_enemy(&mut self, kind: &str, lettered: bool) -> Option<usize> {
+        let data = self.settings.monsters.get(kind)?;
+        let same = self
+            .enemies
+            .iter()
+            .filter(|enemy| enemy.kind == kind)
+            .count();
+        let name = if lettered || same > 0 {
+            format!("{} {}", kind, (b'A' + same as u8) as char)
+        } else {
+            kind.to_string()
+        };
+        self.enemies.push(Enemy {
+            kind: kind.to_string(),
+            fighter: Fighter {
+                name,
+                sprite_source: data.sprite_source.clone(),
+                level: data.level,
+                stats: data.stats.clone(),
+                hp: data.stats.max_hp,
+                mp: data.stats.max_mp,
+                spells: data.spells.clone(),
+            },
+        });
+        Some(self.enemies.len() - 1)
+    }
+
+    pub fn settings(&self) -> &Rc<BattleSettings> {
+        &self.settings
+    }
+
+    pub fn formation(&self) -> &Formation {
+        &self.settings.formations[&self.formation]
+    }
+
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.outcome
+    }
+
+    pub fn spell(&self, name: &str) -> Option<&Spell> {
+        self.settings.spells.get(name)
+    }
+
+    pub fn fighter(&self, actor: Actor) -> &Fighter {
+        match actor.side {
+            Side::Party => &self.party[actor.index],
+            Side::Enemies => &self.enemies[actor.index].fighter,
+        }
+    }
+
+    fn fighter_mut(&mut self, actor: Actor) -> &mut Fighter {
+        match actor.side {
+            Side::Party => &mut self.party[actor.index],
+            Side::Enemies => &mut self.enemies[actor.index].fighter,
+        }
+    }
+
+    pub fn living(&self, side: Side) -> Vec<Actor> {
+        let count = match side {
+            Side::Party => self.party.len(),
+            Side::Enemies => self.enemies.len(),
+        };
+        (0..count)
+            .map(|index| Actor { side, index })
+            .filter(|actor| self.fighter(*actor).is_alive())
+            .collect()
+    }
+
+    pub fn start(&mut self) -> Vec<Vec<BattleEvent>> {
+        let mut turns = vec![(0..self.enemies.len()).map(BattleEvent::Appeared).collect()];
+        self.trigger(|trigger, _| matches!(trigger, Trigger::Start), &mut turns);
+        turns
+    }
+
+    // `commands` are by party member; members without one do nothing.
+    pub fn round(
+        &mut self,
+        commands: &HashMap<usize, Command>,
+        rng: &mut Rng,
+    ) -> Vec<Vec<BattleEvent>> {
+        let mut turns = Vec::new();
+        if self.outcome.is_some() {
+            return turns;
+        }
+        self.round += 1;
+        let round = self.round;
+        self.trigger(
+            |trigger, _| matches!(trigger, Trigger::Turn(turn) if *turn == round),
+            &mut turns,
+        );
+
+        let mut order: Vec<(f32, Actor)> = self
+            .living(Side::Party)
+            .into_iter()
+            .chain(self.living(Side::Enemies))
+            .map(|actor| {
+                let agility = self.fighter(actor).stats.agility;
+                (self.settings.formulas.initiative(rng, agility), actor)
+            })
+            .collect();
+        order.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        // The whole party runs together, so only the first try counts.
+        let mut tried_to_flee = false;
+        for (_, actor) in order {
+            if self.outcome.is_some() {
+                break;
+            }
+            if !self.fighter(actor).is_alive() {
+                continue;
+            }
+            let command = match actor.side {
+                Side::Party => commands.get(&actor.index).cloned(),
+                Side::Enemies => self.choose(actor.index, rng),
+            };
+            let Some(command) = command else {
+                continue;
+            };
+            if let Command::Run = command {
+                if tried_to_flee {
+                    continue;
+                }
+                tried_to_flee = true;
+            }
+            let events = self.act(actor, command, rng);
+            turns.push(events);
+            self.trigger(
+                |trigger, battle| match trigger {
+                    Trigger::HpBelow(index, percent) => {
+                        let fighter = &battle.enemies[*index].fighter;
+                        fighter.is_alive() && fighter.hp * 100 < fighter.stats.max_hp * percent
+                    }
+                    _ => false,
+                },
+                &mut turns,
+            );
+            self.check_end(rng, &mut turns);
+        }
+        turns
+    }
+
+    // Enemies cast what the script asks for and otherwise follow their AI.
+    fn choose(&mut self, index: usize, rng: &mut Rng) -> Option<Command> {
+        let party = self.living(Side::Party);
+        if let Some(spell) = self.forced.remove(&index) {
+            return Some(Command::Spell(spell, pick(rng, &party)));
+        }
+        let data = self.settings.monsters.get(&self.enemies[index].kind)?;
+        let fighter = &self.enemies[index].fighter;
+        let castable = |kind: SpellKind| -> Vec<String> {
+            fighter
+                .spells
+                .iter()
+                .filter(|spell| {
+                    let spell = &self.settings.spells[*spell];
+                    spell.kind == kind && spell.mp <= fighter.mp
+                })
+                .cloned()
+                .collect()
+        };
+        let worst_off = |side: Side| {
+            self.living(side).into_iter().min_by_key(|actor| {
+                let fighter = self.fighter(*actor);
+                fighter.hp * 100 / fighter.stats.max_hp.max(1)
+            })
+        };
+        if let Ai::Healer { below } = data.ai {
+            let heals = castable(SpellKind::Heal);
+            let target = worst_off(Side::Enemies).filter(|actor| {
+                let fighter = self.fighter(*actor);
+                fighter.hp * 100 < fighter.stats.max_hp * below
+            });
+            if let (Some(target), false) = (target, heals.is_empty()) {
+                let spell = heals[rng.range(0, heals.len() as u16) as usize].clone();
+                return Some(Command::Spell(spell, Some(target)));
+            }
+        }
+        let cast = match data.ai {
+            Ai::Caster => true,
+            _ => Formulas::roll(rng, data.cast_chance),
+        };
+        let damage = castable(SpellKind::Damage);
+        if cast && !damage.is_empty() {
+            let spell = damage[rng.range(0, damage.len() as u16) as usize].clone();
+            return Some(Command::Spell(spell, pick(rng, &party)));
+        }
+        let target = match data.ai {
+            Ai::Bully => party
+                .iter()
+                .copied()
+                .min_by_key(|actor| self.fighter(*actor).hp),
+            _ => pick(rng, &party),
+        };
+        target.map(Command::Fight)
+    }
+
+    // Aims at someone else on the same side once the intended target is down.
+    fn retarget(&self, target: Actor) -> Option<Actor> {
+        if self.fighter(target).is_alive() {
+            return Some(target);
+        }
+        self.living(target.side).first().copied()
+    }
+
+    fn act(&mut self, actor: Actor, command: Command, rng: &mut Rng) -> Vec<BattleEvent> {
+        let settings = self.settings.clone();
+        let formulas = &settings.formulas;
+        let mut events = Vec::new();
+        match command {
+            Command::Fight(target) => {
+                events.push(BattleEvent::Attacked(actor));
+                let Some(target) = self.retarget(target) else {
+                    return events;
+                };
+                let attacker = self.fighter(actor).stats.clone();
+                let defender = self.fighter(target).stats.clone();
+                if !Formulas::roll(
+                    rng,
+                    formulas.hit.percent(attacker.agility, defender.agility),
+                ) {
+                    events.push(BattleEvent::Missed(target));
+                    return events;
+                }
+                let critical = Formulas::roll(
+                    rng,
+                    formulas
+                        .critical
+                        .chance
+                        .percent(attacker.agility, defender.agility),
+                );
+                let amount = formulas.damage(rng, attacker.attack, defender.defense, critical);
+                self.hurt(target, amount, critical, &mut events);
+            }
+            Command::Spell(name, target) => {
+                let Some(spell) = settings.spells.get(&name) else {
+                    return events;
+                };
+                events.push(BattleEvent::Cast(actor, name.clone()));
+                let caster = self.fighter_mut(actor);
+                if caster.mp < spell.mp {
+                    events.push(BattleEvent::NotEnoughMp);
+                    return events;
+                }
+                caster.mp -= spell.mp;
+                let side = match spell.kind {
+                    SpellKind::Damage => actor.side.other(),
+                    SpellKind::Heal => actor.side,
+                };
+                let targets = match (spell.reach, target) {
+                    (Reach::Single, Some(target)) if target.side == side => {
+                        self.retarget(target).into_iter().collect()
+                    }
+                    (Reach::Single, _) => self.living(side).into_iter().take(1).collect(),
+                    (Reach::All, _) => self.living(side),
+                };
+                for target in targets {
+                    let amount = formulas.spell(rng, spell.power);
+                    match spell.kind {
+                        SpellKind::Damage => self.hurt(target, amount, false, &mut events),
+                        SpellKind::Heal => {
+                            let amount = self.fighter_mut(target).heal(amount);
+                            events.push(BattleEvent::Healed { target, amount });
+                        }
+                    }
+                }
+            }
+            Command::Item(name, target) => {
+                let Some(data) = settings.items.get(&name) else {
+                    return events;
+                };
+                let used = actor.side == Side::Party
+                    && self
+                        .bags
+                        .get_mut(actor.index)
+                        .is_some_and(|bag| bag.remove(&name));
+                if !used {
+                    return events;
+                }
+                events.push(BattleEvent::UsedItem(actor, name.clone()));
+                let target = target
+                    .and_then(|target| self.retarget(target))
+                    .unwrap_or(actor);
+                for restored in item::restore(data, self.fighter_mut(target)) {
+                    events.push(match restored {
+                        Restored::Hp(amount) => BattleEvent::Healed { target, amount },
+                        Restored::Mp(amount) => BattleEvent::RecoveredMp { target, amount },
+                    });
+                }
+                for effect in &data.effects {
+                    match effect {
+                        ItemEffect::Damage(amount) if target.side != actor.side => {
+                            self.hurt(target, *amount, false, &mut events)
+                        }
+                        ItemEffect::Flag(effect) => events.push(BattleEvent::Flag(effect.clone())),
+                        _ => {}
+                    }
+                }
+            }
+            Command::Run => {
+                if self.formation().no_escape {
+                    events.push(BattleEvent::NoEscape);
+                    return events;
+                }
+                let fastest = |actors: Vec<Actor>| {
+                    actors
+                        .iter()
+                        .map(|actor| self.fighter(*actor).stats.agility)
+                        .max()
+                        .unwrap_or(0)
+                };
+                let percent = formulas.flee.chance.percent(
+                    fastest(self.living(Side::Party)),
+                    fastest(self.living(Side::Enemies)),
+                ) + self.flee_attempts as f32 * formulas.flee.per_attempt;
+                if Formulas::roll(rng, percent) {
+                    events.push(BattleEvent::Fled);
+                    self.outcome = Some(Outcome::Fled);
+                } else {
+                    events.push(BattleEvent::FleeFailed);
+                    self.flee_attempts += 1;
+                }
+            }
+        }
+        events
+    }
+
+    fn hurt(&mut self, target: Actor, amount: i32, critical: bool, events: &mut Vec<BattleEvent>) {
+        let fighter = self.fighter_mut(target);
+        fighter.hp = (fighter.hp - amount).max(0);
+        events.push(BattleEvent::Damaged {
+            target,
+            amount,
+            critical,
+        });
+        if !fighter.is_alive() {
+            events.push(BattleEvent::Defeated(target));
+        }
+    }
+
+    fn check_end(&mut self, rng: &mut Rng, turns: &mut Vec<Vec<BattleEvent>>) {
+        if self.outcome.is_some() {
+            return;
+        }
+        if self.living(Side::Party).is_empty() {
+            turns.push(vec![BattleEvent::Lost]);
+            self.outcome = Some(Outcome::Lost);
+        } else if self.living(Side::Enemies).is_empty() {
+            self.trigger(|trigger, _| matches!(trigger, Trigger::Victory), turns);
+            let mut rewards = Rewards {
+                exp: 0,
+                gold: 0,
+                drops: Vec::new(),
+            };
+            for enemy in &self.enemies {
+                let Some(data) = self.settings.monsters.get(&enemy.kind) else {
+                    continue;
+                };
+                rewards.exp += data.exp;
+                rewards.gold += data.gold;
+                for drop in &data.drops {
+                    if Formulas::roll(rng, drop.chance) {
+                        rewards.drops.push(drop.item.clone());
+                    }
+                }
+            }
+            turns.push(vec![BattleEvent::Won(rewards)]);
+            self.outcome = Some(Outcome::Won);
+        }
+    }
+
+    // Runs the script events that have not fired yet and whose trigger holds.
+    fn trigger(
+        &mut self,
+        holds: impl Fn(&Trigger, &Battle) -> bool,
+        turns: &mut Vec<Vec<BattleEvent>>,
+    ) {
+        let settings = self.settings.clone();
+        let formation = &settings.formations[&self.formation];
+        for (index, event) in formation.script.iter().enumerate() {
+            if self.fired.contains(&index) || !holds(&event.when, self) {
+                continue;
+            }
+            self.fired.insert(index);
+            let mut events = Vec::new();
+            for action in &event.actions {
+                match action {
+                    ScriptAction::Say(text) => events.push(BattleEvent::Said(text.clone())),
+                    ScriptAction::Cast(enemy, spell) => {
+                        self.forced.insert(*enemy, spell.clone());
+                    }
+                    ScriptAction::Summon(kind) => {
+                        if self.enemies.len() < MAX_ENEMIES {
+                            if let Some(index) = self.add_enemy(kind, false) {
+                                events.push(BattleEvent::Summoned(index));
+                            }
+                        }
+                    }
+                    ScriptAction::Flag(effect) => events.push(BattleEvent::Flag(effect.clone())),
+                }
+            }
+            turns.push(events);
+        }
+    }
+
+    // What to show for an event; `None` for those that only change the game.
+    pub fn text(&self, event: &BattleEvent) -> Option<String> {
+        let name = |actor: &Actor| self.fighter(*actor).name.clone();
+        let enemy = |index: &usize| self.enemies[*index].fighter.name.clone();
+        Some(match event {
+            BattleEvent::Appeared(index) => format!("{} appears!", enemy(index)),
+            BattleEvent::Attacked(actor) => format!("{} attacks!", name(actor)),
+            BattleEvent::Cast(actor, spell) => format!("{} casts {}!", name(actor), spell),
+            BattleEvent::NotEnoughMp => "But there is not enough MP.".to_string(),
+            BattleEvent::UsedItem(actor, item) => format!("{} uses the {}!", name(actor), item),
+            BattleEvent::Missed(target) => format!("{} dodges!", name(target)),
+            BattleEvent::Damaged {
+                target,
+                amount,
+                critical,
+            } => {
+                let hit = format!("{} takes {} damage.", name(target), amount);
+                if *critical {
+                    format!("A critical hit!\n{}", hit)
+                } else {
+                    hit
+                }
+            }
+            BattleEvent::Healed { target, amount } => {
+                format!("{} recovers {} HP.", name(target), amount)
+            }
+            BattleEvent::RecoveredMp { target, amount } => {
+                format!("{} recovers {} MP.", name(target), amount)
+            }
+            BattleEvent::Defeated(target) => match target.side {
+                Side::Party => format!("{} falls!", name(target)),
+                Side::Enemies => format!("{} is defeated!", name(target)),
+            },
+            BattleEvent::Said(text) => text.clone(),
+            BattleEvent::Summoned(index) => format!("{} joins the fight!", enemy(index)),
+            BattleEvent::Fled => "The party gets away.".to_string(),
+            BattleEvent::FleeFailed => "But the way is blocked!".to_string(),
+            BattleEvent::NoEscape => "There is no escape!".to_string(),
+            BattleEvent::Flag(_) => return None,
+            BattleEvent::Won(rewards) => {
+                let mut lines = vec![
+                    "Victory!".to_string(),
+                    format!("Gained {} EXP and {} gold.", rewards.exp, rewards.gold),
+                ];
+                lines.extend(
+                    rewards
+                        .drops
+                        .iter()
+                        .map(|item| format!("Found {}.", self.settings.items.a(item))),
+                );
+                lines.join("\n")
+            }
+            BattleEvent::Lost => "The party has been wiped out...".to_string(),
+        })
+    }
+}
+
+fn pick(rng: &mut Rng, actors: &[Actor]) -> Option<Actor> {
+    actors
+        .get(rng.range(0, actors.len() as u16) as usize)
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROUNDS: usize = 50;
+
+    fn stats(max_hp: i32, attack: i32, defense: i32, agility: i32) -> Stats {
+        Stats {
+            max_hp,
+            max_mp: 0,
+            attack,
+            defense,
+            agility,
+        }
+    }
+
+    fn hero(stats: Stats) -> Fighter {
+        Fighter {
+            name: "Hero".to_string(),
+            sprite_source: "Sprite-0001".to_string(),
+            level: 1,
+            hp: stats.max_hp,
+            mp: stats.max_mp,
+            stats,
+            spells: Vec::new(),
+        }
+    }
+
+    // The shipped formulas, spells and items with the given monsters and
+    // formations in place of the real ones.
+    fn settings(monsters: &str, formations: &str) -> Rc<BattleSettings> {
+        let mut settings = BattleSettings::load().unwrap();
+        settings.monsters = Rc::new(serde_json::from_str(monsters).unwrap());
+        settings.formations = serde_json::from_str(formations).unwrap();
+        Rc::new(settings)
+    }
+
+    fn battle(settings: &Rc<BattleSettings>, formation: &str, party: Vec<Fighter>) -> Battle {
+        let bags = vec![Bag::default(); party.len()];
+        Battle::new(settings.clone(), formation, party, bags).unwrap()
+    }
+
+    // Plays rounds until the battle ends, giving every member `command`
+    // against the first enemy standing.
+    fn play(
+        battle: &mut Battle,
+        rng: &mut Rng,
+        command: impl Fn(Actor) -> Option<Command>,
+    ) -> Vec<BattleEvent> {
+        let mut events: Vec<BattleEvent> = battle.start().into_iter().flatten().collect();
+        for _ in 0..ROUNDS {
+            if battle.outcome().is_some() {
+                break;
+            }
+            let Some(target) = battle.living(Side::Enemies).first().copied() else {
+                break;
+            };
+            let commands = (0..battle.party.len())
+                .filter_map(|index| command(target).map(|command| (index, command)))
+                .collect();
+            events.extend(battle.round(&commands, rng).into_iter().flatten());
+        }
+        events
+    }
+
+    fn texts(battle: &Battle, events: &[BattleEvent]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| battle.text(event))
+            .collect()
+    }
+
+    const DUMMIES: &str = r#"{
+        "Dummy": {
+            "sprite_source": "Sprite-0001-Slime",
+            "level": 1,
+            "stats": { "max_hp": 1, "max_mp": 0, "attack": 0, "defense": 0, "agility": 0 },
+            "exp": 5,
+            "gold": 7,
+            "drops": [
+                { "item": "Medical Herb", "chance": 100.0 },
+                { "item": "Antidote", "chance": 0.0 }
+            ]
+        },
+        "Brute": {
+            "sprite_source": "Sprite-0001-Goblin",
+            "level": 9,
+            "stats": { "max_hp": 100, "max_mp": 0, "attack": 999, "defense": 0, "agility": 50 },
+            "exp": 0,
+            "gold": 0
+        }
+    }"#;
+
+    #[test]
+    fn victory_pays_out_every_enemy() {
+        let settings = settings(
+            DUMMIES,
+            r#"{ "dummies": { "enemies": ["Dummy", "Dummy"] } }"#,
+        );
+        let mut battle = battle(&settings, "dummies", vec![hero(stats(50, 20, 0, 10))]);
+        let mut rng = Rng::new(1, 0);
+        let events = play(&mut battle, &mut rng, |target| Some(Command::Fight(target)));
+        assert_eq!(battle.outcome(), Some(Outcome::Won));
+        let rewards = events
+            .iter()
+            .find_map(|event| match event {
+                BattleEvent::Won(rewards) => Some(rewards.clone()),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(rewards.exp, 10);
+        assert_eq!(rewards.gold, 14);
+        assert_eq!(rewards.drops, ["Medical Herb", "Medical Herb"]);
+    }
+
+    #[test]
+    fn same_seed_plays_out_the_same() {
+        let settings = settings(
+            DUMMIES,
+            r#"{ "dummies": { "enemies": ["Dummy", "Brute"] } }"#,
+        );
+        let run = |seed: u64| {
+            let mut battle = battle(&settings, "dummies", vec![hero(stats(2000, 30, 0, 5))]);
+            let events = play(&mut battle, &mut Rng::new(seed, 0), |target| {
+                Some(Command::Fight(target))
+            });
+            texts(&battle, &events)
+        };
+        assert_eq!(run(9), run(9));
+        assert_ne!(run(9), run(10));
+    }
+
+    #[test]
+    fn party_can_run_away() {
+        let settings = settings(DUMMIES, r#"{ "dummies": { "enemies": ["Dummy"] } }"#);
+        let mut battle = battle(&settings, "dummies", vec![hero(stats(50, 1, 0, 10))]);
+        let mut rng = Rng::new(2, 0);
+        let events = play(&mut battle, &mut rng, |_| Some(Command::Run));
+        assert_eq!(battle.outcome(), Some(Outcome::Fled));
+        assert!(matches!(events.last(), Some(BattleEvent::Fled)));
+        let failed = events
+            .iter()
+            .filter(|event| matches!(event, BattleEvent::FleeFailed))
+            .count();
+        assert_eq!(battle.flee_attempts as usize, failed);
+    }
+
+    #[test]
+    fn no_escape_battles_cannot_be_run_from() {
+        let settings = settings(
+            DUMMIES,
+            r#"{ "boss": { "enemies": ["Dummy"], "no_escape": true } }"#,
+        );
+        let mut battle = battle(&settings, "boss", vec![hero(stats(50, 1, 0, 99))]);
+        let mut rng = Rng::new(3, 0);
+        battle.start();
+        for _ in 0..10 {
+            let commands = HashMap::from([(0, Command::Run)]);
+            let events: Vec<BattleEvent> = battle.round(&commands, &mut rng).concat();
+            assert!(events
+                .iter()
+                .any(|event| matches!(event, BattleEvent::NoEscape)));
+            assert!(!events
+                .iter()
+                .any(|event| matches!(event, BattleEvent::Fled)));
+        }
+        assert_eq!(battle.outcome(), None);
+    }
+
+    #[test]
+    fn hp_below_fires_once_when_crossed() {
+        let monsters = r#"{
+            "Sack": {
+                "sprite_source": "Sprite-0001-Slime",
+                "level": 1,
+                "stats": { "max_hp": 100, "max_mp": 0, "attack": 0, "defense": 0, "agility": 0 },
+                "exp": 1,
+                "gold": 1
+            }
+        }"#;
+        let formations = r#"{
+            "sack": {
+                "enemies": ["Sack"],
+                "script": [
+                    { "when": { "hp_below": [0, 50] }, "actions": [{ "say": "Ouch!" }] }
+                ]
+            }
+        }"#;
+        let settings = settings(monsters, formations);
+        let mut battle = battle(&settings, "sack", vec![hero(stats(500, 20, 0, 10))]);
+        let mut rng = Rng::new(4, 0);
+        battle.start();
+        let mut said_at = None;
+        let mut rounds = 0;
+        while battle.outcome().is_none() && rounds < ROUNDS {
+            rounds += 1;
+            let before = battle.enemies[0].fighter.hp;
+            let commands = HashMap::from([(
+                0,
+                Command::Fight(Actor {
+                    side: Side::Enemies,
+                    index: 0,
+                }),
+            )]);
+            let events = battle.round(&commands, &mut rng).concat();
+            let said = events
+                .iter()
+                .filter(|event| matches!(event, BattleEvent::Said(text) if text == "Ouch!"))
+                .count();
+            if said > 0 {
+                assert_eq!(said, 1);
+                assert!(said_at.is_none(), "the event fired twice");
+                assert!(before >= 50);
+                assert!(battle.enemies[0].fighter.hp < 50 || !battle.enemies[0].fighter.is_alive());
+                said_at = Some(rounds);
+            } else if said_at.is_none() {
+                assert!(
+                    battle.enemies[0].fighter.hp >= 50 || !battle.enemies[0].fighter.is_alive()
+                );
+            }
+        }
+        assert_eq!(battle.outcome(), Some(Outcome::Won));
+        assert!(said_at.is_some());
+    }
+
+    #[test]
+    fn healer_heals_the_ally_worst_off() {
+        let monsters = r#"{
+            "Medic": {
+                "sprite_source": "Sprite-0001-Ghost",
+                "level": 1,
+                "stats": { "max_hp": 20, "max_mp": 30, "attack": 0, "defense": 0, "agility": 99 },
+                "spells": ["Heal"],
+                "ai": { "type": "healer", "below": 50 },
+                "exp": 1,
+                "gold": 1
+            },
+            "Patient": {
+                "sprite_source": "Sprite-0001-Slime",
+                "level": 1,
+                "stats": { "max_hp": 100, "max_mp": 0, "attack": 0, "defense": 0, "agility": 0 },
+                "exp": 1,
+                "gold": 1
+            }
+        }"#;
+        let formations = r#"{ "ward": { "enemies": ["Medic", "Patient", "Patient"] } }"#;
+        let settings = settings(monsters, formations);
+        let mut battle = battle(&settings, "ward", vec![hero(stats(500, 1, 0, 1))]);
+        battle.enemies[1].fighter.hp = 40;
+        battle.enemies[2].fighter.hp = 10;
+        let mut rng = Rng::new(5, 0);
+        battle.start();
+        let events = battle.round(&HashMap::new(), &mut rng).concat();
+        let medic = Actor {
+            side: Side::Enemies,
+            index: 0,
+        };
+        let worst = Actor {
+            side: Side::Enemies,
+            index: 2,
+        };
+        assert!(
+            matches!(&events[0], BattleEvent::Cast(actor, spell) if *actor == medic && spell == "Heal")
+        );
+        assert!(
+            matches!(&events[1], BattleEvent::Healed { target, amount } if *target == worst && *amount > 0)
+        );
+        assert_eq!(battle.enemies[1].fighter.hp, 40);
+        assert_eq!(battle.enemies[0].fighter.mp, 27);
+
+        // Nobody is below the threshold any more, so the medic fights instead.
+        battle.enemies[1].fighter.hp = 100;
+        battle.enemies[2].fighter.hp = 100;
+        let events = battle.round(&HashMap::new(), &mut rng).concat();
+        assert!(!events
+            .iter()
+            .any(|event| matches!(event, BattleEvent::Cast(..))));
+    }
+
+    #[test]
+    fn party_wiped_out_loses() {
+        let settings = settings(DUMMIES, r#"{ "brute": { "enemies": ["Brute"] } }"#);
+        let mut battle = battle(
+            &settings,
+            "brute",
+            vec![hero(stats(10, 1, 0, 1)), hero(stats(10, 1, 0, 1))],
+        );
+        let mut rng = Rng::new(6, 0);
+        let events = play(&mut battle, &mut rng, |target| Some(Command::Fight(target)));
+        assert_eq!(battle.outcome(), Some(Outcome::Lost));
+        assert!(matches!(events.last(), Some(BattleEvent::Lost)));
+        assert!(battle.party.iter().all(|fighter| !fighter.is_alive()));
+        assert!(battle.round(&HashMap::new(), &mut rng).is_empty());
+    }
+}
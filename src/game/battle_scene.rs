@@ -0,0 +1,692 @@
+use super::battle::{
+    Actor, Battle, BattleEvent, BattleSettings, Command, Fighter, Reach, Side, SpellKind,
+};
+use super::effects::Effects;
+use super::interaction::{InteractionHandler, InteractionStatus};
+use super::item::ItemTarget;
+use super::message_window::MessageWindow;
+use super::party::{LevelUp, PartySettings};
+use super::state::GameState;
+use crate::engine::{
+    draw_window, DrawOptions, Emitter, KeyState, ListItem, Point, Rect, RenderQueue, Renderer,
+    SpriteSheetStore, Stream, TextStyle, Ui, UiEvent, WidgetId, LINE_HEIGHT, PADDING,
+};
+
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+const FIGHT: usize = 0;
+const SPELL: usize = 1;
+const ITEM: usize = 2;
+const RUN: usize = 3;
+const BACKGROUND_COLOR: &str = "#000000";
+const STAGE_COLOR: &str = "#222034";
+const STAGE_TOP: i16 = 128;
+const STAGE_HEIGHT: i16 = 160;
+// Enemies are field sprites drawn at twice the size.
+const ENEMY_FRAME: &str = "down02.png";
+const ENEMY_SIZE: i16 = 32;
+const ENEMY_SCALE: f64 = 2.0;
+const SPELL_PARTICLES: usize = 24;
+// Updates a hit enemy blinks for, and the screen shakes for when the party
+// is hit.
+const FLASH: u32 = 16;
+const SHAKE: u32 = 12;
+const SHAKE_OFFSET: i16 = 4;
+// Lines per message page; longer turns are split.
+const PAGE_LINES: usize = 4;
+const MENU_WIDTH: i16 = 144;
+const MENU_HEIGHT: i16 = LINE_HEIGHT * 4 + PADDING * 2;
+const OPTIONS_WIDTH: i16 = 296;
+const STATUS_HEIGHT: i16 = LINE_HEIGHT * 3 + PADDING * 2;
+const PARTY_SLOTS: i16 = 4;
+const MARGIN: i16 = 16;
+const TEXT_COLOR: &str = "#ffffff";
+const ACTIVE_COLOR: &str = "#ffd700";
+const FALLEN_COLOR: &str = "#ac3232";
+
+struct Page {
+    text: String,
+    events: Vec<BattleEvent>,
+}
+
+// What a target is being picked for.
+enum Aim {
+    Attack,
+    Spell(String),
+    Item(String),
+}
+
+// What the list next to the commands is offering.
+enum Picking {
+    Spell(Vec<String>),
+    Item(Vec<String>),
+    Target(Aim, Vec<Actor>),
+}
+
+// Plays a `Battle` on screen: the party picks commands one member at a time,
+// then the round is read out page by page.
+pub struct BattleScene {
+    battle: Battle,
+    party: Rc<PartySettings>,
+    effects: Option<Effects>,
+    screen: Rect,
+    ui: Ui,
+    commands: WidgetId,
+    options_window: WidgetId,
+    options: WidgetId,
+    picking: Option<Picking>,
+    // Who is choosing, and what has been chosen so far this round.
+    member: usize,
+    chosen: HashMap<usize, Command>,
+    pages: VecDeque<Page>,
+    window: Option<MessageWindow>,
+    // The screen lags behind the battle until the messages about it are read.
+    shown_hp: Vec<i32>,
+    shown_enemies: Vec<bool>,
+    flash: Option<(usize, u32)>,
+    shake: u32,
+    particles: Vec<Emitter>,
+}
+impl BattleScene {
+    pub fn new(
+        settings: Rc<BattleSettings>,
+        formation: &str,
+        state: &mut GameState,
+        effects: Option<Effects>,
+        screen: Rect,
+    ) -> Result<Self> {
+        let party = settings.party.clone();
+        let fighters = state
+            .party
+            .iter()
+            .map(|member| Fighter {
+                stats: member.stats(&settings.items),
+                ..member.fighter.clone()
+            })
+            .collect();
+        let bags = state
+            .party
+            .iter()
+            .map(|member| member.bag.clone())
+            .collect();
+        let mut battle = Battle::new(settings, formation, fighters, bags)?;
+        let turns = battle.start();
+
+        let mut ui = Ui::new();
+        let commands_window = ui.window(
+            None,
+            Rect {
+                x: MARGIN,
+                y: 0,
+                w: MENU_WIDTH,
+                h: MENU_HEIGHT,
+            },
+        );
+        let commands = ui.list(
+            Some(commands_window),
+            Rect {
+                x: PADDING,
+                y: PADDING,
+                w: MENU_WIDTH - PADDING * 2,
+                h: LINE_HEIGHT * 4,
+            },
+            Vec::new(),
+            1,
+        );
+        let options_window = ui.window(
+            None,
+            Rect {
+                x: MARGIN + MENU_WIDTH + 8,
+                y: 0,
+                w: OPTIONS_WIDTH,
+                h: MENU_HEIGHT,
+            },
+        );
+        let options = ui.list(
+            Some(options_window),
+            Rect {
+                x: PADDING,
+                y: PADDING,
+                w: OPTIONS_WIDTH - PADDING * 2,
+                h: LINE_HEIGHT * 4,
+            },
+            Vec::new(),
+            1,
+        );
+        ui.set_visible(options_window, false);
+
+        let mut scene = Self {
+            shown_hp: battle.party.iter().map(|fighter| fighter.hp).collect(),
+            shown_enemies: vec![false; battle.enemies.len()],
+            battle,
+            party,
+            effects,
+            screen,
+            ui,
+            commands,
+            options_window,
+            options,
+            picking: None,
+            member: 0,
+            chosen: HashMap::new(),
+            pages: VecDeque::new(),
+            window: None,
+            flash: None,
+            shake: 0,
+            particles: Vec::new(),
+        };
+        scene.play(turns, state);
+        scene.next_page(state)?;
+        Ok(scene)
+    }
+
+    // The game state changes straight away; the pages only catch the screen up.
+    fn play(&mut self, turns: Vec<Vec<BattleEvent>>, state: &mut GameState) {
+        for events in turns {
+            let mut notes = Vec::new();
+            for event in &events {
+                match event {
+                    BattleEvent::Appeared(index) | BattleEvent::Summoned(index) => {
+                        state.bestiary.see(&self.battle.enemies[*index].kind)
+                    }
+                    BattleEvent::Defeated(Actor {
+                        side: Side::Enemies,
+                        index,
+                    }) => state.bestiary.defeat(&self.battle.enemies[*index].kind),
+                    BattleEvent::Flag(effect) => state.flags.apply(effect),
+                    // Everyone still standing gets the full EXP.
+                    BattleEvent::Won(rewards) => {
+                        state.gold += rewards.gold;
+                        self.keep_party(state);
+                        for member in &mut state.party {
+                            if member.fighter.is_alive() {
+                                notes.extend(
+                                    self.party
+                                        .gain_exp(
+                                            member,
+                                            rewards.exp,
+                                            state.random.stream(Stream::Combat),
+                                        )
+                                        .iter()
+                                        .map(LevelUp::text),
+                                );
+                            }
+                        }
+                        let items = &self.battle.settings().items;
+                        for item in &rewards.drops {
+                            if items.stow(&mut state.party, item).is_none() {
+                                notes.push(format!("Nobody has room for the {}.", item));
+                            }
+                        }
+                        for (index, member) in state.party.iter().enumerate() {
+                            let fighter = &mut self.battle.party[index];
+                            fighter.hp = member.fighter.hp;
+                            fighter.mp = member.fighter.mp;
+                            self.battle.bags[index] = member.bag.clone();
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            let text: Vec<String> = events
+                .iter()
+                .filter_map(|event| self.battle.text(event))
+                .collect();
+            let lines: Vec<&str> = text.iter().flat_map(|text| text.lines()).collect();
+            let mut events = Some(events);
+            for chunk in lines.chunks(PAGE_LINES) {
+                self.pages.push_back(Page {
+                    text: chunk.join("\n"),
+                    events: events.take().unwrap_or_default(),
+                });
+            }
+            for text in notes {
+                self.pages.push_back(Page {
+                    text,
+                    events: Vec::new(),
+                });
+            }
+        }
+    }
+
+    // Hands the party's HP, MP and items back to the game state; the stats
+    // in battle include equipment, so they stay behind.
+    fn keep_party(&self, state: &mut GameState) {
+        for (index, member) in state.party.iter_mut().enumerate() {
+            let fighter = &self.battle.party[index];
+            member.fighter.hp = fighter.hp;
+            member.fighter.mp = fighter.mp;
+            member.bag = self.battle.bags[index].clone();
+        }
+    }
+
+    // The items in a member's bag that work in battle, once each.
+    fn battle_items(&self, member: usize) -> Vec<String> {
+        let items = &self.battle.settings().items;
+        let mut usable: Vec<String> = Vec::new();
+        for stack in &self.battle.bags[member].stacks {
+            let works = items
+                .get(&stack.item)
+                .is_some_and(|data| data.usable_in_battle());
+            if works && !usable.contains(&stack.item) {
+                usable.push(stack.item.clone());
+            }
+        }
+        usable
+    }
+
+    fn next_page(&mut self, state: &mut GameState) -> Result<()> {
+        let Some(page) = self.pages.pop_front() else {
+            self.window = None;
+            if self.battle.outcome().is_none() {
+                self.begin_round()?;
+            }
+            return Ok(());
+        };
+        let mut casting = false;
+        for event in &page.events {
+            match event {
+                BattleEvent::Appeared(index) | BattleEvent::Summoned(index) => {
+                    if self.shown_enemies.len() <= *index {
+                        self.shown_enemies.resize(index + 1, false);
+                    }
+                    self.shown_enemies[*index] = true;
+                }
+                BattleEvent::Cast(..) | BattleEvent::UsedItem(..) => casting = true,
+                BattleEvent::Damaged { target, amount, .. } => {
+                    match target.side {
+                        Side::Party => {
+                            self.shake = SHAKE;
+                            let hp = &mut self.shown_hp[target.index];
+                            *hp = (*hp - amount).max(0);
+                        }
+                        Side::Enemies => self.flash = Some((target.index, FLASH)),
+                    }
+                    if casting {
+                        self.sparkle(*target, state)?;
+                    }
+                }
+                BattleEvent::Healed { target, amount } => {
+                    if target.side == Side::Party {
+                        self.shown_hp[target.index] += amount;
+                    }
+                    if casting {
+                        self.sparkle(*target, state)?;
+                    }
+                }
+                BattleEvent::Defeated(Actor {
+                    side: Side::Enemies,
+                    index,
+                }) => self.shown_enemies[*index] = false,
+                _ => {}
+            }
+        }
+        self.window = Some(MessageWindow::new(None, &page.text));
+        Ok(())
+    }
+
+    fn sparkle(&mut self, target: Actor, state: &mut GameState) -> Result<()> {
+        if let Some(effects) = &self.effects {
+            self.particles.push(effects.burst(
+                "spell",
+                self.center(target),
+                SPELL_PARTICLES,
+                state.random.fork(Stream::Cosmetic),
+            )?);
+        }
+        Ok(())
+    }
+
+    fn begin_round(&mut self) -> Result<()> {
+        self.chosen.clear();
+        match self.battle.living(Side::Party).first() {
+            Some(actor) => self.choose_for(actor.index),
+            None => Ok(()),
+        }
+    }
+
+    fn choose_for(&mut self, member: usize) -> Result<()> {
+        self.member = member;
+        let can_cast = !self.battle.party[member].spells.is_empty();
+        let items = vec![
+            ListItem::new("Fight"),
+            if can_cast {
+                ListItem::new("Spell")
+            } else {
+                ListItem::disabled("Spell")
+            },
+            if self.battle_items(member).is_empty() {
+                ListItem::disabled("Item")
+            } else {
+                ListItem::new("Item")
+            },
+            ListItem::new("Run"),
+        ];
+        self.ui.set_items(self.commands, items)?;
+        while self.ui.blur().is_some() {}
+        self.ui.focus(self.commands);
+        Ok(())
+    }
+
+    fn open_options(&mut self, picking: Picking) -> Result<()> {
+        let items = match &picking {
+            Picking::Spell(spells) => {
+                let mp = self.battle.party[self.member].mp;
+                spells
+                    .iter()
+                    .map(|name| {
+                        let cost = self.battle.spell(name).map_or(0, |spell| spell.mp);
+                        let text = format!("{} ({} MP)", name, cost);
+                        if cost <= mp {
+                            ListItem::new(&text)
+                        } else {
+                            ListItem::disabled(&text)
+                        }
+                    })
+                    .collect()
+            }
+            Picking::Item(items) => items
+                .iter()
+                .map(|item| {
+                    let count = self.battle.bags[self.member].count(item);
+                    ListItem::new(&format!("{} x{}", item, count))
+                })
+                .collect(),
+            Picking::Target(_, targets) => targets
+                .iter()
+                .map(|target| ListItem::new(&self.battle.fighter(*target).name))
+                .collect(),
+        };
+        self.ui.set_items(self.options, items)?;
+        self.ui.set_visible(self.options_window, true);
+        self.ui.focus(self.options);
+        self.picking = Some(picking);
+        Ok(())
+    }
+
+    fn close_options(&mut self) {
+        self.picking = None;
+        self.ui.set_visible(self.options_window, false);
+        self.ui.blur();
+    }
+
+    // Moves on to the next member who can act, or plays the round once
+    // everyone has chosen.
+    fn choose(&mut self, command: Command, state: &mut GameState) -> Result<()> {
+        if self.ui.is_visible(self.options_window) {
+            self.close_options();
+        }
+        self.chosen.insert(self.member, command);
+        let next = self
+            .battle
+            .living(Side::Party)
+            .into_iter()
+            .find(|actor| actor.index > self.member);
+        match next {
+            Some(actor) => self.choose_for(actor.index),
+            None => self.play_round(state),
+        }
+    }
+
+    fn play_round(&mut self, state: &mut GameState) -> Result<()> {
+        let chosen = std::mem::take(&mut self.chosen);
+        let turns = self
+            .battle
+            .round(&chosen, state.random.stream(Stream::Combat));
+        self.play(turns, state);
+        self.next_page(state)
+    }
+
+    fn update_commands(&mut self, key_state: &KeyState, state: &mut GameState) -> Result<()> {
+        match self.ui.update(key_state) {
+            Some(UiEvent::Selected(id, FIGHT)) if id == self.commands => {
+                let targets = self.battle.living(Side::Enemies);
+                self.open_options(Picking::Target(Aim::Attack, targets))?;
+            }
+            Some(UiEvent::Selected(id, SPELL)) if id == self.commands => {
+                let spells = self.battle.party[self.member].spells.clone();
+                self.open_options(Picking::Spell(spells))?;
+            }
+            Some(UiEvent::Selected(id, ITEM)) if id == self.commands => {
+                let items = self.battle_items(self.member);
+                self.open_options(Picking::Item(items))?;
+            }
+            // The whole party runs at once.
+            Some(UiEvent::Selected(id, RUN)) if id == self.commands => {
+                self.chosen.clear();
+                self.chosen.insert(self.member, Command::Run);
+                self.play_round(state)?;
+            }
+            // Back to the previous member's choice.
+            Some(UiEvent::Cancelled(id)) if id == self.commands => {
+                let previous = self
+                    .battle
+                    .living(Side::Party)
+                    .into_iter()
+                    .rev()
+                    .find(|actor| actor.index < self.member);
+                if let Some(actor) = previous {
+                    self.chosen.remove(&actor.index);
+                    self.choose_for(actor.index)?;
+                }
+            }
+            Some(UiEvent::Selected(id, index)) if id == self.options => match self.picking.take() {
+                Some(Picking::Spell(spells)) => {
+                    let name = spells[index].clone();
+                    let Some(spell) = self.battle.spell(&name) else {
+                        return Ok(());
+                    };
+                    let side = match spell.kind {
+                        SpellKind::Damage => Side::Enemies,
+                        SpellKind::Heal => Side::Party,
+                    };
+                    match spell.reach {
+                        Reach::All => self.choose(Command::Spell(name, None), state)?,
+                        Reach::Single => {
+                            self.close_options();
+                            let targets = self.battle.living(side);
+                            self.open_options(Picking::Target(Aim::Spell(name), targets))?;
+                        }
+                    }
+                }
+                Some(Picking::Item(items)) => {
+                    let name = items[index].clone();
+                    let Some(data) = self.battle.settings().items.get(&name) else {
+                        return Ok(());
+                    };
+                    let side = match data.target {
+                        ItemTarget::Nobody => None,
+                        ItemTarget::Ally => Some(Side::Party),
+                        ItemTarget::Enemy => Some(Side::Enemies),
+                    };
+                    match side {
+                        Some(side) => {
+                            self.close_options();
+                            let targets = self.battle.living(side);
+                            self.open_options(Picking::Target(Aim::Item(name), targets))?;
+                        }
+                        None => self.choose(Command::Item(name, None), state)?,
+                    }
+                }
+                Some(Picking::Target(aim, targets)) => {
+                    let target = targets[index];
+                    let command = match aim {
+                        Aim::Attack => Command::Fight(target),
+                        Aim::Spell(spell) => Command::Spell(spell, Some(target)),
+                        Aim::Item(item) => Command::Item(item, Some(target)),
+                    };
+                    self.choose(command, state)?;
+                }
+                None => {}
+            },
+            Some(UiEvent::Cancelled(id)) if id == self.options => self.close_options(),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn status_rect(&self) -> Rect {
+        Rect {
+            x: self.screen.x + MARGIN,
+            y: self.screen.y + MARGIN,
+            w: self.screen.w - MARGIN * 2,
+            h: STATUS_HEIGHT,
+        }
+    }
+
+    // Where a fighter is on screen, for aiming effects at.
+    fn center(&self, actor: Actor) -> Point {
+        match actor.side {
+            Side::Party => {
+                let status = self.status_rect();
+                let slot = (status.w - PADDING * 2) / PARTY_SLOTS;
+                Point {
+                    x: status.x + PADDING + slot * actor.index as i16 + slot / 2,
+                    y: status.y + status.h / 2,
+                }
+            }
+            Side::Enemies => {
+                let count = self.battle.enemies.len() as i16;
+                Point {
+                    x: self.screen.x + self.screen.w * (actor.index as i16 + 1) / (count + 1),
+                    y: self.screen.y + STAGE_TOP + STAGE_HEIGHT / 2,
+                }
+            }
+        }
+    }
+
+    fn draw_status(
+        &self,
+        renderer: &Renderer,
+        sprite_sheet_store: &SpriteSheetStore,
+        shake: i16,
+    ) -> Result<()> {
+        let status = Rect {
+            x: self.status_rect().x + shake,
+            ..self.status_rect()
+        };
+        draw_window(renderer, sprite_sheet_store, &status)?;
+        let slot = (status.w - PADDING * 2) / PARTY_SLOTS;
+        let choosing = self.window.is_none() && self.battle.outcome().is_none();
+        for (index, fighter) in self.battle.party.iter().enumerate() {
+            let hp = self.shown_hp.get(index).copied().unwrap_or(fighter.hp);
+            let color = if hp == 0 {
+                FALLEN_COLOR
+            } else if choosing && index == self.member {
+                ACTIVE_COLOR
+            } else {
+                TEXT_COLOR
+            };
+            let x = status.x + PADDING + slot * index as i16;
+            let lines = [
+                fighter.name.clone(),
+                format!("HP {}", hp),
+                format!("MP {}", fighter.mp),
+            ];
+            for (row, line) in lines.iter().enumerate() {
+                renderer.draw_text(
+                    line,
+                    &Point {
+                        x,
+                        y: status.y + PADDING + LINE_HEIGHT * row as i16,
+                    },
+                    &TextStyle::new(color),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl InteractionHandler for BattleScene {
+    fn update(&mut self, key_state: &KeyState, state: &mut GameState) -> Result<InteractionStatus> {
+        for emitter in &mut self.particles {
+            emitter.update();
+        }
+        self.particles.retain(|emitter| !emitter.is_finished());
+        self.shake = self.shake.saturating_sub(1);
+        self.flash = self
+            .flash
+            .and_then(|(index, left)| (left > 1).then_some((index, left - 1)));
+
+        if let Some(window) = &mut self.window {
+            if window.update(key_state) {
+                self.next_page(state)?;
+            }
+            return Ok(InteractionStatus::Continue);
+        }
+        if let Some(outcome) = self.battle.outcome() {
+            self.keep_party(state);
+            return Ok(InteractionStatus::Battle(outcome));
+        }
+        self.update_commands(key_state, state)?;
+        Ok(InteractionStatus::Continue)
+    }
+
+    fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
+        let screen = self.screen;
+        let shake = if self.shake % 4 < 2 {
+            SHAKE_OFFSET
+        } else {
+            -SHAKE_OFFSET
+        };
+        let shake = if self.shake > 0 { shake } else { 0 };
+        renderer.fill_rect(&screen, BACKGROUND_COLOR);
+        renderer.fill_rect(
+            &Rect {
+                x: screen.x,
+                y: screen.y + STAGE_TOP,
+                w: screen.w,
+                h: STAGE_HEIGHT,
+            },
+            STAGE_COLOR,
+        );
+        for (index, enemy) in self.battle.enemies.iter().enumerate() {
+            let blinking = self
+                .flash
+                .is_some_and(|(flashing, left)| flashing == index && left / 2 % 2 == 0);
+            if !self.shown_enemies.get(index).copied().unwrap_or(false) || blinking {
+                continue;
+            }
+            let center = self.center(Actor {
+                side: Side::Enemies,
+                index,
+            });
+            renderer.draw_image_with(
+                sprite_sheet_store.get(&enemy.fighter.sprite_source)?,
+                ENEMY_FRAME,
+                &Point {
+                    x: center.x - ENEMY_SIZE / 2 + shake,
+                    y: center.y - ENEMY_SIZE / 2,
+                },
+                &DrawOptions {
+                    scale_x: ENEMY_SCALE,
+                    scale_y: ENEMY_SCALE,
+                    ..DrawOptions::default()
+                },
+            )?;
+        }
+        self.draw_status(renderer, sprite_sheet_store, shake)?;
+
+        let mut queue = RenderQueue::new(Point { x: 0, y: 0 });
+        for emitter in &self.particles {
+            emitter.draw(&mut queue, sprite_sheet_store, 0)?;
+        }
+        queue.flush(renderer)?;
+
+        match &self.window {
+            Some(window) => window.draw(renderer, sprite_sheet_store, true),
+            None if self.battle.outcome().is_none() => self.ui.draw_at(
+                renderer,
+                sprite_sheet_store,
+                &Point {
+                    x: screen.x,
+                    y: screen.y + screen.h - MENU_HEIGHT - MARGIN,
+                },
+            ),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,192 @@
+use super::monster::{Bestiary, MonsterData, Monsters, Sightings};
+use crate::engine::{
+    draw_window, Align, DrawOptions, KeyState, ListItem, Point, Rect, Renderer, SpriteSheetStore,
+    TextStyle, Ui, UiEvent, WidgetId, LINE_HEIGHT, PADDING,
+};
+
+use anyhow::Result;
+
+const UNKNOWN: &str = "??????";
+const TEXT_COLOR: &str = "#ffffff";
+const NAME_COLOR: &str = "#ffd700";
+const HINT_COLOR: &str = "#9badb7";
+const SPRITE_FRAME: &str = "down02.png";
+const SPRITE_SCALE: f64 = 2.0;
+const SPRITE_SPACE: i16 = 80;
+const LIST_ROWS: i16 = 5;
+const DETAIL: Rect = Rect {
+    x: 16,
+    y: 168,
+    w: 448,
+    h: 296,
+};
+
+struct Entry {
+    name: String,
+    data: MonsterData,
+    sightings: Option<Sightings>,
+}
+
+// Every monster in the game, weakest first; only those seen are named and
+// only those defeated show their stats.
+pub struct BestiaryScreen {
+    ui: Ui,
+    list: WidgetId,
+    entries: Vec<Entry>,
+    cursor: usize,
+}
+impl BestiaryScreen {
+    pub fn new(monsters: &Monsters, bestiary: &Bestiary) -> Self {
+        let entries: Vec<Entry> = monsters
+            .by_level()
+            .into_iter()
+            .map(|(name, data)| Entry {
+                name: name.to_string(),
+                data: data.clone(),
+                sightings: bestiary.get(name).cloned(),
+            })
+            .collect();
+        let mut ui = Ui::new();
+        let window = ui.window(
+            None,
+            Rect {
+                x: 16,
+                y: 16,
+                w: 448,
+                h: LINE_HEIGHT * LIST_ROWS + PADDING * 2,
+            },
+        );
+        let list = ui.list(
+            Some(window),
+            Rect {
+                x: PADDING,
+                y: PADDING,
+                w: 448 - PADDING * 2,
+                h: LINE_HEIGHT * LIST_ROWS,
+            },
+            entries
+                .iter()
+                .enumerate()
+                .map(|(index, entry)| {
+                    let name = match entry.sightings {
+                        Some(_) => entry.name.as_str(),
+                        None => UNKNOWN,
+                    };
+                    ListItem::new(&format!("{:>2} {}", index + 1, name))
+                })
+                .collect(),
+            2,
+        );
+        ui.focus(list);
+        Self {
+            ui,
+            list,
+            entries,
+            cursor: 0,
+        }
+    }
+
+    // False once the player backs out.
+    pub fn update(&mut self, key_state: &KeyState) -> Result<bool> {
+        let event = self.ui.update(key_state);
+        self.cursor = self.ui.list_mut(self.list)?.cursor;
+        Ok(!matches!(event, Some(UiEvent::Cancelled(_))))
+    }
+
+    pub fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
+        self.ui.draw(renderer, sprite_sheet_store)?;
+        draw_window(renderer, sprite_sheet_store, &DETAIL)?;
+        let width = DETAIL.w - PADDING * 2;
+        let line = |row: i16, x: i16| Point {
+            x: DETAIL.x + PADDING + x,
+            y: DETAIL.y + PADDING + LINE_HEIGHT * row,
+        };
+        let seen = self
+            .entries
+            .iter()
+            .filter(|entry| entry.sightings.is_some())
+            .count();
+        renderer.draw_text(
+            &format!("Seen {}/{}", seen, self.entries.len()),
+            &line(0, 0),
+            &TextStyle {
+                align: Align::Right,
+                max_width: Some(width),
+                ..TextStyle::new(HINT_COLOR)
+            },
+        )?;
+        let Some(entry) = self.entries.get(self.cursor) else {
+            return Ok(());
+        };
+        let Some(sightings) = &entry.sightings else {
+            renderer.draw_text(UNKNOWN, &line(0, 0), &TextStyle::new(NAME_COLOR))?;
+            renderer.draw_text("Not seen yet.", &line(2, 0), &TextStyle::new(HINT_COLOR))?;
+            return Ok(());
+        };
+        let data = &entry.data;
+        renderer.draw_text(&entry.name, &line(0, 0), &TextStyle::new(NAME_COLOR))?;
+        renderer.draw_image_with(
+            sprite_sheet_store.get(&data.sprite_source)?,
+            SPRITE_FRAME,
+            &line(1, 8),
+            &DrawOptions {
+                scale_x: SPRITE_SCALE,
+                scale_y: SPRITE_SCALE,
+                pivot: Some(Point { x: 0, y: 0 }),
+                ..DrawOptions::default()
+            },
+        )?;
+        let mut lines = vec![
+            format!("Level {}", data.level),
+            format!("Seen {}  Defeated {}", sightings.seen, sightings.defeated),
+        ];
+        if sightings.defeated > 0 {
+            let stats = &data.stats;
+            lines.push(format!("HP {}  MP {}", stats.max_hp, stats.max_mp));
+            lines.push(format!(
+                "Atk {}  Def {}  Agi {}",
+                stats.attack, stats.defense, stats.agility
+            ));
+            lines.push(format!("EXP {}  Gold {}", data.exp, data.gold));
+        }
+        for (row, text) in lines.iter().enumerate() {
+            renderer.draw_text(
+                text,
+                &line(1 + row as i16, SPRITE_SPACE),
+                &TextStyle::new(TEXT_COLOR),
+            )?;
+        }
+        let below = 1 + lines.len().max(3) as i16;
+        if sightings.defeated == 0 {
+            renderer.draw_text(
+                "Defeat one to learn more.",
+                &line(below, 0),
+                &TextStyle::new(HINT_COLOR),
+            )?;
+            return Ok(());
+        }
+        let drops = data
+            .drops
+            .iter()
+            .map(|drop| drop.item.as_str())
+            .collect::<Vec<&str>>()
+            .join(", ");
+        let drops = if drops.is_empty() { "nothing" } else { &drops };
+        renderer.draw_text(
+            &format!("Drops {}", drops),
+            &line(below, 0),
+            &TextStyle {
+                max_width: Some(width),
+                ..TextStyle::new(TEXT_COLOR)
+            },
+        )?;
+        renderer.draw_text(
+            &data.description,
+            &line(below + 2, 0),
+            &TextStyle {
+                max_width: Some(width),
+                ..TextStyle::new(HINT_COLOR)
+            },
+        )
+    }
+}
@@ -0,0 +1,364 @@
+use super::flags::{Condition, Effect};
+use super::interaction::{InteractionHandler, InteractionStatus};
+use super::message_window::{ChoiceWindow, MessageWindow};
+use super::state::GameState;
+use crate::engine::{KeyState, Renderer, SpriteSheetStore};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Deserialize)]
+pub struct Dialogues {
+    scripts: HashMap<String, DialogueScript>,
+}
+impl Dialogues {
+    // TODO load every script in `asset/dialogue/*` once assets are discovered automatically
+    pub fn load() -> Result<Self> {
+        let json = include_bytes!("../asset/dialogue.json");
+        let dialogues: Dialogues = serde_json::from_slice(json)?;
+        dialogues.validate()?;
+        Ok(dialogues)
+    }
+
+    pub fn start(&self, name: &str, state: &mut GameState) -> Result<Dialogue> {
+        let script = self
+            .scripts
+            .get(name)
+            .ok_or_else(|| anyhow!("no DialogueScript found with name {}", name))?;
+        Ok(Dialogue::new(script.clone(), state))
+    }
+
+    fn validate(&self) -> Result<()> {
+        for (name, script) in &self.scripts {
+            let check = |next: &str| {
+                if next == END || script.nodes.contains_key(next) {
+                    Ok(())
+                } else {
+                    Err(anyhow!("invalid node {} in DialogueScript {}", next, name))
+                }
+            };
+            check(&script.start)?;
+            for node in script.nodes.values() {
+                if let Some(next) = &node.next {
+                    check(next)?;
+                }
+                for branch in &node.branches {
+                    check(&branch.next)?;
+                }
+                for choice in &node.choices {
+                    check(&choice.next)?;
+                }
+            }
+            script.check_cycles(name)?;
+        }
+        Ok(())
+    }
+}
+
+// A node id that ends the conversation.
+const END: &str = "end";
+
+#[derive(Clone, Deserialize)]
+pub struct DialogueScript {
+    start: String,
+    nodes: HashMap<String, DialogueNode>,
+}
+impl DialogueScript {
+    // Nodes without lines are passed straight through, so a loop made only
+    // of them would never stop to wait for the player.
+    fn check_cycles(&self, name: &str) -> Result<()> {
+        let mut done = HashSet::new();
+        for start in self.nodes.keys() {
+            let mut path = Vec::new();
+            self.visit(name, start, &mut path, &mut done)?;
+        }
+        Ok(())
+    }
+
+    fn visit<'a>(
+        &'a self,
+        name: &str,
+        id: &'a str,
+        path: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+    ) -> Result<()> {
+        if path.contains(&id) {
+            return Err(anyhow!(
+                "nodes without lines loop through {} in DialogueScript {}",
+                id,
+                name
+            ));
+        }
+        let Some(node) = self.nodes.get(id) else {
+            return Ok(());
+        };
+        if !node.lines.is_empty() || done.contains(id) {
+            return Ok(());
+        }
+        path.push(id);
+        let branches = node.branches.iter().map(|branch| branch.next.as_str());
+        for next in branches.chain(node.next.as_deref()) {
+            self.visit(name, next, path, done)?;
+        }
+        path.pop();
+        done.insert(id);
+        Ok(())
+    }
+}
+
+// Pages are shown in order, then the first visible choice list is offered, or
+// else the first branch whose condition holds is followed, falling back to `next`.
+#[derive(Clone, Deserialize)]
+struct DialogueNode {
+    speaker: Option<String>,
+    lines: Vec<String>,
+    #[serde(default)]
+    effects: Vec<Effect>,
+    #[serde(default)]
+    choices: Vec<Choice>,
+    #[serde(default)]
+    branches: Vec<Branch>,
+    next: Option<String>,
+}
+
+#[derive(Clone, Deserialize)]
+struct Choice {
+    text: String,
+    next: String,
+    condition: Option<Condition>,
+    #[serde(default)]
+    effects: Vec<Effect>,
+}
+
+#[derive(Clone, Deserialize)]
+struct Branch {
+    condition: Condition,
+    next: String,
+}
+
+enum DialogueStep {
+    Reading(MessageWindow),
+    Choosing(MessageWindow, ChoiceWindow, Vec<usize>),
+    Finished,
+}
+
+pub struct Dialogue {
+    script: DialogueScript,
+    node: String,
+    line: usize,
+    step: DialogueStep,
+}
+impl Dialogue {
+    fn new(script: DialogueScript, state: &mut GameState) -> Self {
+        let start = script.start.clone();
+        let mut dialogue = Self {
+            script,
+            node: String::new(),
+            line: 0,
+            step: DialogueStep::Finished,
+        };
+        dialogue.enter(&start, state);
+        dialogue
+    }
+
+    fn current(&self) -> &DialogueNode {
+        &self.script.nodes[&self.node]
+    }
+
+    fn enter(&mut self, node: &str, state: &mut GameState) {
+        if node == END {
+            self.step = DialogueStep::Finished;
+            return;
+        }
+        self.node = node.to_string();
+        self.line = 0;
+        for effect in &self.script.nodes[node].effects {
+            state.flags.apply(effect);
+        }
+        self.show_line(state);
+    }
+
+    fn show_line(&mut self, state: &mut GameState) {
+        let node = self.current();
+        match node.lines.get(self.line) {
+            Some(line) => {
+                self.step = DialogueStep::Reading(MessageWindow::new(node.speaker.as_deref(), line))
+            }
+            // A node with nothing to say can still ask something.
+            None if node.lines.is_empty() => {
+                let window = MessageWindow::new(node.speaker.as_deref(), "");
+                let choices = self.visible_choices(state);
+                if choices.is_empty() {
+                    self.leave(state);
+                } else {
+                    self.offer(window, choices);
+                }
+            }
+            None => self.leave(state),
+        }
+    }
+
+    fn offer(&mut self, window: MessageWindow, choices: Vec<usize>) {
+        let options = choices
+            .iter()
+            .map(|index| self.current().choices[*index].text.clone())
+            .collect();
+        self.step = DialogueStep::Choosing(window, ChoiceWindow::new(options), choices);
+    }
+
+    // Called once every page of the current node has been read.
+    fn leave(&mut self, state: &mut GameState) {
+        let node = self.current();
+        let next = node
+            .branches
+            .iter()
+            .find(|branch| state.flags.check(&branch.condition))
+            .map(|branch| branch.next.clone())
+            .or_else(|| node.next.clone())
+            .unwrap_or_else(|| END.to_string());
+        self.enter(&next, state);
+    }
+
+    fn visible_choices(&self, state: &GameState) -> Vec<usize> {
+        self.current()
+            .choices
+            .iter()
+            .enumerate()
+            .filter(|(_, choice)| match &choice.condition {
+                Some(condition) => state.flags.check(condition),
+                None => true,
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    fn choose(&mut self, index: usize, state: &mut GameState) {
+        let choice = self.current().choices[index].clone();
+        for effect in &choice.effects {
+            state.flags.apply(effect);
+        }
+        self.enter(&choice.next, state);
+    }
+}
+
+impl InteractionHandler for Dialogue {
+    fn update(&mut self, key_state: &KeyState, state: &mut GameState) -> Result<InteractionStatus> {
+        match std::mem::replace(&mut self.step, DialogueStep::Finished) {
+            DialogueStep::Reading(mut window) => {
+                let is_last = self.line + 1 >= self.current().lines.len();
+                let choices = self.visible_choices(state);
+                if is_last && window.is_revealed() && !choices.is_empty() {
+                    self.offer(window, choices);
+                } else if window.update(key_state) {
+                    self.line += 1;
+                    self.show_line(state);
+                } else {
+                    self.step = DialogueStep::Reading(window);
+                }
+            }
+            DialogueStep::Choosing(window, mut choice_window, choices) => {
+                match choice_window.update(key_state) {
+                    Some(chosen) => self.choose(choices[chosen], state),
+                    None => self.step = DialogueStep::Choosing(window, choice_window, choices),
+                }
+            }
+            DialogueStep::Finished => {}
+        }
+        Ok(match self.step {
+            DialogueStep::Finished => InteractionStatus::Finished,
+            _ => InteractionStatus::Continue,
+        })
+    }
+
+    fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
+        match &self.step {
+            DialogueStep::Reading(window) => window.draw(renderer, sprite_sheet_store, true),
+            DialogueStep::Choosing(window, choice_window, _) => {
+                window.draw(renderer, sprite_sheet_store, false)?;
+                choice_window.draw(renderer, sprite_sheet_store)
+            }
+            DialogueStep::Finished => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dialogues(json: &str) -> Result<Dialogues> {
+        let dialogues: Dialogues = serde_json::from_str(json)?;
+        dialogues.validate()?;
+        Ok(dialogues)
+    }
+
+    #[test]
+    fn node_without_lines_offers_its_choices() {
+        let dialogues = dialogues(
+            r#"{ "scripts": { "ask": { "start": "ask", "nodes": {
+                "ask": {
+                    "speaker": "Guard",
+                    "lines": [],
+                    "choices": [{ "text": "Yes", "next": "end" }, { "text": "No", "next": "end" }]
+                }
+            } } } }"#,
+        )
+        .unwrap();
+        let mut state = GameState::new(1);
+        let dialogue = dialogues.start("ask", &mut state).unwrap();
+        match &dialogue.step {
+            DialogueStep::Choosing(_, _, choices) => assert_eq!(choices, &vec![0, 1]),
+            _ => panic!("expected the choices to be offered"),
+        }
+    }
+
+    #[test]
+    fn node_without_lines_or_visible_choices_moves_on() {
+        let dialogues = dialogues(
+            r#"{ "scripts": { "ask": { "start": "ask", "nodes": {
+                "ask": {
+                    "lines": [],
+                    "choices": [{ "text": "Yes", "next": "end", "condition": { "set": "asked" } }],
+                    "next": "after"
+                },
+                "after": { "lines": ["Never mind."] }
+            } } } }"#,
+        )
+        .unwrap();
+        let mut state = GameState::new(1);
+        let dialogue = dialogues.start("ask", &mut state).unwrap();
+        assert_eq!(dialogue.node, "after");
+        assert!(matches!(dialogue.step, DialogueStep::Reading(_)));
+    }
+
+    #[test]
+    fn loop_of_nodes_without_lines_is_rejected() {
+        let error = dialogues(
+            r#"{ "scripts": { "spin": { "start": "a", "nodes": {
+                "a": { "lines": [], "next": "b" },
+                "b": { "lines": [], "branches": [{ "condition": { "set": "x" }, "next": "c" }], "next": "a" },
+                "c": { "lines": ["Out."] }
+            } } } }"#,
+        )
+        .err()
+        .expect("the loop should be rejected");
+        assert!(error.to_string().contains("DialogueScript spin"));
+    }
+
+    #[test]
+    fn loop_through_a_node_with_lines_is_allowed() {
+        dialogues(
+            r#"{ "scripts": { "again": { "start": "a", "nodes": {
+                "a": { "lines": [], "next": "b" },
+                "b": { "lines": ["Once more?"], "next": "a" }
+            } } } }"#,
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn shipped_dialogue_is_valid() {
+        Dialogues::load().unwrap();
+    }
+}
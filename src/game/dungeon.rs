@@ -0,0 +1,737 @@
+use super::encounter::{Encounter, Encounters};
+use super::floor::{FloorData, LayerData, Tile};
+use super::lighting::Darkness;
+use super::map::{Warp, WarpKind};
+use super::npc::{Behaviour, NpcData};
+use super::object::{FieldObject, ObjectKind};
+use super::Direction;
+use crate::engine::{Point, Rect, Rng};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+
+// Dungeon floors are the maps named "dungeon-1", "dungeon-2" and so on.
+pub const DUNGEON_PREFIX: &str = "dungeon-";
+const WALL: char = '#';
+const OPEN: char = '.';
+const STAIRS_UP: char = '<';
+const STAIRS_DOWN: char = '>';
+const ROOM_ATTEMPTS: u16 = 80;
+const MAX_ROOMS: usize = 10;
+// BSP leaves are split this many times, and never below MIN_LEAF tiles.
+const BSP_DEPTH: u8 = 4;
+const MIN_LEAF: i16 = 7;
+// Caves start with this percentage of open tiles, then are smoothed.
+const CAVE_FILL: u16 = 42;
+const CAVE_STEPS: u8 = 4;
+// Smaller caves are thrown away for rooms.
+const MIN_OPEN: usize = 120;
+// Nothing is placed this close to the stairs, so arrivals are never blocked.
+const STAIRS_CLEARANCE: i16 = 2;
+const WANDER_RADIUS: i16 = 3;
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Layout {
+    Rooms,
+    Bsp,
+    Caves,
+}
+
+// `base + per_depth * (depth - 1)`, kept within `min..=max`.
+#[derive(Deserialize)]
+struct Scaling {
+    base: i16,
+    per_depth: i16,
+    #[serde(default)]
+    min: i16,
+    max: i16,
+}
+impl Scaling {
+    fn at(&self, depth: u16) -> i16 {
+        (self.base + self.per_depth * (depth as i16 - 1)).clamp(self.min, self.max)
+    }
+}
+
+// Found on floors `min_depth..=max_depth`; no `max_depth` means all the way down.
+#[derive(Deserialize)]
+struct Depths {
+    min_depth: u16,
+    max_depth: Option<u16>,
+}
+impl Depths {
+    fn contains(&self, depth: u16) -> bool {
+        depth >= self.min_depth && self.max_depth.is_none_or(|max| depth <= max)
+    }
+}
+
+#[derive(Deserialize)]
+struct Treasure {
+    item: String,
+    #[serde(flatten)]
+    depths: Depths,
+}
+
+#[derive(Deserialize)]
+struct Monster {
+    name: String,
+    sprite_source: String,
+    formation: String,
+    #[serde(flatten)]
+    depths: Depths,
+}
+
+#[derive(Deserialize)]
+struct DungeonEncounter {
+    #[serde(flatten)]
+    encounter: Encounter,
+    #[serde(flatten)]
+    depths: Depths,
+}
+
+// Each floor's table holds the groups found at its depth.
+#[derive(Deserialize)]
+struct DungeonEncounters {
+    rate: f32,
+    grace: u32,
+    table: Vec<DungeonEncounter>,
+}
+
+#[derive(Deserialize)]
+struct DungeonSettings {
+    width: i16,
+    height: i16,
+    max_depth: u16,
+    // Where the first floor's up stairs lead.
+    exit: String,
+    tileset: String,
+    legend: HashMap<char, Tile>,
+    darkness: Option<Darkness>,
+    sight: Scaling,
+    layouts: Vec<Layout>,
+    treasure_count: Scaling,
+    monster_count: Scaling,
+    treasure: Vec<Treasure>,
+    monsters: Vec<Monster>,
+    encounters: DungeonEncounters,
+}
+
+pub struct Dungeon {
+    pub floor: FloorData,
+    pub npcs: Vec<NpcData>,
+    pub objects: Vec<FieldObject>,
+}
+
+// Floors are numbered from 1. `rng` should be derived from the seed and the
+// depth alone, so that a shared seed gives everyone the same floors.
+pub fn generate(mut rng: Rng, depth: u16) -> Result<Dungeon> {
+    let settings: DungeonSettings =
+        serde_json::from_slice(include_bytes!("../asset/dungeon.json"))?;
+    if depth == 0 || depth > settings.max_depth {
+        return Err(anyhow!("there is no dungeon floor {}", depth));
+    }
+    let layout = match settings.layouts.len() {
+        0 => Layout::Rooms,
+        count => settings.layouts[rng.range(0, count as u16) as usize],
+    };
+    build(settings, layout, rng, depth)
+}
+
+fn build(settings: DungeonSettings, layout: Layout, mut rng: Rng, depth: u16) -> Result<Dungeon> {
+    let mut grid = Grid::new(settings.width, settings.height);
+    match layout {
+        Layout::Rooms => grid.carve_rooms(&mut rng),
+        Layout::Bsp => grid.carve_bsp(&mut rng),
+        Layout::Caves => grid.carve_caves(&mut rng),
+    }
+    if grid.open_tiles().len() < MIN_OPEN {
+        grid = Grid::new(settings.width, settings.height);
+        grid.carve_rooms(&mut rng);
+    }
+
+    // The down stairs go as far from the up stairs as the floor allows.
+    let open = grid.open_tiles();
+    let up = open[rng.range(0, open.len() as u16) as usize];
+    let distances = grid.distances(&up);
+    let down = open
+        .iter()
+        .copied()
+        .max_by_key(|tile| grid.index(tile).and_then(|index| distances[index]))
+        .unwrap_or(up);
+    let last = depth == settings.max_depth;
+
+    let mut spots: Vec<Point> = open
+        .iter()
+        .copied()
+        .filter(|tile| grid.is_roomy(tile))
+        .filter(|tile| {
+            [up, down].iter().all(|stairs| {
+                (tile.x - stairs.x).abs().max((tile.y - stairs.y).abs()) > STAIRS_CLEARANCE
+            })
+        })
+        .collect();
+    shuffle(&mut spots, &mut rng);
+    let mut spots = spots.into_iter();
+
+    let treasure: Vec<&Treasure> = settings
+        .treasure
+        .iter()
+        .filter(|treasure| treasure.depths.contains(depth))
+        .collect();
+    let mut objects = Vec::new();
+    if !treasure.is_empty() {
+        // Chests block the way, so each one is tried as a wall first and
+        // skipped if that would cut anything off from the stairs.
+        let count = settings.treasure_count.at(depth) as usize;
+        let mut reachable = grid.reachable(&up);
+        for tile in spots.by_ref() {
+            if objects.len() == count {
+                break;
+            }
+            grid.set(&tile, false);
+            if grid.reachable(&up) + 1 < reachable {
+                grid.set(&tile, true);
+                continue;
+            }
+            reachable -= 1;
+            let treasure = treasure[rng.range(0, treasure.len() as u16) as usize];
+            objects.push(FieldObject {
+                tile,
+                kind: ObjectKind::Chest {
+                    item: treasure.item.clone(),
+                    opened: false,
+                },
+                sparkles: None,
+            });
+        }
+        for object in &objects {
+            grid.set(&object.tile, true);
+        }
+    }
+    let monsters: Vec<&Monster> = settings
+        .monsters
+        .iter()
+        .filter(|monster| monster.depths.contains(depth))
+        .collect();
+    let mut npcs = Vec::new();
+    if !monsters.is_empty() {
+        for tile in spots.take(settings.monster_count.at(depth) as usize) {
+            let monster = monsters[rng.range(0, monsters.len() as u16) as usize];
+            npcs.push(NpcData {
+                name: monster.name.clone(),
+                sprite_source: monster.sprite_source.clone(),
+                tile,
+                behaviour: Behaviour::Wander {
+                    origin: tile,
+                    radius: WANDER_RADIUS,
+                },
+                dialogue: None,
+                battle: Some(monster.formation.clone()),
+                shop: None,
+                inn: None,
+                hidden_if: None,
+            });
+        }
+    }
+
+    let mut warps = vec![Warp {
+        tile: up,
+        kind: WarpKind::StairsUp,
+        target: match depth {
+            1 => settings.exit.clone(),
+            _ => format!("{}{}", DUNGEON_PREFIX, depth - 1),
+        },
+        spawn: None,
+        facing: Direction::Down,
+        transition: None,
+    }];
+    if !last {
+        warps.push(Warp {
+            tile: down,
+            kind: WarpKind::StairsDown,
+            target: format!("{}{}", DUNGEON_PREFIX, depth + 1),
+            spawn: None,
+            facing: Direction::Down,
+            transition: None,
+        });
+    }
+    let darkness = settings.darkness.map(|darkness| Darkness {
+        sight: settings.sight.at(depth),
+        ..darkness
+    });
+    let rows = (0..grid.height)
+        .map(|y| {
+            (0..grid.width)
+                .map(|x| {
+                    let tile = Point { x, y };
+                    if tile == up {
+                        STAIRS_UP
+                    } else if tile == down && !last {
+                        STAIRS_DOWN
+                    } else if grid.is_open(&tile) {
+                        OPEN
+                    } else {
+                        WALL
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    Ok(Dungeon {
+        floor: FloorData {
+            name: format!("{}{}", DUNGEON_PREFIX, depth),
+            tileset: settings.tileset,
+            legend: settings.legend,
+            layers: vec![LayerData {
+                name: "ground".to_string(),
+                above: false,
+                rows,
+            }],
+            entrance: None,
+            darkness,
+            warps,
+            encounters: Some(Encounters {
+                rate: settings.encounters.rate,
+                grace: settings.encounters.grace,
+                table: settings
+                    .encounters
+                    .table
+                    .into_iter()
+                    .filter(|entry| entry.depths.contains(depth))
+                    .map(|entry| entry.encounter)
+                    .collect(),
+                regions: Vec::new(),
+            }),
+        },
+        npcs,
+        objects,
+    })
+}
+
+fn shuffle<T>(items: &mut [T], rng: &mut Rng) {
+    for i in (1..items.len()).rev() {
+        items.swap(i, rng.range(0, i as u16 + 1) as usize);
+    }
+}
+
+fn center(rect: &Rect) -> Point {
+    Point {
+        x: rect.x + rect.w / 2,
+        y: rect.y + rect.h / 2,
+    }
+}
+
+// Whether `a` and `b` come within `margin` tiles of each other.
+fn overlaps(a: &Rect, b: &Rect, margin: i16) -> bool {
+    a.x - margin < b.x + b.w
+        && b.x < a.x + a.w + margin
+        && a.y - margin < b.y + b.h
+        && b.y < a.y + a.h + margin
+}
+
+// Open and walled tiles; the outermost ring is always wall.
+struct Grid {
+    width: i16,
+    height: i16,
+    open: Vec<bool>,
+}
+impl Grid {
+    fn new(width: i16, height: i16) -> Self {
+        Self {
+            width,
+            height,
+            open: vec![false; (width * height) as usize],
+        }
+    }
+
+    fn index(&self, tile: &Point) -> Option<usize> {
+        if tile.x < 0 || tile.y < 0 || tile.x >= self.width || tile.y >= self.height {
+            return None;
+        }
+        Some((tile.y * self.width + tile.x) as usize)
+    }
+
+    fn is_open(&self, tile: &Point) -> bool {
+        self.index(tile).is_some_and(|index| self.open[index])
+    }
+
+    fn set(&mut self, tile: &Point, open: bool) {
+        let inside =
+            (1..self.width - 1).contains(&tile.x) && (1..self.height - 1).contains(&tile.y);
+        if let (true, Some(index)) = (inside, self.index(tile)) {
+            self.open[index] = open;
+        }
+    }
+
+    fn open_tiles(&self) -> Vec<Point> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).map(move |x| Point { x, y }))
+            .filter(|tile| self.is_open(tile))
+            .collect()
+    }
+
+    // Open on all four sides, so that things are placed in rooms rather than
+    // in corridors.
+    fn is_roomy(&self, tile: &Point) -> bool {
+        Self::neighbours(tile).iter().all(|next| self.is_open(next))
+    }
+
+    fn neighbours(tile: &Point) -> [Point; 4] {
+        [(-1, 0), (0, -1), (1, 0), (0, 1)].map(|(x, y)| Point {
+            x: tile.x + x,
+            y: tile.y + y,
+        })
+    }
+
+    // Steps from `from` to every tile, or `None` where it cannot be reached.
+    fn distances(&self, from: &Point) -> Vec<Option<u16>> {
+        let mut distances = vec![None; self.open.len()];
+        let mut queue = VecDeque::new();
+        if let Some(index) = self.index(from) {
+            distances[index] = Some(0);
+            queue.push_back((*from, 0));
+        }
+        while let Some((tile, distance)) = queue.pop_front() {
+            for next in Self::neighbours(&tile) {
+                if let Some(index) = self.index(&next) {
+                    if self.open[index] && distances[index].is_none() {
+                        distances[index] = Some(distance + 1);
+                        queue.push_back((next, distance + 1));
+                    }
+                }
+            }
+        }
+        distances
+    }
+
+    // How many tiles can be walked to from `from`, counting itself.
+    fn reachable(&self, from: &Point) -> usize {
+        self.distances(from)
+            .iter()
+            .filter(|distance| distance.is_some())
+            .count()
+    }
+
+    fn carve_rect(&mut self, rect: &Rect) {
+        for y in rect.y..rect.y + rect.h {
+            for x in rect.x..rect.x + rect.w {
+                self.set(&Point { x, y }, true);
+            }
+        }
+    }
+
+    // An L-shaped corridor, turning at one of the two possible corners.
+    fn carve_corridor(&mut self, from: &Point, to: &Point, rng: &mut Rng) {
+        let corner = if rng.range(0, 2) == 0 {
+            Point { x: to.x, y: from.y }
+        } else {
+            Point { x: from.x, y: to.y }
+        };
+        for (a, b) in [(from, &corner), (&corner, to)] {
+            self.carve_rect(&Rect {
+                x: a.x.min(b.x),
+                y: a.y.min(b.y),
+                w: (a.x - b.x).abs() + 1,
+                h: (a.y - b.y).abs() + 1,
+            });
+        }
+    }
+
+    // Rooms scattered where they fit, each joined to the one before.
+    fn carve_rooms(&mut self, rng: &mut Rng) {
+        let mut rooms: Vec<Rect> = Vec::new();
+        for _ in 0..ROOM_ATTEMPTS {
+            let w = rng.range(4, 10) as i16;
+            let h = rng.range(4, 8) as i16;
+            let room = Rect {
+                x: rng.range(1, (self.width - w) as u16) as i16,
+                y: rng.range(1, (self.height - h) as u16) as i16,
+                w,
+                h,
+            };
+            if rooms.iter().any(|other| overlaps(other, &room, 1)) {
+                continue;
+            }
+            self.carve_rect(&room);
+            if let Some(last) = rooms.last() {
+                self.carve_corridor(&center(last), &center(&room), rng);
+            }
+            rooms.push(room);
+            if rooms.len() == MAX_ROOMS {
+                break;
+            }
+        }
+    }
+
+    // Splits the floor in two again and again, puts a room in each part and
+    // joins the parts back up in the order they were split.
+    fn carve_bsp(&mut self, rng: &mut Rng) {
+        let area = Rect {
+            x: 1,
+            y: 1,
+            w: self.width - 2,
+            h: self.height - 2,
+        };
+        self.split(&area, BSP_DEPTH, rng);
+    }
+
+    // Returns the centre of one of the rooms in `area` to connect to.
+    fn split(&mut self, area: &Rect, depth: u8, rng: &mut Rng) -> Point {
+        let across = match (area.w * 4 > area.h * 5, area.h * 4 > area.w * 5) {
+            (true, _) => false,
+            (_, true) => true,
+            _ => rng.range(0, 2) == 0,
+        };
+        let size = if across { area.h } else { area.w };
+        if depth == 0 || size < MIN_LEAF * 2 {
+            let w = rng.range(3, (area.w - 1) as u16) as i16;
+            let h = rng.range(3, (area.h - 1) as u16) as i16;
+            let room = Rect {
+                x: area.x + 1 + rng.range(0, (area.w - w - 1) as u16) as i16,
+                y: area.y + 1 + rng.range(0, (area.h - h - 1) as u16) as i16,
+                w,
+                h,
+            };
+            self.carve_rect(&room);
+            return center(&room);
+        }
+        let cut = rng.range(MIN_LEAF as u16, (size - MIN_LEAF + 1) as u16) as i16;
+        let (first, second) = if across {
+            (
+                Rect { h: cut, ..*area },
+                Rect {
+                    y: area.y + cut,
+                    h: area.h - cut,
+                    ..*area
+                },
+            )
+        } else {
+            (
+                Rect { w: cut, ..*area },
+                Rect {
+                    x: area.x + cut,
+                    w: area.w - cut,
+                    ..*area
+                },
+            )
+        };
+        let a = self.split(&first, depth - 1, rng);
+        let b = self.split(&second, depth - 1, rng);
+        self.carve_corridor(&a, &b, rng);
+        if rng.range(0, 2) == 0 {
+            a
+        } else {
+            b
+        }
+    }
+
+    // Random noise smoothed by a cellular automaton: a tile becomes wall when
+    // most of its eight neighbours are. Only the largest cave is kept.
+    fn carve_caves(&mut self, rng: &mut Rng) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                self.set(&Point { x, y }, rng.range(0, 100) < CAVE_FILL);
+            }
+        }
+        for _ in 0..CAVE_STEPS {
+            let mut next = Grid::new(self.width, self.height);
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    let walls = (-1..=1)
+                        .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                        .filter(|&(dx, dy)| {
+                            (dx, dy) != (0, 0)
+                                && !self.is_open(&Point {
+                                    x: x + dx,
+                                    y: y + dy,
+                                })
+                        })
+                        .count();
+                    next.set(&Point { x, y }, walls < 5);
+                }
+            }
+            *self = next;
+        }
+
+        let mut largest: Option<Vec<Option<u16>>> = None;
+        let mut seen = vec![false; self.open.len()];
+        for tile in self.open_tiles() {
+            let Some(index) = self.index(&tile) else {
+                continue;
+            };
+            if seen[index] {
+                continue;
+            }
+            let region = self.distances(&tile);
+            let size = region.iter().flatten().count();
+            for (index, distance) in region.iter().enumerate() {
+                seen[index] |= distance.is_some();
+            }
+            if largest
+                .as_ref()
+                .is_none_or(|largest| size > largest.iter().flatten().count())
+            {
+                largest = Some(region);
+            }
+        }
+        if let Some(largest) = largest {
+            for (open, distance) in self.open.iter_mut().zip(largest) {
+                *open = distance.is_some();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{Random, Stream};
+
+    const SEEDS: [u32; 8] = [1, 2, 3, 42, 1234, 65535, 99999, 4_000_000_000];
+    const LAYOUTS: [Layout; 3] = [Layout::Rooms, Layout::Bsp, Layout::Caves];
+
+    fn settings() -> DungeonSettings {
+        serde_json::from_slice(include_bytes!("../asset/dungeon.json")).unwrap()
+    }
+
+    fn floor(seed: u32, depth: u16, layout: Layout) -> Dungeon {
+        let rng = Random::new(seed).derive(Stream::World, depth as u32);
+        build(settings(), layout, rng, depth).unwrap()
+    }
+
+    // Everything about a floor that the player could tell apart.
+    fn summary(dungeon: &Dungeon) -> String {
+        let warps: Vec<String> = dungeon
+            .floor
+            .warps
+            .iter()
+            .map(|warp| format!("{:?} {}", warp.tile, warp.target))
+            .collect();
+        let objects: Vec<String> = dungeon
+            .objects
+            .iter()
+            .map(|object| match &object.kind {
+                ObjectKind::Chest { item, .. } => format!("{:?} {}", object.tile, item),
+                _ => format!("{:?}", object.tile),
+            })
+            .collect();
+        let npcs: Vec<String> = dungeon
+            .npcs
+            .iter()
+            .map(|npc| format!("{:?} {} {:?}", npc.tile, npc.name, npc.battle))
+            .collect();
+        format!(
+            "{}\n{:?}\n{:?}\n{:?}",
+            dungeon.floor.layers[0].rows.join("\n"),
+            warps,
+            objects,
+            npcs
+        )
+    }
+
+    fn find(rows: &[String], key: char) -> Option<Point> {
+        rows.iter().enumerate().find_map(|(y, row)| {
+            row.chars().position(|c| c == key).map(|x| Point {
+                x: x as i16,
+                y: y as i16,
+            })
+        })
+    }
+
+    // Flood-fills from the up stairs over anything but walls and chests.
+    fn reachable(dungeon: &Dungeon) -> Vec<Point> {
+        let rows: Vec<Vec<char>> = dungeon.floor.layers[0]
+            .rows
+            .iter()
+            .map(|row| row.chars().collect())
+            .collect();
+        let chests: Vec<Point> = dungeon.objects.iter().map(|object| object.tile).collect();
+        let passable = |tile: &Point| {
+            tile.x >= 0
+                && tile.y >= 0
+                && rows
+                    .get(tile.y as usize)
+                    .and_then(|row| row.get(tile.x as usize))
+                    .is_some_and(|c| *c != WALL)
+                && !chests.contains(tile)
+        };
+        let start = find(&dungeon.floor.layers[0].rows, STAIRS_UP).unwrap();
+        let mut seen = vec![start];
+        let mut queue = VecDeque::from([start]);
+        while let Some(tile) = queue.pop_front() {
+            for next in Grid::neighbours(&tile) {
+                if passable(&next) && !seen.contains(&next) {
+                    seen.push(next);
+                    queue.push_back(next);
+                }
+            }
+        }
+        seen
+    }
+
+    #[test]
+    fn same_seed_and_depth_give_the_same_floor() {
+        for layout in LAYOUTS {
+            for seed in SEEDS {
+                for depth in [1, 3] {
+                    assert_eq!(
+                        summary(&floor(seed, depth, layout)),
+                        summary(&floor(seed, depth, layout)),
+                        "{:?} floor {} of seed {}",
+                        layout,
+                        depth,
+                        seed
+                    );
+                }
+            }
+        }
+        let random = Random::new(7);
+        let a = generate(random.derive(Stream::World, 2), 2).unwrap();
+        let b = generate(random.derive(Stream::World, 2), 2).unwrap();
+        assert_eq!(summary(&a), summary(&b));
+    }
+
+    #[test]
+    fn other_seeds_give_other_floors() {
+        assert_ne!(
+            summary(&floor(1, 1, Layout::Rooms)),
+            summary(&floor(2, 1, Layout::Rooms))
+        );
+    }
+
+    #[test]
+    fn down_stairs_can_be_reached_from_the_up_stairs() {
+        for layout in LAYOUTS {
+            for seed in SEEDS {
+                let dungeon = floor(seed, 1, layout);
+                let rows = &dungeon.floor.layers[0].rows;
+                let down = find(rows, STAIRS_DOWN).expect("the first floor has down stairs");
+                assert!(
+                    reachable(&dungeon).contains(&down),
+                    "{:?} floor of seed {}:\n{}",
+                    layout,
+                    seed,
+                    rows.join("\n")
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn everything_placed_can_be_reached() {
+        for layout in LAYOUTS {
+            for seed in SEEDS {
+                let dungeon = floor(seed, 2, layout);
+                let reachable = reachable(&dungeon);
+                for npc in &dungeon.npcs {
+                    assert!(reachable.contains(&npc.tile), "{:?} seed {}", layout, seed);
+                }
+                for object in &dungeon.objects {
+                    let beside = Grid::neighbours(&object.tile);
+                    assert!(beside.iter().any(|tile| reachable.contains(tile)));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use crate::engine::{Emitter, EmitterSettings, Point, Rng};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// Named particle emitters shared by the field and battles.
+#[derive(Clone, Deserialize)]
+pub struct Effects {
+    emitters: HashMap<String, EmitterSettings>,
+}
+impl Effects {
+    pub fn load() -> Result<Self> {
+        let json = include_bytes!("../asset/effects.json");
+        Ok(serde_json::from_slice(json)?)
+    }
+
+    fn settings(&self, name: &str) -> Result<EmitterSettings> {
+        self.emitters
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("no EmitterSettings found with name {}", name))
+    }
+
+    pub fn start(&self, name: &str, position: Point, rng: Rng) -> Result<Emitter> {
+        Ok(Emitter::new(self.settings(name)?, position, rng))
+    }
+
+    pub fn burst(&self, name: &str, position: Point, count: usize, rng: Rng) -> Result<Emitter> {
+        Ok(Emitter::burst(self.settings(name)?, position, count, rng))
+    }
+}
@@ -0,0 +1,70 @@
+use crate::engine::{Point, Rng};
+
+use serde::Deserialize;
+
+// Remaining steps of repel; set by repel items.
+pub const REPEL_FLAG: &str = "repel";
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Encounter {
+    pub formation: String,
+    pub weight: u16,
+}
+
+// A rectangle of tiles, `from` and `to` included, with a table of its own.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Region {
+    pub from: Point,
+    pub to: Point,
+    pub rate: Option<f32>,
+    pub table: Vec<Encounter>,
+}
+impl Region {
+    fn contains(&self, tile: &Point) -> bool {
+        (self.from.x..=self.to.x).contains(&tile.x) && (self.from.y..=self.to.y).contains(&tile.y)
+    }
+}
+
+// `rate` is the chance of a battle on each step, in percent, and `grace` the
+// steps after a battle before the next one can start. The first region that
+// holds a tile replaces the floor's own table there.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Encounters {
+    pub rate: f32,
+    pub grace: u32,
+    #[serde(default)]
+    pub table: Vec<Encounter>,
+    #[serde(default)]
+    pub regions: Vec<Region>,
+}
+impl Encounters {
+    // The formation met on stepping onto `tile`, if any; groups that are
+    // `repelled` are never met.
+    pub fn roll(
+        &self,
+        tile: &Point,
+        repelled: impl Fn(&str) -> bool,
+        rng: &mut Rng,
+    ) -> Option<&str> {
+        let (rate, table) = match self.regions.iter().find(|region| region.contains(tile)) {
+            Some(region) => (region.rate.unwrap_or(self.rate), &region.table),
+            None => (self.rate, &self.table),
+        };
+        if rng.range_f32(0.0, 100.0) >= rate {
+            return None;
+        }
+        let candidates: Vec<&Encounter> = table
+            .iter()
+            .filter(|encounter| !repelled(&encounter.formation))
+            .collect();
+        let total: u16 = candidates.iter().map(|encounter| encounter.weight).sum();
+        let mut pick = rng.range(0, total);
+        for encounter in candidates {
+            if pick < encounter.weight {
+                return Some(&encounter.formation);
+            }
+            pick -= encounter.weight;
+        }
+        None
+    }
+}
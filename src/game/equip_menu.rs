@@ -0,0 +1,333 @@
+use super::battle::Stats;
+use super::equipment::SLOTS;
+use super::item::Items;
+use super::state::GameState;
+use crate::engine::{
+    Action, KeyState, ListItem, Rect, Renderer, SpriteSheetStore, Ui, UiEvent, WidgetId,
+    LINE_HEIGHT, PADDING, TEXT_COLOR,
+};
+
+use anyhow::Result;
+use std::rc::Rc;
+
+const CHOICE_ROWS: i16 = 9;
+const STAT_NAMES: [&str; 3] = ["Attack", "Defense", "Agility"];
+const BETTER_COLOR: &str = "#40c040";
+const WORSE_COLOR: &str = "#e04040";
+
+// Picks a member, then a slot, then what to wear there, comparing stats
+// before anything changes hands.
+pub struct EquipMenu {
+    items: Rc<Items>,
+    ui: Ui,
+    members: WidgetId,
+    slots_window: WidgetId,
+    slots: WidgetId,
+    choices_window: WidgetId,
+    choices: WidgetId,
+    compare: WidgetId,
+    values: Vec<WidgetId>,
+    info: WidgetId,
+    member: usize,
+    slot: usize,
+    // What each row of `choices` puts on; `None` takes the slot off.
+    options: Vec<Option<String>>,
+    // Shown in place of the description until a button is pressed.
+    message: bool,
+}
+impl EquipMenu {
+    pub fn new(items: Rc<Items>, state: &GameState) -> Self {
+        let count = state.party.len().max(1) as i16;
+        let mut ui = Ui::new();
+        let window = |ui: &mut Ui, x: i16, y: i16, w: i16, rows: i16| {
+            ui.window(
+                None,
+                Rect {
+                    x,
+                    y,
+                    w,
+                    h: LINE_HEIGHT * rows + PADDING * 2,
+                },
+            )
+        };
+        let list = |ui: &mut Ui, window: WidgetId, w: i16, rows: i16, items: Vec<ListItem>| {
+            ui.list(
+                Some(window),
+                Rect {
+                    x: PADDING,
+                    y: PADDING,
+                    w: w - PADDING * 2,
+                    h: LINE_HEIGHT * rows,
+                },
+                items,
+                1,
+            )
+        };
+        let members_window = window(&mut ui, 16, 16, 160, count);
+        let members = list(
+            &mut ui,
+            members_window,
+            160,
+            count,
+            state
+                .party
+                .iter()
+                .map(|member| ListItem::new(&member.fighter.name))
+                .collect(),
+        );
+        let slots_window = window(&mut ui, 184, 16, 280, SLOTS.len() as i16);
+        let slots = list(&mut ui, slots_window, 280, SLOTS.len() as i16, Vec::new());
+        ui.set_visible(slots_window, false);
+        let choices_window = window(&mut ui, 184, 144, 280, CHOICE_ROWS);
+        let choices = list(&mut ui, choices_window, 280, CHOICE_ROWS, Vec::new());
+        ui.set_visible(choices_window, false);
+
+        let compare = window(&mut ui, 16, 144, 160, STAT_NAMES.len() as i16);
+        let row = |index: usize, x: i16, w: i16| Rect {
+            x: PADDING + x,
+            y: PADDING + LINE_HEIGHT * index as i16,
+            w,
+            h: LINE_HEIGHT,
+        };
+        let values = STAT_NAMES
+            .iter()
+            .enumerate()
+            .map(|(index, stat)| {
+                ui.label(Some(compare), row(index, 0, 72), stat);
+                ui.label(Some(compare), row(index, 72, 64), "")
+            })
+            .collect();
+        ui.set_visible(compare, false);
+
+        let info_window = window(&mut ui, 16, 392, 448, 2);
+        let info = ui.label(
+            Some(info_window),
+            Rect {
+                x: PADDING,
+                y: PADDING,
+                w: 448 - PADDING * 2,
+                h: LINE_HEIGHT * 2,
+            },
+            "",
+        );
+        ui.focus(members);
+        Self {
+            items,
+            ui,
+            members,
+            slots_window,
+            slots,
+            choices_window,
+            choices,
+            compare,
+            values,
+            info,
+            member: 0,
+            slot: 0,
+            options: Vec::new(),
+            message: false,
+        }
+    }
+
+    fn show_slots(&mut self, state: &GameState) -> Result<()> {
+        let equipment = &state.party[self.member].equipment;
+        let items = SLOTS
+            .iter()
+            .map(|slot| {
+                ListItem::new(&format!(
+                    "{}: {}",
+                    slot.name(),
+                    equipment.get(*slot).unwrap_or("-")
+                ))
+            })
+            .collect();
+        self.ui.set_items(self.slots, items)?;
+        self.ui.set_visible(self.slots_window, true);
+        Ok(())
+    }
+
+    // The member's equipment for the slot, with whatever is worn first so
+    // that it can be taken off. Pieces of other classes are listed but
+    // cannot be chosen.
+    fn show_choices(&mut self, state: &GameState) -> Result<()> {
+        let member = &state.party[self.member];
+        let slot = SLOTS[self.slot];
+        self.options = vec![None];
+        let mut items = vec![if member.equipment.get(slot).is_some() {
+            ListItem::new("Take off")
+        } else {
+            ListItem::disabled("Take off")
+        }];
+        for stack in &member.bag.stacks {
+            if self.options.contains(&Some(stack.item.clone())) {
+                continue;
+            }
+            let Some(data) = self.items.get(&stack.item) else {
+                continue;
+            };
+            if data.kind.slot() != Some(slot) {
+                continue;
+            }
+            self.options.push(Some(stack.item.clone()));
+            items.push(if data.equippable_by(&member.class) {
+                ListItem::new(&stack.item)
+            } else {
+                ListItem::disabled(&stack.item)
+            });
+        }
+        self.ui.set_items(self.choices, items)?;
+        // Start on the first thing that can be worn.
+        let list = self.ui.list_mut(self.choices)?;
+        list.cursor = list.items.iter().position(|item| item.enabled).unwrap_or(0);
+        self.ui.set_visible(self.choices_window, true);
+        self.ui.set_visible(self.compare, true);
+        Ok(())
+    }
+
+    fn close_choices(&mut self) {
+        self.ui.set_visible(self.choices_window, false);
+        self.ui.set_visible(self.compare, false);
+        while self.ui.focused() != Some(self.slots) && self.ui.blur().is_some() {}
+    }
+
+    // Current stats against those with the highlighted choice worn.
+    fn preview(&mut self, state: &GameState) -> Result<()> {
+        let member = &state.party[self.member];
+        let cursor = self.ui.list_mut(self.choices)?.cursor;
+        let option = self.options.get(cursor).cloned().flatten();
+        let mut equipment = member.equipment.clone();
+        equipment.put(SLOTS[self.slot], option);
+        let now = member.stats(&self.items);
+        let then = equipment.apply(&member.fighter.stats, &self.items);
+        let pairs = |stats: &Stats| [stats.attack, stats.defense, stats.agility];
+        for ((id, now), then) in self.values.iter().zip(pairs(&now)).zip(pairs(&then)) {
+            let label = self.ui.label_mut(*id)?;
+            label.text = format!("{} > {}", now, then);
+            label.color = if then > now {
+                BETTER_COLOR
+            } else if then < now {
+                WORSE_COLOR
+            } else {
+                TEXT_COLOR
+            }
+            .to_string();
+        }
+        Ok(())
+    }
+
+    fn say(&mut self, text: &str) -> Result<()> {
+        self.ui.set_text(self.info, text)?;
+        self.message = true;
+        Ok(())
+    }
+
+    // False once the player backs out.
+    pub fn update(&mut self, key_state: &KeyState, state: &mut GameState) -> Result<bool> {
+        if self.message {
+            let pressed = key_state.is_action_just_pressed(Action::Confirm)
+                || key_state.is_action_just_pressed(Action::Cancel);
+            if pressed {
+                self.message = false;
+            }
+            return Ok(true);
+        }
+        match self.ui.update(key_state) {
+            Some(UiEvent::Selected(id, index)) if id == self.members => {
+                self.member = index;
+                self.show_slots(state)?;
+                self.ui.focus(self.slots);
+            }
+            Some(UiEvent::Cancelled(id)) if id == self.members => return Ok(false),
+            Some(UiEvent::Selected(id, index)) if id == self.slots => {
+                self.slot = index;
+                self.show_choices(state)?;
+                if self.options.len() == 1
+                    && state.party[self.member]
+                        .equipment
+                        .get(SLOTS[index])
+                        .is_none()
+                {
+                    self.close_choices();
+                    let name = state.party[self.member].fighter.name.clone();
+                    let slot = SLOTS[index].name().to_lowercase();
+                    self.say(&format!("{} has no {} to put on.", name, slot))?;
+                } else {
+                    self.ui.focus(self.choices);
+                }
+            }
+            Some(UiEvent::Cancelled(id)) if id == self.slots => {
+                self.ui.set_visible(self.slots_window, false);
+                self.ui.blur();
+            }
+            Some(UiEvent::Selected(id, index)) if id == self.choices => {
+                let option = self.options.get(index).cloned().flatten();
+                let text = self.equip(option, state);
+                self.close_choices();
+                self.show_slots(state)?;
+                self.say(&text)?;
+            }
+            Some(UiEvent::Cancelled(id)) if id == self.choices => self.close_choices(),
+            _ => {}
+        }
+        if self.ui.focused() == Some(self.choices) {
+            self.preview(state)?;
+        }
+        if !self.message {
+            let description = match self.ui.focused() {
+                Some(id) if id == self.choices => {
+                    let cursor = self.ui.list_mut(self.choices)?.cursor;
+                    let member = &state.party[self.member];
+                    self.options
+                        .get(cursor)
+                        .cloned()
+                        .flatten()
+                        .and_then(|item| self.items.get(&item))
+                        .map(|data| {
+                            if data.equippable_by(&member.class) {
+                                data.description.clone()
+                            } else {
+                                format!(
+                                    "{}\n{} cannot wear this.",
+                                    data.description, member.fighter.name
+                                )
+                            }
+                        })
+                }
+                _ => None,
+            };
+            self.ui
+                .set_text(self.info, description.as_deref().unwrap_or(""))?;
+        }
+        Ok(true)
+    }
+
+    // Swaps `item` into the slot, or empties it, moving whatever was worn
+    // into the bag.
+    fn equip(&self, item: Option<String>, state: &mut GameState) -> String {
+        let slot = SLOTS[self.slot];
+        let member = &mut state.party[self.member];
+        let name = member.fighter.name.clone();
+        if let Some(item) = &item {
+            member.bag.remove(item);
+        }
+        let worn = member.equipment.get(slot).map(str::to_string);
+        if let Some(worn) = &worn {
+            if !member.bag.add(worn, self.items.stack_limit(worn)) {
+                if let Some(item) = &item {
+                    member.bag.add(item, self.items.stack_limit(item));
+                }
+                return format!("{} has no room to take off the {}.", name, worn);
+            }
+        }
+        member.equipment.put(slot, item.clone());
+        match (item, worn) {
+            (Some(item), _) => format!("{} puts on the {}.", name, item),
+            (None, Some(worn)) => format!("{} takes off the {}.", name, worn),
+            (None, None) => String::new(),
+        }
+    }
+
+    pub fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
+        self.ui.draw(renderer, sprite_sheet_store)
+    }
+}
@@ -0,0 +1,90 @@
+use super::battle::Stats;
+use super::item::Items;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Slot {
+    Weapon,
+    Armor,
+    Shield,
+    Helm,
+}
+// In menu order, which is also the order the pieces are drawn over the body.
+pub const SLOTS: [Slot; 4] = [Slot::Armor, Slot::Weapon, Slot::Shield, Slot::Helm];
+impl Slot {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Slot::Weapon => "Weapon",
+            Slot::Armor => "Armor",
+            Slot::Shield => "Shield",
+            Slot::Helm => "Helm",
+        }
+    }
+}
+
+// Added to a member's own stats while the item is worn.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Bonus {
+    pub attack: i32,
+    pub defense: i32,
+    pub agility: i32,
+}
+impl Bonus {
+    pub fn is_empty(&self) -> bool {
+        *self == Bonus::default()
+    }
+}
+
+// What a member is wearing, by item name.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Equipment {
+    pub weapon: Option<String>,
+    pub armor: Option<String>,
+    pub shield: Option<String>,
+    pub helm: Option<String>,
+}
+impl Equipment {
+    pub fn get(&self, slot: Slot) -> Option<&str> {
+        match slot {
+            Slot::Weapon => self.weapon.as_deref(),
+            Slot::Armor => self.armor.as_deref(),
+            Slot::Shield => self.shield.as_deref(),
+            Slot::Helm => self.helm.as_deref(),
+        }
+    }
+
+    // Puts `item` on, or takes the slot off with `None`; returns what was there.
+    pub fn put(&mut self, slot: Slot, item: Option<String>) -> Option<String> {
+        let worn = match slot {
+            Slot::Weapon => &mut self.weapon,
+            Slot::Armor => &mut self.armor,
+            Slot::Shield => &mut self.shield,
+            Slot::Helm => &mut self.helm,
+        };
+        std::mem::replace(worn, item)
+    }
+
+    pub fn worn(&self) -> impl Iterator<Item = &str> {
+        SLOTS.iter().filter_map(|slot| self.get(*slot))
+    }
+
+    // `stats` with the bonuses of everything worn added.
+    pub fn apply(&self, stats: &Stats, items: &Items) -> Stats {
+        let mut stats = stats.clone();
+        for data in self.worn().filter_map(|item| items.get(item)) {
+            stats.attack += data.bonus.attack;
+            stats.defense += data.bonus.defense;
+            stats.agility += data.bonus.agility;
+        }
+        stats
+    }
+
+    // Overlays for the field sprite, bottom first.
+    pub fn layers<'a>(&'a self, items: &'a Items) -> impl Iterator<Item = &'a str> {
+        self.worn()
+            .filter_map(|item| items.get(item)?.sprite_source.as_deref())
+    }
+}
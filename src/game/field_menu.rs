@@ -0,0 +1,360 @@
+use super::battle::BattleSettings;
+use super::bestiary::BestiaryScreen;
+use super::equip_menu::EquipMenu;
+use super::interaction::{InteractionHandler, InteractionStatus};
+use super::item_menu::ItemMenu;
+use super::party::Member;
+use super::state::GameState;
+use crate::engine::{
+    Action, Align, KeyState, ListItem, Rect, Renderer, SpriteSheetStore, Ui, UiEvent, WidgetId,
+    LINE_HEIGHT, PADDING,
+};
+
+use anyhow::Result;
+use std::rc::Rc;
+
+const STATUS: usize = 0;
+const ITEM: usize = 1;
+const EQUIP: usize = 2;
+const BESTIARY: usize = 3;
+const SEARCH: usize = 4;
+const CLOSE: usize = 5;
+const HP_COLOR: &str = "#40c040";
+const MP_COLOR: &str = "#4080e0";
+const STATUS_ROWS: i16 = 9;
+const STAT_NAMES: [&str; 5] = ["Attack", "Defense", "Agility", "EXP", "Next"];
+
+// The status window's widgets that change with the member shown.
+struct StatusWidgets {
+    name: WidgetId,
+    class: WidgetId,
+    hp: WidgetId,
+    hp_text: WidgetId,
+    mp: WidgetId,
+    mp_text: WidgetId,
+    values: Vec<WidgetId>,
+}
+
+// The command window opened with Cancel while walking around.
+pub struct FieldMenu {
+    ui: Ui,
+    commands: WidgetId,
+    members: WidgetId,
+    members_window: WidgetId,
+    status: WidgetId,
+    status_widgets: StatusWidgets,
+    notice: WidgetId,
+    notice_text: WidgetId,
+    settings: Option<Rc<BattleSettings>>,
+    bestiary: Option<BestiaryScreen>,
+    item_menu: Option<ItemMenu>,
+    equip_menu: Option<EquipMenu>,
+}
+impl FieldMenu {
+    pub fn new(settings: Option<Rc<BattleSettings>>, state: &GameState) -> Self {
+        let mut ui = Ui::new();
+        let commands = ui.window(
+            None,
+            Rect {
+                x: 16,
+                y: 16,
+                w: 224,
+                h: LINE_HEIGHT * 3 + PADDING * 2,
+            },
+        );
+        let list = ui.list(
+            Some(commands),
+            Rect {
+                x: PADDING,
+                y: PADDING,
+                w: 224 - PADDING * 2,
+                h: LINE_HEIGHT * 3,
+            },
+            vec![
+                ListItem::new("Status"),
+                if settings.is_some() {
+                    ListItem::new("Item")
+                } else {
+                    ListItem::disabled("Item")
+                },
+                if settings.is_some() {
+                    ListItem::new("Equip")
+                } else {
+                    ListItem::disabled("Equip")
+                },
+                if settings.is_some() {
+                    ListItem::new("Bestiary")
+                } else {
+                    ListItem::disabled("Bestiary")
+                },
+                ListItem::new("Search"),
+                ListItem::new("Close"),
+            ],
+            2,
+        );
+        ui.focus(list);
+
+        // Picking whose status to show comes first.
+        let count = state.party.len().max(1) as i16;
+        let members_window = ui.window(
+            None,
+            Rect {
+                x: 16,
+                y: 128,
+                w: 160,
+                h: LINE_HEIGHT * count + PADDING * 2,
+            },
+        );
+        let members = ui.list(
+            Some(members_window),
+            Rect {
+                x: PADDING,
+                y: PADDING,
+                w: 160 - PADDING * 2,
+                h: LINE_HEIGHT * count,
+            },
+            state
+                .party
+                .iter()
+                .map(|member| ListItem::new(&member.fighter.name))
+                .collect(),
+            1,
+        );
+        ui.set_visible(members_window, false);
+
+        let status = ui.window(
+            None,
+            Rect {
+                x: 248,
+                y: 16,
+                w: 216,
+                h: LINE_HEIGHT * STATUS_ROWS + PADDING * 2,
+            },
+        );
+        let row = |index: i16, x: i16, w: i16| Rect {
+            x: PADDING + x,
+            y: PADDING + LINE_HEIGHT * index,
+            w,
+            h: LINE_HEIGHT,
+        };
+        let bar = |index: i16| Rect {
+            h: 12,
+            y: PADDING + LINE_HEIGHT * index + 4,
+            ..row(index, 32, 88)
+        };
+        let name = ui.label(Some(status), row(0, 0, 192), "");
+        let class = ui.label(Some(status), row(1, 0, 192), "");
+        ui.label(Some(status), row(2, 0, 32), "HP");
+        let hp = ui.gauge(Some(status), bar(2), 0, 0, HP_COLOR);
+        let hp_text = ui.label(Some(status), row(2, 128, 64), "");
+        ui.label(Some(status), row(3, 0, 32), "MP");
+        let mp = ui.gauge(Some(status), bar(3), 0, 0, MP_COLOR);
+        let mp_text = ui.label(Some(status), row(3, 128, 64), "");
+        let values = STAT_NAMES
+            .iter()
+            .enumerate()
+            .map(|(index, stat)| {
+                let index = 4 + index as i16;
+                ui.label(Some(status), row(index, 0, 96), stat);
+                ui.label(Some(status), row(index, 96, 96), "")
+            })
+            .collect();
+        let status_widgets = StatusWidgets {
+            name,
+            class,
+            hp,
+            hp_text,
+            mp,
+            mp_text,
+            values,
+        };
+        ui.set_visible(status, false);
+
+        let notice = ui.window(
+            None,
+            Rect {
+                x: 16,
+                y: 128,
+                w: 224,
+                h: LINE_HEIGHT * 2 + PADDING * 2,
+            },
+        );
+        let notice_text = ui.label(
+            Some(notice),
+            Rect {
+                x: PADDING,
+                y: PADDING,
+                w: 224 - PADDING * 2,
+                h: LINE_HEIGHT * 2,
+            },
+            "",
+        );
+        ui.set_visible(notice, false);
+
+        let gold_window = ui.window(
+            None,
+            Rect {
+                x: 16,
+                y: 416,
+                w: 160,
+                h: LINE_HEIGHT + PADDING * 2,
+            },
+        );
+        let gold = ui.label(
+            Some(gold_window),
+            Rect {
+                x: PADDING,
+                y: PADDING,
+                w: 160 - PADDING * 2,
+                h: LINE_HEIGHT,
+            },
+            &format!("{} G", state.gold),
+        );
+        if let Ok(label) = ui.label_mut(gold) {
+            label.align = Align::Right;
+        }
+
+        Self {
+            ui,
+            commands: list,
+            members,
+            members_window,
+            status,
+            status_widgets,
+            notice,
+            notice_text,
+            settings,
+            bestiary: None,
+            item_menu: None,
+            equip_menu: None,
+        }
+    }
+
+    fn show_status(&mut self, member: &Member) -> Result<()> {
+        let widgets = &self.status_widgets;
+        let fighter = &member.fighter;
+        let stats = match &self.settings {
+            Some(settings) => member.stats(&settings.items),
+            None => fighter.stats.clone(),
+        };
+        let next = self
+            .settings
+            .as_ref()
+            .and_then(|settings| settings.party.to_next(member))
+            .map_or("-".to_string(), |exp| exp.to_string());
+        self.ui.set_text(widgets.name, &fighter.name)?;
+        self.ui.set_text(
+            widgets.class,
+            &format!("{}  Lv {}", member.class, fighter.level),
+        )?;
+        self.ui.set_gauge(widgets.hp, fighter.hp, stats.max_hp)?;
+        self.ui
+            .set_text(widgets.hp_text, &format!("{}/{}", fighter.hp, stats.max_hp))?;
+        self.ui.set_gauge(widgets.mp, fighter.mp, stats.max_mp)?;
+        self.ui
+            .set_text(widgets.mp_text, &format!("{}/{}", fighter.mp, stats.max_mp))?;
+        let values = [
+            stats.attack.to_string(),
+            stats.defense.to_string(),
+            stats.agility.to_string(),
+            member.exp.to_string(),
+            next,
+        ];
+        for (id, value) in widgets.values.iter().zip(values) {
+            self.ui.set_text(*id, &value)?;
+        }
+        self.ui.set_visible(self.status, true);
+        Ok(())
+    }
+
+    fn show_notice(&mut self, text: &str) -> Result<()> {
+        self.ui.set_text(self.notice_text, text)?;
+        self.ui.set_visible(self.notice, true);
+        Ok(())
+    }
+}
+
+impl InteractionHandler for FieldMenu {
+    fn update(&mut self, key_state: &KeyState, state: &mut GameState) -> Result<InteractionStatus> {
+        if let Some(bestiary) = &mut self.bestiary {
+            if !bestiary.update(key_state)? {
+                self.bestiary = None;
+            }
+            return Ok(InteractionStatus::Continue);
+        }
+        if let Some(item_menu) = &mut self.item_menu {
+            if !item_menu.update(key_state, state)? {
+                self.item_menu = None;
+            }
+            return Ok(InteractionStatus::Continue);
+        }
+        if let Some(equip_menu) = &mut self.equip_menu {
+            if !equip_menu.update(key_state, state)? {
+                self.equip_menu = None;
+            }
+            return Ok(InteractionStatus::Continue);
+        }
+        // Read-only windows close on any button and hand focus back to the list.
+        let pressed = key_state.is_action_just_pressed(Action::Confirm)
+            || key_state.is_action_just_pressed(Action::Cancel);
+        if pressed && (self.ui.is_visible(self.status) || self.ui.is_visible(self.notice)) {
+            self.ui.set_visible(self.status, false);
+            self.ui.set_visible(self.notice, false);
+            return Ok(InteractionStatus::Continue);
+        }
+        match self.ui.update(key_state) {
+            Some(UiEvent::Selected(id, STATUS)) if id == self.commands => {
+                self.ui.set_visible(self.members_window, true);
+                self.ui.focus(self.members);
+            }
+            Some(UiEvent::Selected(id, ITEM)) if id == self.commands => {
+                if let Some(settings) = &self.settings {
+                    self.item_menu = Some(ItemMenu::new(settings.items.clone(), state));
+                }
+            }
+            Some(UiEvent::Selected(id, EQUIP)) if id == self.commands => {
+                if let Some(settings) = &self.settings {
+                    self.equip_menu = Some(EquipMenu::new(settings.items.clone(), state));
+                }
+            }
+            Some(UiEvent::Selected(id, BESTIARY)) if id == self.commands => {
+                if let Some(settings) = &self.settings {
+                    self.bestiary = Some(BestiaryScreen::new(&settings.monsters, &state.bestiary));
+                }
+            }
+            Some(UiEvent::Selected(id, SEARCH)) if id == self.commands => {
+                self.show_notice("There is nothing here.")?
+            }
+            Some(UiEvent::Selected(id, CLOSE)) if id == self.commands => {
+                return Ok(InteractionStatus::Finished)
+            }
+            Some(UiEvent::Cancelled(id)) if id == self.commands => {
+                return Ok(InteractionStatus::Finished)
+            }
+            Some(UiEvent::Selected(id, index)) if id == self.members => {
+                if let Some(member) = state.party.get(index) {
+                    self.show_status(member)?;
+                }
+            }
+            Some(UiEvent::Cancelled(id)) if id == self.members => {
+                self.ui.set_visible(self.members_window, false);
+                self.ui.blur();
+            }
+            _ => {}
+        }
+        Ok(InteractionStatus::Continue)
+    }
+
+    fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
+        if let Some(bestiary) = &self.bestiary {
+            return bestiary.draw(renderer, sprite_sheet_store);
+        }
+        if let Some(item_menu) = &self.item_menu {
+            return item_menu.draw(renderer, sprite_sheet_store);
+        }
+        if let Some(equip_menu) = &self.equip_menu {
+            return equip_menu.draw(renderer, sprite_sheet_store);
+        }
+        self.ui.draw(renderer, sprite_sheet_store)
+    }
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Story switches and counters shared by dialogue, events and shops. A flag that
+// was never set reads as 0.
+#[derive(Deserialize, Serialize)]
+pub struct Flags {
+    values: HashMap<String, i32>,
+}
+impl Flags {
+    pub fn new() -> Self {
+        Self {
+            values: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> i32 {
+        self.values.get(name).copied().unwrap_or(0)
+    }
+
+    pub fn check(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Set(name) => self.get(name) != 0,
+            Condition::Unset(name) => self.get(name) == 0,
+            Condition::Equals(name, value) => self.get(name) == *value,
+            Condition::AtLeast(name, value) => self.get(name) >= *value,
+        }
+    }
+
+    pub fn apply(&mut self, effect: &Effect) {
+        match effect {
+            Effect::Set(name, value) => {
+                self.values.insert(name.clone(), *value);
+            }
+            Effect::Add(name, value) => {
+                *self.values.entry(name.clone()).or_insert(0) += value;
+            }
+            Effect::Clear(name) => {
+                self.values.remove(name);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    Set(String),
+    Unset(String),
+    Equals(String, i32),
+    AtLeast(String, i32),
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Effect {
+    Set(String, i32),
+    Add(String, i32),
+    Clear(String),
+}
@@ -0,0 +1,379 @@
+use super::encounter::Encounters;
+use super::lighting::Darkness;
+use super::map::Warp;
+use super::{TILE_HEIGHT, TILE_WIDTH};
+use crate::engine::{
+    Point, Rect, Renderer, SpriteSheet, SpriteSheetStore, Surface, TransitionSpec,
+};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
+
+pub const GROUND: &str = "ground";
+pub const DOOR_CLOSED: char = '+';
+pub const DOOR_OPEN: char = '\'';
+const EMPTY: char = ' ';
+// Map colours for tiles whose legend entry has none.
+const OPEN_COLOR: &str = "#8f974a";
+const SOLID_COLOR: &str = "#595652";
+
+// A floor as written in map files, or as built by the dungeon generator.
+#[derive(Deserialize)]
+pub struct FloorData {
+    pub name: String,
+    pub tileset: String,
+    pub legend: HashMap<char, Tile>,
+    pub layers: Vec<LayerData>,
+    pub entrance: Option<TransitionSpec>,
+    pub darkness: Option<Darkness>,
+    #[serde(default)]
+    pub warps: Vec<Warp>,
+    pub encounters: Option<Encounters>,
+}
+
+#[derive(Deserialize)]
+pub struct LayerData {
+    pub name: String,
+    #[serde(default)]
+    pub above: bool,
+    pub rows: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Tile {
+    pub frame: String,
+    #[serde(default)]
+    pub solid: bool,
+    // Blocks the field of view on dark floors.
+    #[serde(default)]
+    pub opaque: bool,
+    // How the tile looks on the minimap.
+    #[serde(default)]
+    pub map_color: Option<String>,
+    // Roads, bridges and the like, where monsters never attack.
+    #[serde(default)]
+    pub no_encounters: bool,
+}
+
+// Each layer is drawn once into an offscreen surface the size of the whole
+// floor; a frame then costs one blit of the part the camera sees. Tiles
+// changed with `set_tile` are redrawn into the surface on the next draw.
+struct Layer {
+    name: String,
+    above: bool,
+    tiles: Vec<char>,
+    cache: RefCell<Option<Surface>>,
+    dirty: RefCell<Vec<Point>>,
+}
+
+pub struct Floor {
+    pub name: String,
+    pub width: i16,
+    pub height: i16,
+    // Played when the player arrives on this floor.
+    pub entrance: Option<TransitionSpec>,
+    // Only set on floors that are dark outside the hero's light.
+    pub darkness: Option<Darkness>,
+    pub warps: Vec<Warp>,
+    // Floors without encounters are safe everywhere.
+    pub encounters: Option<Encounters>,
+    tileset: String,
+    legend: HashMap<char, Tile>,
+    layers: Vec<Layer>,
+    // Kept up to date by `set_tile` so collision checks need not scan layers.
+    solid: HashSet<Point>,
+}
+impl Floor {
+    pub fn load(json: &[u8]) -> Result<Self> {
+        Self::from_data(serde_json::from_slice(json)?)
+    }
+
+    // Layers are rows of legend keys; a space leaves the tile empty.
+    pub fn from_data(data: FloorData) -> Result<Self> {
+        let height = data
+            .layers
+            .first()
+            .map(|layer| layer.rows.len())
+            .ok_or_else(|| anyhow!("floor {} has no layers", data.name))?;
+        let width = data.layers[0]
+            .rows
+            .first()
+            .map(|row| row.chars().count())
+            .unwrap_or(0);
+        let mut layers = Vec::new();
+        for layer in data.layers {
+            if layer.rows.len() != height {
+                return Err(anyhow!(
+                    "layer {} of floor {} has {} rows, expected {}",
+                    layer.name,
+                    data.name,
+                    layer.rows.len(),
+                    height
+                ));
+            }
+            let mut tiles = Vec::new();
+            for (y, row) in layer.rows.iter().enumerate() {
+                if row.chars().count() != width {
+                    return Err(anyhow!(
+                        "row {} of layer {} in floor {} is not {} tiles wide",
+                        y,
+                        layer.name,
+                        data.name,
+                        width
+                    ));
+                }
+                if let Some(key) = row
+                    .chars()
+                    .find(|key| *key != EMPTY && !data.legend.contains_key(key))
+                {
+                    return Err(anyhow!(
+                        "unknown tile {:?} in row {} of layer {} in floor {}",
+                        key,
+                        y,
+                        layer.name,
+                        data.name
+                    ));
+                }
+                tiles.extend(row.chars());
+            }
+            layers.push(Layer {
+                name: layer.name,
+                above: layer.above,
+                tiles,
+                cache: RefCell::new(None),
+                dirty: RefCell::new(Vec::new()),
+            });
+        }
+        let mut floor = Self {
+            name: data.name,
+            width: width as i16,
+            height: height as i16,
+            entrance: data.entrance,
+            darkness: data.darkness,
+            warps: data.warps,
+            encounters: data.encounters,
+            tileset: data.tileset,
+            legend: data.legend,
+            layers,
+            solid: HashSet::new(),
+        };
+        for y in 0..floor.height {
+            for x in 0..floor.width {
+                floor.update_solid(&Point { x, y });
+            }
+        }
+        Ok(floor)
+    }
+
+    fn index(&self, tile: &Point) -> Option<usize> {
+        if tile.x < 0 || tile.y < 0 || tile.x >= self.width || tile.y >= self.height {
+            return None;
+        }
+        Some((tile.y * self.width + tile.x) as usize)
+    }
+
+    pub fn solid_tiles(&self) -> &HashSet<Point> {
+        &self.solid
+    }
+
+    fn update_solid(&mut self, tile: &Point) {
+        let Some(index) = self.index(tile) else {
+            return;
+        };
+        let solid = self.layers.iter().any(|layer| {
+            self.legend
+                .get(&layer.tiles[index])
+                .is_some_and(|tile| tile.solid)
+        });
+        if solid {
+            self.solid.insert(*tile);
+        } else {
+            self.solid.remove(tile);
+        }
+    }
+
+    // Anything outside the floor is solid.
+    pub fn is_solid(&self, tile: &Point) -> bool {
+        self.index(tile).is_none() || self.solid.contains(tile)
+    }
+
+    // Anything outside the floor is opaque so sight stops at its edge.
+    pub fn is_opaque(&self, tile: &Point) -> bool {
+        let Some(index) = self.index(tile) else {
+            return true;
+        };
+        self.layers.iter().any(|layer| {
+            self.legend
+                .get(&layer.tiles[index])
+                .is_some_and(|tile| tile.opaque)
+        })
+    }
+
+    pub fn allows_encounters(&self, tile: &Point) -> bool {
+        let Some(index) = self.index(tile) else {
+            return false;
+        };
+        !self.layers.iter().any(|layer| {
+            self.legend
+                .get(&layer.tiles[index])
+                .is_some_and(|tile| tile.no_encounters)
+        })
+    }
+
+    pub fn warp_at(&self, tile: &Point) -> Option<&Warp> {
+        self.warps.iter().find(|warp| warp.tile == *tile)
+    }
+
+    // The colour of the topmost tile, so roofs show rather than the floors
+    // under them; `None` where every layer is empty.
+    pub fn map_color(&self, tile: &Point) -> Option<&str> {
+        let index = self.index(tile)?;
+        let kind = self
+            .layers
+            .iter()
+            .rev()
+            .find_map(|layer| self.legend.get(&layer.tiles[index]))?;
+        Some(match &kind.map_color {
+            Some(color) => color,
+            None if kind.solid => SOLID_COLOR,
+            None => OPEN_COLOR,
+        })
+    }
+
+    pub fn set_tile(&mut self, layer_name: &str, tile: &Point, key: char) -> Result<()> {
+        if key != EMPTY && !self.legend.contains_key(&key) {
+            return Err(anyhow!("unknown tile {:?} in floor {}", key, self.name));
+        }
+        let index = self
+            .index(tile)
+            .ok_or_else(|| anyhow!("tile {:?} is outside floor {}", tile, self.name))?;
+        let layer = self
+            .layers
+            .iter_mut()
+            .find(|layer| layer.name == layer_name)
+            .ok_or_else(|| anyhow!("no layer {} in floor {}", layer_name, self.name))?;
+        if layer.tiles[index] != key {
+            layer.tiles[index] = key;
+            layer.dirty.get_mut().push(*tile);
+            self.update_solid(tile);
+        }
+        Ok(())
+    }
+
+    // The screen-sized region centred on `focus`, kept inside the floor.
+    pub fn camera(&self, focus: &Point, screen: &Rect) -> Rect {
+        let clamp = |center: i16, view: i16, size: i16| (center - view / 2).min(size - view).max(0);
+        Rect {
+            x: clamp(focus.x + TILE_WIDTH / 2, screen.w, self.width * TILE_WIDTH),
+            y: clamp(
+                focus.y + TILE_HEIGHT / 2,
+                screen.h,
+                self.height * TILE_HEIGHT,
+            ),
+            w: screen.w.min(self.width * TILE_WIDTH),
+            h: screen.h.min(self.height * TILE_HEIGHT),
+        }
+    }
+
+    pub fn draw_below(
+        &self,
+        renderer: &Renderer,
+        sprite_sheet_store: &SpriteSheetStore,
+        camera: &Rect,
+    ) -> Result<()> {
+        self.draw_layers(renderer, sprite_sheet_store, camera, false)
+    }
+
+    // Roofs and treetops, drawn after the entities so they can walk under them.
+    pub fn draw_above(
+        &self,
+        renderer: &Renderer,
+        sprite_sheet_store: &SpriteSheetStore,
+        camera: &Rect,
+    ) -> Result<()> {
+        self.draw_layers(renderer, sprite_sheet_store, camera, true)
+    }
+
+    fn draw_layers(
+        &self,
+        renderer: &Renderer,
+        sprite_sheet_store: &SpriteSheetStore,
+        camera: &Rect,
+        above: bool,
+    ) -> Result<()> {
+        for layer in self.layers.iter().filter(|layer| layer.above == above) {
+            self.refresh(layer, sprite_sheet_store)?;
+            if let Some(surface) = layer.cache.borrow().as_ref() {
+                renderer.draw_surface(
+                    surface,
+                    camera,
+                    &Point {
+                        x: camera.x,
+                        y: camera.y,
+                    },
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    // Builds the layer's surface once the tileset has loaded, then only
+    // redraws tiles that changed since the last frame.
+    fn refresh(&self, layer: &Layer, sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
+        let tileset = sprite_sheet_store.get(&self.tileset)?;
+        let mut cache = layer.cache.borrow_mut();
+        if cache.is_none() {
+            if !tileset.is_loaded() {
+                return Ok(());
+            }
+            let surface = Surface::new(self.width * TILE_WIDTH, self.height * TILE_HEIGHT)?;
+            layer.dirty.borrow_mut().clear();
+            for y in 0..self.height {
+                for x in 0..self.width {
+                    self.draw_tile(&surface, layer, tileset, &Point { x, y })?;
+                }
+            }
+            *cache = Some(surface);
+        }
+        let Some(surface) = cache.as_ref() else {
+            return Ok(());
+        };
+        for tile in layer.dirty.borrow_mut().drain(..) {
+            surface.clear(&Rect {
+                x: tile.x * TILE_WIDTH,
+                y: tile.y * TILE_HEIGHT,
+                w: TILE_WIDTH,
+                h: TILE_HEIGHT,
+            });
+            self.draw_tile(surface, layer, tileset, &tile)?;
+        }
+        Ok(())
+    }
+
+    fn draw_tile(
+        &self,
+        surface: &Surface,
+        layer: &Layer,
+        tileset: &SpriteSheet,
+        tile: &Point,
+    ) -> Result<()> {
+        let Some(index) = self.index(tile) else {
+            return Ok(());
+        };
+        let Some(kind) = self.legend.get(&layer.tiles[index]) else {
+            return Ok(());
+        };
+        surface.draw_image(
+            tileset,
+            &kind.frame,
+            &Point {
+                x: tile.x * TILE_WIDTH,
+                y: tile.y * TILE_HEIGHT,
+            },
+        )
+    }
+}
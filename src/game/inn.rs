@@ -0,0 +1,150 @@
+use super::interaction::{InteractionHandler, InteractionStatus};
+use super::state::GameState;
+use crate::engine::{
+    Action, Align, KeyState, ListItem, Rect, Renderer, SpriteSheetStore, Ui, UiEvent, WidgetId,
+    LINE_HEIGHT, PADDING,
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+const STAY: usize = 0;
+
+// An inn kept by an NPC, as given in the map file.
+#[derive(Clone, Deserialize)]
+pub struct InnData {
+    // For the whole party, one night.
+    pub price: i32,
+}
+
+enum Stage {
+    Asking,
+    // The night is paid for; the field still has to write the save.
+    Resting,
+    Leaving,
+}
+
+// Offers a night's rest, which heals everyone and saves the game.
+pub struct Inn {
+    keeper: String,
+    price: i32,
+    ui: Ui,
+    choices_window: WidgetId,
+    choices: WidgetId,
+    gold: WidgetId,
+    text: WidgetId,
+    stage: Stage,
+}
+impl Inn {
+    pub fn new(keeper: &str, data: &InnData, state: &GameState) -> Result<Self> {
+        let mut ui = Ui::new();
+        let window = |ui: &mut Ui, x: i16, y: i16, w: i16, rows: i16| {
+            ui.window(
+                None,
+                Rect {
+                    x,
+                    y,
+                    w,
+                    h: LINE_HEIGHT * rows + PADDING * 2,
+                },
+            )
+        };
+        let inside = |w: i16, rows: i16| Rect {
+            x: PADDING,
+            y: PADDING,
+            w: w - PADDING * 2,
+            h: LINE_HEIGHT * rows,
+        };
+        let gold_window = window(&mut ui, 304, 16, 160, 1);
+        let gold = ui.label(
+            Some(gold_window),
+            inside(160, 1),
+            &format!("{} G", state.gold),
+        );
+        ui.label_mut(gold)?.align = Align::Right;
+        let choices_window = window(&mut ui, 16, 296, 120, 2);
+        let choices = ui.list(
+            Some(choices_window),
+            inside(120, 2),
+            vec![ListItem::new("Stay"), ListItem::new("Leave")],
+            1,
+        );
+        let text_window = window(&mut ui, 16, 368, 448, 3);
+        let text = ui.label(
+            Some(text_window),
+            inside(448, 3),
+            &format!(
+                "{}: Welcome, travellers! A night's rest is {} gold. Will you stay?",
+                keeper, data.price
+            ),
+        );
+        ui.focus(choices);
+        Ok(Self {
+            keeper: keeper.to_string(),
+            price: data.price,
+            ui,
+            choices_window,
+            choices,
+            gold,
+            text,
+            stage: Stage::Asking,
+        })
+    }
+
+    fn leave(&mut self, text: &str) -> Result<()> {
+        self.ui.set_text(self.text, text)?;
+        self.ui.set_visible(self.choices_window, false);
+        self.stage = Stage::Leaving;
+        Ok(())
+    }
+}
+
+impl InteractionHandler for Inn {
+    fn update(&mut self, key_state: &KeyState, state: &mut GameState) -> Result<InteractionStatus> {
+        match self.stage {
+            Stage::Asking => {}
+            Stage::Resting => {
+                self.stage = Stage::Leaving;
+                return Ok(InteractionStatus::Save);
+            }
+            Stage::Leaving => {
+                let pressed = key_state.is_action_just_pressed(Action::Confirm)
+                    || key_state.is_action_just_pressed(Action::Cancel);
+                return Ok(if pressed {
+                    InteractionStatus::Finished
+                } else {
+                    InteractionStatus::Continue
+                });
+            }
+        }
+        match self.ui.update(key_state) {
+            Some(UiEvent::Selected(id, STAY)) if id == self.choices => {
+                if state.gold < self.price {
+                    let text = format!("{}: I'm afraid you can't afford a room.", self.keeper);
+                    self.leave(&text)?;
+                } else {
+                    state.gold -= self.price;
+                    for member in &mut state.party {
+                        member.fighter.restore();
+                    }
+                    self.ui.set_text(self.gold, &format!("{} G", state.gold))?;
+                    self.leave(&format!(
+                        "The party rests until morning.\n{}: Good morning! Your journey has been recorded.",
+                        self.keeper
+                    ))?;
+                    self.stage = Stage::Resting;
+                }
+            }
+            Some(UiEvent::Selected(id, _)) | Some(UiEvent::Cancelled(id)) if id == self.choices => {
+                let text = format!("{}: Safe travels!", self.keeper);
+                self.leave(&text)?;
+            }
+            _ => {}
+        }
+        Ok(InteractionStatus::Continue)
+    }
+
+    fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
+        self.ui.draw(renderer, sprite_sheet_store)
+    }
+}
@@ -0,0 +1,83 @@
+use super::battle::Outcome;
+use super::message_window::MessageWindow;
+use super::state::GameState;
+use crate::engine::{KeyState, Renderer, SpriteSheetStore};
+
+use anyhow::Result;
+
+// What the player examined, if anything; kept so the world can be tidied up
+// afterwards.
+#[derive(Clone, Copy)]
+pub enum Target {
+    Npc(usize),
+    Object(usize),
+}
+
+pub enum InteractionStatus {
+    Continue,
+    Finished,
+    // A battle is over and the field has to deal with how it ended.
+    Battle(Outcome),
+    // The field writes a save, then the interaction carries on.
+    Save,
+}
+
+pub trait InteractionHandler {
+    fn update(&mut self, key_state: &KeyState, state: &mut GameState) -> Result<InteractionStatus>;
+    fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()>;
+}
+
+pub struct Interaction {
+    pub target: Option<Target>,
+    pub handler: Box<dyn InteractionHandler>,
+}
+
+// Shows one page at a time and finishes once the last page is confirmed.
+pub struct Message {
+    pages: Vec<String>,
+    window: MessageWindow,
+}
+impl Message {
+    pub fn new(pages: Vec<String>) -> Self {
+        let mut pages = pages;
+        pages.reverse();
+        let window = MessageWindow::new(None, &pages.pop().unwrap_or_default()).centered();
+        Self { pages, window }
+    }
+}
+impl InteractionHandler for Message {
+    fn update(
+        &mut self,
+        key_state: &KeyState,
+        _state: &mut GameState,
+    ) -> Result<InteractionStatus> {
+        if !self.window.update(key_state) {
+            return Ok(InteractionStatus::Continue);
+        }
+        match self.pages.pop() {
+            Some(page) => {
+                self.window = MessageWindow::new(None, &page).centered();
+                Ok(InteractionStatus::Continue)
+            }
+            None => Ok(InteractionStatus::Finished),
+        }
+    }
+    fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
+        self.window.draw(renderer, sprite_sheet_store, true)
+    }
+}
+
+// For interactions whose whole effect happens when they start, like doors.
+pub struct Immediate;
+impl InteractionHandler for Immediate {
+    fn update(
+        &mut self,
+        _key_state: &KeyState,
+        _state: &mut GameState,
+    ) -> Result<InteractionStatus> {
+        Ok(InteractionStatus::Finished)
+    }
+    fn draw(&self, _renderer: &Renderer, _sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
+        Ok(())
+    }
+}
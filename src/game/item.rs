@@ -0,0 +1,300 @@
+use super::battle::Fighter;
+use super::equipment::{Bonus, Slot};
+use super::flags::Effect;
+use super::party::Member;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const ITEMS_FILE: &str = "items.json";
+// Stacks each member can carry.
+pub const BAG_SLOTS: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemKind {
+    Consumable,
+    // Never used up and never thrown away.
+    Key,
+    Weapon,
+    Armor,
+    Shield,
+    Helm,
+}
+impl ItemKind {
+    // Where the item is worn, if it is equipment.
+    pub fn slot(&self) -> Option<Slot> {
+        match self {
+            ItemKind::Consumable | ItemKind::Key => None,
+            ItemKind::Weapon => Some(Slot::Weapon),
+            ItemKind::Armor => Some(Slot::Armor),
+            ItemKind::Shield => Some(Slot::Shield),
+            ItemKind::Helm => Some(Slot::Helm),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Usable {
+    #[default]
+    Anywhere,
+    Field,
+    Battle,
+    // Only ever used by the game itself, like keys on doors.
+    Nowhere,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemTarget {
+    #[default]
+    Nobody,
+    Ally,
+    Enemy,
+}
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemEffect {
+    HealHp(i32),
+    HealMp(i32),
+    Damage(i32),
+    Flag(Effect),
+}
+
+#[derive(Clone, Deserialize)]
+pub struct ItemData {
+    // "a" or "an", for messages.
+    pub article: String,
+    pub description: String,
+    pub kind: ItemKind,
+    // What shops charge; items without one are never bought or sold.
+    pub price: Option<i32>,
+    #[serde(default)]
+    pub usable: Usable,
+    #[serde(default)]
+    pub target: ItemTarget,
+    #[serde(default)]
+    pub effects: Vec<ItemEffect>,
+    // Replaces the usual stack limit.
+    pub max_stack: Option<u16>,
+    // Equipment only: what it adds, who can wear it (anyone if empty) and
+    // the sheet drawn over the wearer's field sprite.
+    #[serde(default)]
+    pub bonus: Bonus,
+    #[serde(default)]
+    pub classes: Vec<String>,
+    pub sprite_source: Option<String>,
+}
+impl ItemData {
+    pub fn usable_in_field(&self) -> bool {
+        self.kind.slot().is_none() && matches!(self.usable, Usable::Anywhere | Usable::Field)
+    }
+
+    pub fn usable_in_battle(&self) -> bool {
+        self.kind.slot().is_none() && matches!(self.usable, Usable::Anywhere | Usable::Battle)
+    }
+
+    pub fn equippable_by(&self, class: &str) -> bool {
+        self.kind.slot().is_some()
+            && (self.classes.is_empty() || self.classes.iter().any(|name| name == class))
+    }
+}
+
+#[derive(Default, Deserialize)]
+pub struct Items {
+    pub stack_limit: u16,
+    items: HashMap<String, ItemData>,
+}
+impl Items {
+    pub fn load() -> Result<Self> {
+        let json = include_bytes!("../asset/items.json");
+        let items: Items =
+            serde_json::from_slice(json).map_err(|error| anyhow!("{}: {}", ITEMS_FILE, error))?;
+        items.validate()?;
+        Ok(items)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ItemData> {
+        self.items.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.items.contains_key(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &ItemData)> {
+        self.items.iter()
+    }
+
+    // The name with its article, as in "You found a Torch!".
+    pub fn a(&self, name: &str) -> String {
+        match self.items.get(name) {
+            Some(data) => format!("{} {}", data.article, name),
+            None => name.to_string(),
+        }
+    }
+
+    pub fn stack_limit(&self, name: &str) -> u16 {
+        match self.items.get(name) {
+            Some(ItemData {
+                kind: ItemKind::Key,
+                ..
+            }) => 1,
+            Some(data) => data.max_stack.unwrap_or(self.stack_limit),
+            None => self.stack_limit,
+        }
+    }
+
+    // Gives one `item` to the first member with room for it, returning who.
+    pub fn stow(&self, party: &mut [Member], item: &str) -> Option<usize> {
+        let limit = self.stack_limit(item);
+        let index = party
+            .iter()
+            .position(|member| member.bag.has_room(item, limit))?;
+        party[index].bag.add(item, limit);
+        Some(index)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.stack_limit == 0 {
+            return Err(anyhow!("{}: stack_limit: must be at least 1", ITEMS_FILE));
+        }
+        for (name, data) in &self.items {
+            let error = |field: &str, message: &str| {
+                Err(anyhow!("{}: {}.{}: {}", ITEMS_FILE, name, field, message))
+            };
+            if data.article.is_empty() {
+                return error("article", "must not be empty");
+            }
+            if data.max_stack == Some(0) {
+                return error("max_stack", "must be at least 1");
+            }
+            match data.price {
+                Some(price) if price <= 0 => return error("price", "must be positive"),
+                Some(_) if data.kind == ItemKind::Key => {
+                    return error("price", "key items cannot be sold")
+                }
+                _ => {}
+            }
+            for (index, effect) in data.effects.iter().enumerate() {
+                let field = format!("effects[{}]", index);
+                let (amount, target) = match effect {
+                    ItemEffect::HealHp(amount) | ItemEffect::HealMp(amount) => {
+                        (*amount, ItemTarget::Ally)
+                    }
+                    ItemEffect::Damage(amount) => (*amount, ItemTarget::Enemy),
+                    ItemEffect::Flag(_) => (1, data.target),
+                };
+                if amount <= 0 {
+                    return error(&field, "must be positive");
+                }
+                if target != data.target {
+                    return error(&field, &format!("needs a target of {:?}", target));
+                }
+            }
+            if data.target == ItemTarget::Enemy && data.usable_in_field() {
+                return error("usable", "items aimed at enemies only work in battle");
+            }
+            if data.kind.slot().is_some() {
+                if !data.effects.is_empty() {
+                    return error("effects", "equipment is worn, not used");
+                }
+                if data.sprite_source.as_deref() == Some("") {
+                    return error("sprite_source", "must not be empty");
+                }
+            } else if !data.bonus.is_empty()
+                || !data.classes.is_empty()
+                || data.sprite_source.is_some()
+            {
+                return error("kind", "only equipment has a bonus, classes or a sprite");
+            }
+        }
+        Ok(())
+    }
+}
+
+// What an item did to whoever it was used on, for messages.
+pub enum Restored {
+    Hp(i32),
+    Mp(i32),
+}
+
+// Applies the healing effects of an item; the rest are up to the caller.
+pub fn restore(data: &ItemData, fighter: &mut Fighter) -> Vec<Restored> {
+    data.effects
+        .iter()
+        .filter_map(|effect| match effect {
+            ItemEffect::HealHp(amount) => Some(Restored::Hp(fighter.heal(*amount))),
+            ItemEffect::HealMp(amount) => Some(Restored::Mp(fighter.recover_mp(*amount))),
+            _ => None,
+        })
+        .collect()
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Stack {
+    pub item: String,
+    pub count: u16,
+}
+
+// One member's belongings: up to `BAG_SLOTS` stacks of one item each.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Bag {
+    pub stacks: Vec<Stack>,
+}
+impl Bag {
+    pub fn count(&self, item: &str) -> u16 {
+        self.stacks
+            .iter()
+            .filter(|stack| stack.item == item)
+            .map(|stack| stack.count)
+            .sum()
+    }
+
+    pub fn has_room(&self, item: &str, limit: u16) -> bool {
+        self.stacks.len() < BAG_SLOTS
+            || self
+                .stacks
+                .iter()
+                .any(|stack| stack.item == item && stack.count < limit)
+    }
+
+    // Tops up a stack of `item` if one has room, or starts a new one.
+    pub fn add(&mut self, item: &str, limit: u16) -> bool {
+        if let Some(stack) = self
+            .stacks
+            .iter_mut()
+            .find(|stack| stack.item == item && stack.count < limit)
+        {
+            stack.count += 1;
+            return true;
+        }
+        if self.stacks.len() >= BAG_SLOTS {
+            return false;
+        }
+        self.stacks.push(Stack {
+            item: item.to_string(),
+            count: 1,
+        });
+        true
+    }
+
+    // Takes one `item` out, from its last stack.
+    pub fn remove(&mut self, item: &str) -> bool {
+        let Some(index) = self.stacks.iter().rposition(|stack| stack.item == item) else {
+            return false;
+        };
+        self.stacks[index].count -= 1;
+        if self.stacks[index].count == 0 {
+            self.stacks.remove(index);
+        }
+        true
+    }
+
+    pub fn discard(&mut self, index: usize) -> Option<Stack> {
+        (index < self.stacks.len()).then(|| self.stacks.remove(index))
+    }
+}
@@ -0,0 +1,326 @@
+use super::item::{self, ItemEffect, ItemKind, ItemTarget, Items, Restored};
+use super::state::GameState;
+use crate::engine::{
+    Action, KeyState, ListItem, Rect, Renderer, SpriteSheetStore, Ui, UiEvent, WidgetId,
+    LINE_HEIGHT, PADDING,
+};
+
+use anyhow::Result;
+use std::rc::Rc;
+
+const USE: usize = 0;
+const GIVE: usize = 1;
+const DISCARD: usize = 2;
+const BAG_ROWS: i16 = 8;
+
+enum Purpose {
+    Use,
+    Give,
+}
+
+// Picks a member, then one of their items, then what to do with it.
+pub struct ItemMenu {
+    items: Rc<Items>,
+    ui: Ui,
+    owners: WidgetId,
+    bag_window: WidgetId,
+    bag: WidgetId,
+    actions_window: WidgetId,
+    actions: WidgetId,
+    targets_window: WidgetId,
+    targets: WidgetId,
+    info: WidgetId,
+    owner: usize,
+    purpose: Option<Purpose>,
+    // Shown in place of the description until a button is pressed.
+    message: bool,
+}
+impl ItemMenu {
+    pub fn new(items: Rc<Items>, state: &GameState) -> Self {
+        let names = || {
+            state
+                .party
+                .iter()
+                .map(|member| ListItem::new(&member.fighter.name))
+                .collect()
+        };
+        let count = state.party.len().max(1) as i16;
+        let mut ui = Ui::new();
+        let window = |ui: &mut Ui, x: i16, y: i16, w: i16, rows: i16| {
+            ui.window(
+                None,
+                Rect {
+                    x,
+                    y,
+                    w,
+                    h: LINE_HEIGHT * rows + PADDING * 2,
+                },
+            )
+        };
+        let list = |ui: &mut Ui, window: WidgetId, w: i16, rows: i16, items: Vec<ListItem>| {
+            ui.list(
+                Some(window),
+                Rect {
+                    x: PADDING,
+                    y: PADDING,
+                    w: w - PADDING * 2,
+                    h: LINE_HEIGHT * rows,
+                },
+                items,
+                1,
+            )
+        };
+        let owners_window = window(&mut ui, 16, 16, 160, count);
+        let owners = list(&mut ui, owners_window, 160, count, names());
+        let bag_window = window(&mut ui, 184, 16, 280, BAG_ROWS);
+        let bag = list(&mut ui, bag_window, 280, BAG_ROWS, Vec::new());
+        ui.set_visible(bag_window, false);
+        let actions_window = window(&mut ui, 184, 240, 120, 3);
+        let actions = list(&mut ui, actions_window, 120, 3, Vec::new());
+        ui.set_visible(actions_window, false);
+        let targets_window = window(&mut ui, 312, 240, 152, count);
+        let targets = list(&mut ui, targets_window, 152, count, names());
+        ui.set_visible(targets_window, false);
+        let info_window = window(&mut ui, 16, 392, 448, 2);
+        let info = ui.label(
+            Some(info_window),
+            Rect {
+                x: PADDING,
+                y: PADDING,
+                w: 448 - PADDING * 2,
+                h: LINE_HEIGHT * 2,
+            },
+            "",
+        );
+        ui.focus(owners);
+        Self {
+            items,
+            ui,
+            owners,
+            bag_window,
+            bag,
+            actions_window,
+            actions,
+            targets_window,
+            targets,
+            info,
+            owner: 0,
+            purpose: None,
+            message: false,
+        }
+    }
+
+    fn show_bag(&mut self, state: &GameState) -> Result<()> {
+        let stacks = state
+            .party
+            .get(self.owner)
+            .map(|member| member.bag.stacks.as_slice())
+            .unwrap_or_default();
+        let items = stacks
+            .iter()
+            .map(|stack| ListItem::new(&format!("{} x{}", stack.item, stack.count)))
+            .collect();
+        let cursor = self.ui.list_mut(self.bag)?.cursor;
+        self.ui.set_items(self.bag, items)?;
+        // Stay on the same row, or the one above once the last item is gone.
+        let list = self.ui.list_mut(self.bag)?;
+        list.cursor = cursor.min(list.items.len().saturating_sub(1));
+        self.ui.set_visible(self.bag_window, true);
+        Ok(())
+    }
+
+    fn say(&mut self, text: &str) -> Result<()> {
+        self.ui.set_text(self.info, text)?;
+        self.message = true;
+        Ok(())
+    }
+
+    // Back to the bag after an action, with the result on show.
+    fn finish(&mut self, text: &str, state: &GameState) -> Result<()> {
+        self.ui.set_visible(self.targets_window, false);
+        self.ui.set_visible(self.actions_window, false);
+        while self.ui.focused() != Some(self.bag) && self.ui.blur().is_some() {}
+        self.purpose = None;
+        self.show_bag(state)?;
+        if state.party[self.owner].bag.stacks.is_empty() {
+            self.ui.set_visible(self.bag_window, false);
+            self.ui.blur();
+        }
+        self.say(text)
+    }
+
+    // False once the player backs out.
+    pub fn update(&mut self, key_state: &KeyState, state: &mut GameState) -> Result<bool> {
+        if self.message {
+            let pressed = key_state.is_action_just_pressed(Action::Confirm)
+                || key_state.is_action_just_pressed(Action::Cancel);
+            if pressed {
+                self.message = false;
+            }
+            return Ok(true);
+        }
+        let event = self.ui.update(key_state);
+        let stack = self.ui.list_mut(self.bag)?.cursor;
+        let item = state.party[self.owner]
+            .bag
+            .stacks
+            .get(stack)
+            .map(|stack| stack.item.clone());
+        match event {
+            Some(UiEvent::Selected(id, index)) if id == self.owners => {
+                self.owner = index;
+                self.ui.set_items(self.bag, Vec::new())?;
+                self.show_bag(state)?;
+                if state.party[index].bag.stacks.is_empty() {
+                    self.ui.set_visible(self.bag_window, false);
+                    let name = state.party[index].fighter.name.clone();
+                    self.say(&format!("{} is not carrying anything.", name))?;
+                } else {
+                    self.ui.focus(self.bag);
+                }
+            }
+            Some(UiEvent::Cancelled(id)) if id == self.owners => return Ok(false),
+            Some(UiEvent::Selected(id, _)) if id == self.bag => {
+                let Some(data) = item.as_ref().and_then(|item| self.items.get(item)) else {
+                    return Ok(true);
+                };
+                let actions = vec![
+                    if data.usable_in_field() {
+                        ListItem::new("Use")
+                    } else {
+                        ListItem::disabled("Use")
+                    },
+                    if state.party.len() > 1 {
+                        ListItem::new("Give")
+                    } else {
+                        ListItem::disabled("Give")
+                    },
+                    if data.kind == ItemKind::Key {
+                        ListItem::disabled("Discard")
+                    } else {
+                        ListItem::new("Discard")
+                    },
+                ];
+                self.ui.set_items(self.actions, actions)?;
+                self.ui.set_visible(self.actions_window, true);
+                self.ui.focus(self.actions);
+            }
+            Some(UiEvent::Cancelled(id)) if id == self.bag => {
+                self.ui.set_visible(self.bag_window, false);
+                self.ui.blur();
+            }
+            Some(UiEvent::Selected(id, action)) if id == self.actions => {
+                let (Some(item), Some(data)) = (
+                    item.clone(),
+                    item.as_ref().and_then(|item| self.items.get(item)),
+                ) else {
+                    return Ok(true);
+                };
+                match action {
+                    USE if data.target == ItemTarget::Nobody => {
+                        let text = self.use_item(&item, None, state);
+                        self.finish(&text, state)?;
+                    }
+                    USE | GIVE => {
+                        self.purpose = Some(if action == USE {
+                            Purpose::Use
+                        } else {
+                            Purpose::Give
+                        });
+                        self.ui.set_visible(self.targets_window, true);
+                        self.ui.focus(self.targets);
+                    }
+                    DISCARD => {
+                        let owner = &mut state.party[self.owner];
+                        owner.bag.discard(stack);
+                        let text = format!("{} throws away the {}.", owner.fighter.name, item);
+                        self.finish(&text, state)?;
+                    }
+                    _ => {}
+                }
+            }
+            Some(UiEvent::Cancelled(id)) if id == self.actions => {
+                self.ui.set_visible(self.actions_window, false);
+                self.ui.blur();
+            }
+            Some(UiEvent::Selected(id, target)) if id == self.targets => {
+                let Some(item) = item.clone() else {
+                    return Ok(true);
+                };
+                let text = match self.purpose {
+                    Some(Purpose::Use) => self.use_item(&item, Some(target), state),
+                    Some(Purpose::Give) => self.give(&item, target, state),
+                    None => return Ok(true),
+                };
+                self.finish(&text, state)?;
+            }
+            Some(UiEvent::Cancelled(id)) if id == self.targets => {
+                self.purpose = None;
+                self.ui.set_visible(self.targets_window, false);
+                self.ui.blur();
+            }
+            _ => {}
+        }
+        if !self.message {
+            let description = match self.ui.focused() {
+                Some(id) if id == self.bag || id == self.actions => item
+                    .as_ref()
+                    .and_then(|item| self.items.get(item))
+                    .map(|data| data.description.clone()),
+                _ => None,
+            };
+            self.ui
+                .set_text(self.info, description.as_deref().unwrap_or(""))?;
+        }
+        Ok(true)
+    }
+
+    fn use_item(&self, item: &str, target: Option<usize>, state: &mut GameState) -> String {
+        let Some(data) = self.items.get(item) else {
+            return String::new();
+        };
+        let user = state.party[self.owner].fighter.name.clone();
+        let mut lines = vec![format!("{} uses the {}.", user, item)];
+        if let Some(target) = target {
+            let fighter = &mut state.party[target].fighter;
+            if !fighter.is_alive() {
+                return format!("It is too late for {}.", fighter.name);
+            }
+            for restored in item::restore(data, fighter) {
+                lines.push(match restored {
+                    Restored::Hp(amount) => format!("{} recovers {} HP.", fighter.name, amount),
+                    Restored::Mp(amount) => format!("{} recovers {} MP.", fighter.name, amount),
+                });
+            }
+        }
+        for effect in &data.effects {
+            if let ItemEffect::Flag(effect) = effect {
+                state.flags.apply(effect);
+            }
+        }
+        if data.kind != ItemKind::Key {
+            state.party[self.owner].bag.remove(item);
+        }
+        lines.join("\n")
+    }
+
+    fn give(&self, item: &str, target: usize, state: &mut GameState) -> String {
+        let giver = state.party[self.owner].fighter.name.clone();
+        let receiver = state.party[target].fighter.name.clone();
+        if target == self.owner {
+            return format!("{} already has the {}.", giver, item);
+        }
+        if !state.party[target]
+            .bag
+            .add(item, self.items.stack_limit(item))
+        {
+            return format!("{} has no room for the {}.", receiver, item);
+        }
+        state.party[self.owner].bag.remove(item);
+        format!("{} gives the {} to {}.", giver, item, receiver)
+    }
+
+    pub fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
+        self.ui.draw(renderer, sprite_sheet_store)
+    }
+}
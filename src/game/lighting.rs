@@ -0,0 +1,272 @@
+use super::floor::Floor;
+use super::{tile_of, TILE_HEIGHT, TILE_WIDTH};
+use crate::engine::{Point, Rect, Renderer, Rng};
+
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+// Remaining steps of torchlight; set by torches and light spells.
+pub const TORCH_FLAG: &str = "torch";
+// Extra tiles of sight while the torch burns.
+const TORCH_BONUS: i16 = 2;
+// The overlay is drawn in squares of this many pixels so light falls off in
+// rings rather than whole tiles.
+const CELL: i16 = 8;
+// How much of the darkness is left on tiles that are remembered but out of
+// sight; lit tiles are never darker than this.
+const REMEMBERED: f64 = 0.7;
+// Light fades out in this many steps, for a retro banded look.
+const LIGHT_LEVELS: f32 = 4.0;
+
+// How a dark floor is lit, from the floor's data. `sight` is in tiles.
+#[derive(Deserialize)]
+pub struct Darkness {
+    pub color: String,
+    pub sight: i16,
+    #[serde(default)]
+    pub flicker: i16,
+    #[serde(default)]
+    pub lights: Vec<LightData>,
+}
+
+// A fixed light such as a brazier; `radius` is in tiles.
+#[derive(Deserialize)]
+pub struct LightData {
+    pub tile: Point,
+    pub radius: i16,
+    #[serde(default)]
+    pub flicker: i16,
+}
+
+// A circle of light in world pixels whose radius wanders by up to `flicker`.
+struct Light {
+    center: Point,
+    radius: i16,
+    flicker: i16,
+    offset: i16,
+}
+impl Light {
+    fn update(&mut self, rng: &mut Rng) {
+        if self.flicker > 0 {
+            let step = rng.range(0, 3) as i16 - 1;
+            self.offset = (self.offset + step).clamp(-self.flicker, self.flicker);
+        }
+    }
+
+    fn intensity(&self, x: i16, y: i16) -> f32 {
+        let radius = (self.radius + self.offset).max(1) as f32;
+        let distance = ((x - self.center.x) as f32).hypot((y - self.center.y) as f32);
+        ((1.0 - distance / radius) * LIGHT_LEVELS).ceil().max(0.0) / LIGHT_LEVELS
+    }
+}
+
+pub struct Lighting {
+    color: String,
+    sight: i16,
+    hero: Light,
+    lights: Vec<Light>,
+    visible: HashSet<Point>,
+    explored: HashSet<Point>,
+    rng: Rng,
+}
+impl Lighting {
+    // `rng` should come from the cosmetic stream.
+    pub fn new(darkness: &Darkness, rng: Rng) -> Self {
+        let lights = darkness
+            .lights
+            .iter()
+            .map(|light| Light {
+                center: Point {
+                    x: light.tile.x * TILE_WIDTH + TILE_WIDTH / 2,
+                    y: light.tile.y * TILE_HEIGHT + TILE_HEIGHT / 2,
+                },
+                radius: light.radius * TILE_WIDTH,
+                flicker: light.flicker,
+                offset: 0,
+            })
+            .collect();
+        Self {
+            color: darkness.color.clone(),
+            sight: darkness.sight,
+            hero: Light {
+                center: Point { x: 0, y: 0 },
+                radius: 0,
+                flicker: darkness.flicker,
+                offset: 0,
+            },
+            lights,
+            visible: HashSet::new(),
+            explored: HashSet::new(),
+            rng,
+        }
+    }
+
+    pub fn is_visible(&self, tile: &Point) -> bool {
+        self.visible.contains(tile)
+    }
+
+    pub fn visible(&self) -> impl Iterator<Item = &Point> {
+        self.visible.iter()
+    }
+
+    // `position` is the hero's; the view is recomputed every update so doors
+    // opening and closing are seen straight away.
+    pub fn update(&mut self, floor: &Floor, position: &Point, torch: bool) {
+        let sight = self.sight + if torch { TORCH_BONUS } else { 0 };
+        self.visible = field_of_view(tile_of(position), sight, |tile| floor.is_opaque(tile));
+        self.explored.extend(self.visible.iter().copied());
+        self.hero.center = Point {
+            x: position.x + TILE_WIDTH / 2,
+            y: position.y + TILE_HEIGHT / 2,
+        };
+        self.hero.radius = sight * TILE_WIDTH + TILE_WIDTH / 2;
+        self.hero.update(&mut self.rng);
+        for light in &mut self.lights {
+            light.update(&mut self.rng);
+        }
+    }
+
+    fn darkness(&self, x: i16, y: i16) -> f64 {
+        let tile = Point {
+            x: x.div_euclid(TILE_WIDTH),
+            y: y.div_euclid(TILE_HEIGHT),
+        };
+        if !self.explored.contains(&tile) {
+            return 1.0;
+        }
+        if !self.visible.contains(&tile) {
+            return REMEMBERED;
+        }
+        let intensity = self
+            .lights
+            .iter()
+            .chain([&self.hero])
+            .map(|light| light.intensity(x, y))
+            .fold(0.0, f32::max);
+        REMEMBERED * (1.0 - intensity as f64)
+    }
+
+    // Covers the part of the floor inside `camera`; runs of cells with the
+    // same darkness are filled together.
+    pub fn draw(&self, renderer: &Renderer, camera: &Rect) -> Result<()> {
+        let left = camera.x.div_euclid(CELL) * CELL;
+        let top = camera.y.div_euclid(CELL) * CELL;
+        for y in (top..camera.y + camera.h).step_by(CELL as usize) {
+            let mut run: Option<(i16, f64)> = None;
+            for x in (left..camera.x + camera.w + CELL).step_by(CELL as usize) {
+                let darkness = if x < camera.x + camera.w {
+                    self.darkness(x + CELL / 2, y + CELL / 2)
+                } else {
+                    -1.0
+                };
+                match run {
+                    Some((_, current)) if current == darkness => continue,
+                    Some((start, current)) if current > 0.0 => {
+                        renderer.with_alpha(current, |renderer| {
+                            renderer.fill_rect(
+                                &Rect {
+                                    x: start,
+                                    y,
+                                    w: x - start,
+                                    h: CELL,
+                                },
+                                &self.color,
+                            );
+                            Ok(())
+                        })?;
+                    }
+                    _ => {}
+                }
+                run = Some((x, darkness));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Octant transforms for shadowcasting: (xx, xy, yx, yy).
+const OCTANTS: [(i16, i16, i16, i16); 8] = [
+    (1, 0, 0, 1),
+    (0, 1, 1, 0),
+    (0, -1, 1, 0),
+    (-1, 0, 0, 1),
+    (-1, 0, 0, -1),
+    (0, -1, -1, 0),
+    (0, 1, -1, 0),
+    (1, 0, 0, -1),
+];
+
+// Tiles seen from `origin` within `radius` by recursive shadowcasting. Opaque
+// tiles are seen themselves but hide what lies behind them.
+pub fn field_of_view(
+    origin: Point,
+    radius: i16,
+    is_opaque: impl Fn(&Point) -> bool,
+) -> HashSet<Point> {
+    let mut shadowcast = Shadowcast {
+        visible: HashSet::from([origin]),
+        origin,
+        radius,
+        is_opaque,
+    };
+    for octant in OCTANTS {
+        shadowcast.cast(octant, 1, 1.0, 0.0);
+    }
+    shadowcast.visible
+}
+
+struct Shadowcast<F> {
+    visible: HashSet<Point>,
+    origin: Point,
+    radius: i16,
+    is_opaque: F,
+}
+impl<F: Fn(&Point) -> bool> Shadowcast<F> {
+    // Scans rows of one octant outwards between the slopes `start` and `end`,
+    // recursing past each opaque run with the slopes narrowed around it.
+    fn cast(&mut self, octant: (i16, i16, i16, i16), row: i16, mut start: f32, end: f32) {
+        if start < end {
+            return;
+        }
+        let (xx, xy, yx, yy) = octant;
+        let mut next_start = start;
+        for distance in row..=self.radius {
+            let mut blocked = false;
+            let dy = -distance;
+            for dx in -distance..=0 {
+                let left = (dx as f32 - 0.5) / (dy as f32 + 0.5);
+                let right = (dx as f32 + 0.5) / (dy as f32 - 0.5);
+                if start < right {
+                    continue;
+                }
+                if end > left {
+                    break;
+                }
+                let tile = Point {
+                    x: self.origin.x + dx * xx + dy * xy,
+                    y: self.origin.y + dx * yx + dy * yy,
+                };
+                if dx * dx + dy * dy <= self.radius * self.radius {
+                    self.visible.insert(tile);
+                }
+                let opaque = (self.is_opaque)(&tile);
+                if blocked {
+                    if opaque {
+                        next_start = right;
+                    } else {
+                        blocked = false;
+                        start = next_start;
+                    }
+                } else if opaque && distance < self.radius {
+                    blocked = true;
+                    self.cast(octant, distance + 1, start, left);
+                    next_start = right;
+                }
+            }
+            if blocked {
+                break;
+            }
+        }
+    }
+}
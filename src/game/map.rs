@@ -0,0 +1,167 @@
+use super::dungeon::{self, DUNGEON_PREFIX};
+use super::effects::Effects;
+use super::flags::Flags;
+use super::floor::Floor;
+use super::lighting::Lighting;
+use super::minimap::Minimap;
+use super::npc::{Npc, NpcData};
+use super::object::{FieldObject, ObjectKind};
+use super::Direction;
+use crate::engine::{Point, Random, Stream, TransitionSpec};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarpKind {
+    Door,
+    StairsUp,
+    StairsDown,
+    // Sits just outside the floor and is taken by walking off that edge.
+    Edge,
+}
+
+// Takes the player to `spawn` on the map named `target`, facing `facing`.
+// Without a spawn the player lands beside the target's matching stairs.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Warp {
+    pub tile: Point,
+    pub kind: WarpKind,
+    pub target: String,
+    pub spawn: Option<Point>,
+    pub facing: Direction,
+    // Played around the swap; a short fade when unset.
+    pub transition: Option<TransitionSpec>,
+}
+
+#[derive(Deserialize)]
+struct Entities {
+    #[serde(default)]
+    npcs: Vec<NpcData>,
+    #[serde(default)]
+    objects: Vec<FieldObject>,
+}
+
+// Everything on a map that changes while the player is there. Maps are kept
+// whole after the player leaves, so coming back finds opened chests and NPCs
+// where they were.
+pub struct MapState {
+    pub floor: Floor,
+    pub npcs: Vec<Npc>,
+    pub objects: Vec<FieldObject>,
+    pub lighting: Option<Lighting>,
+    pub minimap: Option<Minimap>,
+}
+impl MapState {
+    // Maps are parsed, or generated, the first time they are visited; `flags`
+    // say which of their objects were already used and which NPCs are gone.
+    pub fn load(
+        name: &str,
+        effects: Option<&Effects>,
+        random: &mut Random,
+        flags: &Flags,
+    ) -> Result<Self> {
+        let depth = name
+            .strip_prefix(DUNGEON_PREFIX)
+            .and_then(|depth| depth.parse().ok());
+        let (floor, entities) = match depth {
+            Some(depth) => {
+                let dungeon = dungeon::generate(random.derive(Stream::World, depth as u32), depth)?;
+                let entities = Entities {
+                    npcs: dungeon.npcs,
+                    objects: dungeon.objects,
+                };
+                (Floor::from_data(dungeon.floor)?, entities)
+            }
+            None => {
+                let json = source(name)?;
+                (Floor::load(json)?, serde_json::from_slice(json)?)
+            }
+        };
+        if floor.name != name {
+            return Err(anyhow!("map {} is named {} in its file", name, floor.name));
+        }
+        let mut objects = entities.objects;
+        for object in &mut objects {
+            if flags.get(&object.flag(name)) > 0 {
+                object.restore();
+            }
+        }
+        if let Some(effects) = effects {
+            for object in &mut objects {
+                if let ObjectKind::Chest { opened: false, .. } = object.kind {
+                    object.sparkles = Some(effects.start(
+                        "sparkles",
+                        object.center(),
+                        random.fork(Stream::Cosmetic),
+                    )?);
+                }
+            }
+        }
+        Ok(Self {
+            lighting: floor
+                .darkness
+                .as_ref()
+                .map(|darkness| Lighting::new(darkness, random.fork(Stream::Cosmetic))),
+            minimap: Some(Minimap::new(&floor)?),
+            npcs: entities
+                .npcs
+                .into_iter()
+                .filter(|npc| {
+                    !npc.hidden_if
+                        .as_ref()
+                        .is_some_and(|hidden| flags.check(hidden))
+                })
+                .map(|npc| Npc::from_data(npc, random.fork(Stream::Ai)))
+                .collect(),
+            objects,
+            floor,
+        })
+    }
+
+    // Where a warp of `kind` without a spawn point lands: next to this map's
+    // stairs going the other way, on the `facing` side if that is free.
+    pub fn arrival(&self, kind: WarpKind, facing: Direction) -> Option<Point> {
+        let back = match kind {
+            WarpKind::StairsUp => WarpKind::StairsDown,
+            WarpKind::StairsDown => WarpKind::StairsUp,
+            kind => kind,
+        };
+        let stairs = self.floor.warps.iter().find(|warp| warp.kind == back)?;
+        [
+            facing,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+            Direction::Up,
+        ]
+        .iter()
+        .map(|direction| {
+            let offset = direction.offset();
+            Point {
+                x: stairs.tile.x + offset.x,
+                y: stairs.tile.y + offset.y,
+            }
+        })
+        .find(|tile| {
+            !self.floor.is_solid(tile)
+                && self.floor.warp_at(tile).is_none()
+                && !self
+                    .objects
+                    .iter()
+                    .any(|object| object.tile == *tile && object.blocks())
+        })
+    }
+}
+
+// TODO like sprite sheets, maps should be picked up from `asset/*`
+fn source(name: &str) -> Result<&'static [u8]> {
+    match name {
+        "village" => Ok(include_bytes!("../asset/village.json")),
+        "field" => Ok(include_bytes!("../asset/field.json")),
+        "hut" => Ok(include_bytes!("../asset/hut.json")),
+        "cave" => Ok(include_bytes!("../asset/cave.json")),
+        _ => Err(anyhow!("unknown map {}", name)),
+    }
+}
@@ -0,0 +1,278 @@
+use crate::engine::{
+    draw_window, Action, Align, KeyState, ListItem, Point, Rect, Renderer, SpriteSheetStore,
+    TextStyle, Ui, UiEvent, DEFAULT_FONT, LINE_HEIGHT, PADDING, TEXT_COLOR,
+};
+
+use anyhow::Result;
+
+const CHOICE_WIDTH: i16 = 144;
+// Characters revealed per update, normally and while Confirm is held.
+const REVEAL_SPEED: f32 = 0.5;
+const FAST_REVEAL_SPEED: f32 = 3.0;
+const SPEAKER_COLOR: &str = "#ffd700";
+
+// A bordered window at the bottom of the screen that types out one page of text.
+pub struct MessageWindow {
+    speaker: Option<String>,
+    align: Align,
+    text: Vec<char>,
+    revealed: f32,
+    ticks: u32,
+}
+impl MessageWindow {
+    pub fn new(speaker: Option<&str>, text: &str) -> Self {
+        Self {
+            speaker: speaker.map(str::to_string),
+            align: Align::Left,
+            text: text.chars().collect(),
+            revealed: 0.0,
+            ticks: 0,
+        }
+    }
+
+    // Narration such as signs reads better centred than flush left.
+    pub fn centered(mut self) -> Self {
+        self.align = Align::Center;
+        self
+    }
+
+    fn text_style(&self, window: &Rect) -> TextStyle<'static> {
+        TextStyle {
+            align: self.align,
+            max_width: Some(window.w - PADDING * 2),
+            ..TextStyle::new(TEXT_COLOR)
+        }
+    }
+
+    pub fn is_revealed(&self) -> bool {
+        self.revealed as usize >= self.text.len()
+    }
+
+    // Returns true once the fully revealed page has been confirmed.
+    pub fn update(&mut self, key_state: &KeyState) -> bool {
+        self.ticks = self.ticks.wrapping_add(1);
+        if self.is_revealed() {
+            return key_state.is_action_just_pressed(Action::Confirm);
+        }
+        self.revealed += if key_state.is_action_pressed(Action::Confirm) {
+            FAST_REVEAL_SPEED
+        } else {
+            REVEAL_SPEED
+        };
+        false
+    }
+
+    // `show_indicator` is false when something else, like a choice list,
+    // takes over once the page is revealed.
+    pub fn draw(
+        &self,
+        renderer: &Renderer,
+        sprite_sheet_store: &SpriteSheetStore,
+        show_indicator: bool,
+    ) -> Result<()> {
+        let window = window_rect(renderer);
+        draw_window(renderer, sprite_sheet_store, &window)?;
+        let mut y = window.y + PADDING;
+        if let Some(speaker) = &self.speaker {
+            renderer.draw_text(
+                speaker,
+                &Point {
+                    x: window.x + PADDING,
+                    y,
+                },
+                &TextStyle::new(SPEAKER_COLOR),
+            )?;
+            y += LINE_HEIGHT;
+        }
+        // Wrap the whole page first so words do not jump between lines as
+        // they are typed out.
+        let text: String = self.text.iter().collect();
+        let style = self.text_style(&window);
+        let lines = renderer.wrap_text(&text, &style)?;
+        let revealed = revealed_lines(&self.text, &lines, self.revealed as usize);
+        for (line, shown) in lines.iter().zip(revealed) {
+            // Align the full line so centred text does not drift while typing.
+            let offset = match self.align {
+                Align::Left => 0,
+                Align::Center => {
+                    (style.max_width.unwrap_or(0) - renderer.measure_text(line, DEFAULT_FONT)?) / 2
+                }
+                Align::Right => {
+                    style.max_width.unwrap_or(0) - renderer.measure_text(line, DEFAULT_FONT)?
+                }
+            };
+            renderer.draw_text(
+                &shown,
+                &Point {
+                    x: window.x + PADDING + offset,
+                    y,
+                },
+                &TextStyle::new(TEXT_COLOR),
+            )?;
+            y += LINE_HEIGHT;
+        }
+        if show_indicator && self.is_revealed() && self.ticks % 40 < 20 {
+            renderer.draw_text(
+                "▼",
+                &Point {
+                    x: window.x + PADDING,
+                    y: window.y + window.h - PADDING - 12,
+                },
+                &TextStyle {
+                    align: Align::Right,
+                    max_width: Some(window.w - PADDING * 2),
+                    ..TextStyle::new(TEXT_COLOR)
+                },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// A small window above the message window listing the answers to a prompt.
+// It is laid out relative to the message window's top-right corner.
+pub struct ChoiceWindow {
+    ui: Ui,
+}
+impl ChoiceWindow {
+    pub fn new(options: Vec<String>) -> Self {
+        let mut ui = Ui::new();
+        let h = options.len() as i16 * LINE_HEIGHT + PADDING * 2;
+        let window = ui.window(
+            None,
+            Rect {
+                x: -CHOICE_WIDTH,
+                y: -h - 8,
+                w: CHOICE_WIDTH,
+                h,
+            },
+        );
+        let list = ui.list(
+            Some(window),
+            Rect {
+                x: PADDING,
+                y: PADDING,
+                w: CHOICE_WIDTH - PADDING * 2,
+                h: h - PADDING * 2,
+            },
+            options.iter().map(|option| ListItem::new(option)).collect(),
+            1,
+        );
+        ui.focus(list);
+        Self { ui }
+    }
+
+    // Returns the chosen index once Confirm is pressed.
+    pub fn update(&mut self, key_state: &KeyState) -> Option<usize> {
+        match self.ui.update(key_state) {
+            Some(UiEvent::Selected(_, index)) => Some(index),
+            _ => None,
+        }
+    }
+
+    pub fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
+        let window = window_rect(renderer);
+        self.ui.draw_at(
+            renderer,
+            sprite_sheet_store,
+            &Point {
+                x: window.x + window.w,
+                y: window.y,
+            },
+        )
+    }
+}
+
+// How much of each wrapped line the first `revealed` characters of `text`
+// cover. Wrapping drops the spaces it breaks at but CJK text breaks between
+// characters, so each line is found in the source to see what it used up.
+fn revealed_lines(text: &[char], lines: &[String], revealed: usize) -> Vec<String> {
+    let mut start = 0;
+    lines
+        .iter()
+        .map(|line| {
+            let line: Vec<char> = line.chars().collect();
+            while start < text.len() && !text[start..].starts_with(&line) {
+                start += 1;
+            }
+            let shown = revealed.saturating_sub(start).min(line.len());
+            start += line.len();
+            line[..shown].iter().collect()
+        })
+        .collect()
+}
+
+// Along the bottom of the screen, whatever its size.
+fn window_rect(renderer: &Renderer) -> Rect {
+    let screen = renderer.screen().rect();
+    Rect {
+        x: 16,
+        y: screen.h - 136,
+        w: screen.w - 32,
+        h: 120,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::{next_image_id, Font, FontDescriptor, Sheet, SpriteSheet};
+
+    fn font() -> Font {
+        let descriptor: FontDescriptor =
+            serde_json::from_slice(include_bytes!("../asset/font.json")).unwrap();
+        let sheet: Sheet =
+            serde_json::from_slice(include_bytes!("../asset/Font-0001.json")).unwrap();
+        Font::new(
+            descriptor,
+            SpriteSheet {
+                name: "Font-0001".to_string(),
+                id: next_image_id(),
+                sheet: Some(sheet),
+                image: None,
+            },
+        )
+    }
+
+    fn reveal(text: &str, max_width: i16, revealed: usize) -> (Vec<String>, Vec<String>) {
+        let lines = font().wrap(text, Some(max_width));
+        let chars: Vec<char> = text.chars().collect();
+        let shown = revealed_lines(&chars, &lines, revealed);
+        (lines, shown)
+    }
+
+    #[test]
+    fn text_without_spaces_is_fully_revealed() {
+        let text = "むかしむかしあるところにおじいさんとおばあさんがすんでいました";
+        let (lines, shown) = reveal(text, 64, text.chars().count());
+        // Kana are 13 pixels wide, so four fit in 64.
+        let chars: Vec<char> = text.chars().collect();
+        let expected: Vec<String> = chars.chunks(4).map(|line| line.iter().collect()).collect();
+        assert_eq!(lines, expected);
+        assert_eq!(lines, shown);
+        assert_eq!(shown.concat(), text);
+    }
+
+    #[test]
+    fn text_without_spaces_reveals_in_order() {
+        let text = "むかしむかしあるところにおじいさんとおばあさんがすんでいました";
+        for revealed in 0..=text.chars().count() {
+            let (_, shown) = reveal(text, 64, revealed);
+            let expected: String = text.chars().take(revealed).collect();
+            assert_eq!(shown.concat(), expected);
+        }
+        let (_, shown) = reveal(text, 64, 6);
+        assert_eq!(shown[..3], ["むかしむ", "かし", ""]);
+    }
+
+    #[test]
+    fn spaces_dropped_at_breaks_are_not_counted_as_shown() {
+        let text = "Monsters have been seen near the harbour lately.";
+        let (lines, shown) = reveal(text, 120, text.chars().count());
+        assert!(lines.len() > 1);
+        assert_eq!(lines, shown);
+        let (lines, shown) = reveal(text, 120, lines[0].chars().count() + 1);
+        assert_eq!(shown[0], lines[0]);
+        assert!(shown[1..].iter().all(String::is_empty));
+    }
+}
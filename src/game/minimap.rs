@@ -0,0 +1,315 @@
+use super::floor::Floor;
+use super::{Direction, TILE_HEIGHT, TILE_WIDTH};
+use crate::engine::{
+    draw_window, Action, KeyState, Point, Rect, Renderer, SpriteSheetStore, Surface, TextStyle,
+    LINE_HEIGHT, PADDING,
+};
+
+use anyhow::Result;
+use std::collections::HashSet;
+
+// Pixels per tile in the cached map image.
+const SCALE: i16 = 4;
+// The corner overlay shows this many screen pixels of the map.
+const VIEW: i16 = 96;
+const MARGIN: i16 = 8;
+// The full map is the cached image at twice the size.
+const ZOOM: i16 = 2;
+// Map pixels panned per update.
+const PAN_SPEED: i16 = 2;
+const BACKGROUND_COLOR: &str = "#000000";
+const BACKGROUND_ALPHA: f64 = 0.6;
+const BORDER_COLOR: &str = "#ffffff";
+const PLAYER_COLOR: &str = "#ff4040";
+const FACING_COLOR: &str = "#ffffff";
+const TITLE_COLOR: &str = "#ffffff";
+
+// A point of interest, only shown once its tile has been explored.
+pub struct Marker {
+    pub tile: Point,
+    pub color: &'static str,
+}
+
+// Where the player is drawn; `position` is in world pixels.
+pub struct Hero<'a> {
+    pub position: &'a Point,
+    pub direction: Direction,
+}
+
+// The floor as the player has seen it. Tiles are painted into the cached
+// image the first time they are explored, so a frame costs one blit.
+pub struct Minimap {
+    title: String,
+    width: i16,
+    height: i16,
+    surface: Surface,
+    explored: HashSet<Point>,
+    // The top-left corner in map pixels while the full map is open.
+    pan: Option<Point>,
+}
+impl Minimap {
+    pub fn new(floor: &Floor) -> Result<Self> {
+        let width = floor.width * SCALE;
+        let height = floor.height * SCALE;
+        Ok(Self {
+            title: floor.name.clone(),
+            width,
+            height,
+            surface: Surface::new(width, height)?,
+            explored: HashSet::new(),
+            pan: None,
+        })
+    }
+
+    pub fn explore<'a>(&mut self, floor: &Floor, tiles: impl IntoIterator<Item = &'a Point>) {
+        for tile in tiles {
+            if self.explored.insert(*tile) {
+                self.paint(floor, tile);
+            }
+        }
+    }
+
+    // For explored tiles that change afterwards, such as doors.
+    pub fn repaint(&self, floor: &Floor, tile: &Point) {
+        if self.explored.contains(tile) {
+            self.paint(floor, tile);
+        }
+    }
+
+    fn paint(&self, floor: &Floor, tile: &Point) {
+        let rect = Rect {
+            x: tile.x * SCALE,
+            y: tile.y * SCALE,
+            w: SCALE,
+            h: SCALE,
+        };
+        self.surface.clear(&rect);
+        if let Some(color) = floor.map_color(tile) {
+            self.surface.fill_rect(&rect, color);
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.pan.is_some()
+    }
+
+    // Opens the full map centred on the player.
+    pub fn open(&mut self, position: &Point, screen: &Rect) {
+        let frame = full_frame(screen);
+        let center = to_map(position);
+        self.pan = Some(Point {
+            x: center.x + SCALE / 2 - frame.w / ZOOM / 2,
+            y: center.y + SCALE / 2 - frame.h / ZOOM / 2,
+        });
+        self.clamp_pan(screen);
+    }
+
+    // Arrows pan the full map; Cancel or Map closes it.
+    pub fn update(&mut self, key_state: &KeyState, screen: &Rect) {
+        if key_state.is_action_just_pressed(Action::Cancel)
+            || key_state.is_action_just_pressed(Action::Map)
+        {
+            self.pan = None;
+            return;
+        }
+        if let Some(pan) = &mut self.pan {
+            for direction in [
+                Direction::Left,
+                Direction::Up,
+                Direction::Right,
+                Direction::Down,
+            ] {
+                if key_state.is_action_pressed(direction.action()) {
+                    let offset = direction.offset();
+                    pan.x += offset.x * PAN_SPEED;
+                    pan.y += offset.y * PAN_SPEED;
+                }
+            }
+        }
+        self.clamp_pan(screen);
+    }
+
+    fn clamp_pan(&mut self, screen: &Rect) {
+        let frame = full_frame(screen);
+        let (width, height) = (self.width, self.height);
+        if let Some(pan) = &mut self.pan {
+            pan.x = pan.x.min(width - frame.w / ZOOM).max(0);
+            pan.y = pan.y.min(height - frame.h / ZOOM).max(0);
+        }
+    }
+
+    // The corner overlay, following the player.
+    pub fn draw(
+        &self,
+        renderer: &Renderer,
+        screen: &Rect,
+        hero: &Hero,
+        markers: &[Marker],
+    ) -> Result<()> {
+        let frame = Rect {
+            x: screen.x + screen.w - VIEW - MARGIN,
+            y: screen.y + MARGIN,
+            w: VIEW,
+            h: VIEW,
+        };
+        renderer.with_alpha(BACKGROUND_ALPHA, |renderer| {
+            renderer.fill_rect(&frame, BACKGROUND_COLOR);
+            Ok(())
+        })?;
+        let center = to_map(hero.position);
+        let corner = Point {
+            x: (center.x + SCALE / 2 - VIEW / 2)
+                .min(self.width - VIEW)
+                .max(0),
+            y: (center.y + SCALE / 2 - VIEW / 2)
+                .min(self.height - VIEW)
+                .max(0),
+        };
+        self.draw_region(renderer, &frame, &corner, 1, hero, markers)?;
+        renderer.stroke_rect(&frame, BORDER_COLOR, 1.0);
+        Ok(())
+    }
+
+    pub fn draw_full(
+        &self,
+        renderer: &Renderer,
+        sprite_sheet_store: &SpriteSheetStore,
+        screen: &Rect,
+        hero: &Hero,
+        markers: &[Marker],
+    ) -> Result<()> {
+        let Some(pan) = &self.pan else {
+            return Ok(());
+        };
+        draw_window(
+            renderer,
+            sprite_sheet_store,
+            &Rect {
+                x: screen.x + MARGIN,
+                y: screen.y + MARGIN,
+                w: screen.w - MARGIN * 2,
+                h: screen.h - MARGIN * 2,
+            },
+        )?;
+        renderer.draw_text(
+            &self.title,
+            &Point {
+                x: screen.x + MARGIN + PADDING,
+                y: screen.y + MARGIN + PADDING,
+            },
+            &TextStyle::new(TITLE_COLOR),
+        )?;
+        self.draw_region(renderer, &full_frame(screen), pan, ZOOM, hero, markers)
+    }
+
+    // Draws the part of the map from `corner` that fits in `frame` at `zoom`,
+    // centred when the map is smaller than the frame.
+    fn draw_region(
+        &self,
+        renderer: &Renderer,
+        frame: &Rect,
+        corner: &Point,
+        zoom: i16,
+        hero: &Hero,
+        markers: &[Marker],
+    ) -> Result<()> {
+        let source = Rect {
+            x: corner.x,
+            y: corner.y,
+            w: (frame.w / zoom).min(self.width),
+            h: (frame.h / zoom).min(self.height),
+        };
+        let destination = Rect {
+            x: frame.x + (frame.w - source.w * zoom) / 2,
+            y: frame.y + (frame.h - source.h * zoom) / 2,
+            w: source.w * zoom,
+            h: source.h * zoom,
+        };
+        renderer.draw_surface_scaled(&self.surface, &source, &destination)?;
+
+        // Map pixels to screen pixels; anything partly outside is left out.
+        let place = |point: &Point, size: i16| {
+            let rect = Rect {
+                x: destination.x + (point.x - source.x) * zoom,
+                y: destination.y + (point.y - source.y) * zoom,
+                w: size,
+                h: size,
+            };
+            let inside = rect.x >= destination.x
+                && rect.y >= destination.y
+                && rect.x + rect.w <= destination.x + destination.w
+                && rect.y + rect.h <= destination.y + destination.h;
+            inside.then_some(rect)
+        };
+        let size = SCALE * zoom;
+        for marker in markers
+            .iter()
+            .filter(|marker| self.explored.contains(&marker.tile))
+        {
+            let tile = Point {
+                x: marker.tile.x * SCALE,
+                y: marker.tile.y * SCALE,
+            };
+            if let Some(rect) = place(&tile, size) {
+                renderer.fill_rect(&rect, marker.color);
+            }
+        }
+        if let Some(rect) = place(&to_map(hero.position), size) {
+            renderer.fill_rect(&rect, PLAYER_COLOR);
+            // A notch on the side the player faces.
+            let offset = hero.direction.offset();
+            let notch = size / 2;
+            renderer.fill_rect(
+                &Rect {
+                    x: rect.x + notch / 2 + offset.x * (size + notch) / 2,
+                    y: rect.y + notch / 2 + offset.y * (size + notch) / 2,
+                    w: notch,
+                    h: notch,
+                },
+                FACING_COLOR,
+            );
+        }
+        Ok(())
+    }
+}
+
+// Where the full map is drawn, below its title.
+fn full_frame(screen: &Rect) -> Rect {
+    let top = MARGIN + PADDING + LINE_HEIGHT;
+    Rect {
+        x: screen.x + MARGIN + PADDING,
+        y: screen.y + top,
+        w: screen.w - (MARGIN + PADDING) * 2,
+        h: screen.h - top - MARGIN - PADDING,
+    }
+}
+
+// Widened first, as pixel positions on a large floor overflow once scaled.
+fn to_map(position: &Point) -> Point {
+    let scale =
+        |value: i16, size: i16| (i32::from(value) * i32::from(SCALE) / i32::from(size)) as i16;
+    Point {
+        x: scale(position.x, TILE_WIDTH),
+        y: scale(position.y, TILE_HEIGHT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn far_positions_do_not_overflow() {
+        let position = Point {
+            x: 500 * TILE_WIDTH,
+            y: 300 * TILE_HEIGHT,
+        };
+        assert_eq!(
+            to_map(&position),
+            Point {
+                x: 500 * SCALE,
+                y: 300 * SCALE
+            }
+        );
+    }
+}
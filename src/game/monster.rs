@@ -0,0 +1,175 @@
+use super::battle::{Drop, Spell, Stats};
+use super::item::Items;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+const MONSTERS_FILE: &str = "monsters.json";
+
+// How a monster picks what to do on its turn.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Ai {
+    // Attacks someone at random, sometimes casting instead.
+    #[default]
+    Random,
+    // Goes after whoever has the least HP left.
+    Bully,
+    // Casts whenever it has the MP for a damage spell.
+    Caster,
+    // Heals an ally below `below` percent of its HP before anything else.
+    Healer {
+        below: i32,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct MonsterData {
+    pub sprite_source: String,
+    #[serde(default)]
+    pub description: String,
+    pub level: u16,
+    pub stats: Stats,
+    // Names from the spells in battle.json.
+    #[serde(default)]
+    pub spells: Vec<String>,
+    #[serde(default)]
+    pub ai: Ai,
+    // In percent, for each turn the monster has the MP for a spell.
+    #[serde(default)]
+    pub cast_chance: f32,
+    pub exp: i32,
+    pub gold: i32,
+    #[serde(default)]
+    pub drops: Vec<Drop>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(transparent)]
+pub struct Monsters {
+    monsters: HashMap<String, MonsterData>,
+}
+impl Monsters {
+    pub fn load(spells: &HashMap<String, Spell>, items: &Items) -> Result<Self> {
+        let json = include_bytes!("../asset/monsters.json");
+        let monsters: Monsters = serde_json::from_slice(json)
+            .map_err(|error| anyhow!("{}: {}", MONSTERS_FILE, error))?;
+        monsters.validate(spells, items)?;
+        Ok(monsters)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MonsterData> {
+        self.monsters.get(name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.monsters.contains_key(name)
+    }
+
+    // Weakest first, the order of the bestiary.
+    pub fn by_level(&self) -> Vec<(&str, &MonsterData)> {
+        let mut monsters: Vec<(&str, &MonsterData)> = self
+            .monsters
+            .iter()
+            .map(|(name, data)| (name.as_str(), data))
+            .collect();
+        monsters.sort_by_key(|(name, data)| (data.level, *name));
+        monsters
+    }
+
+    fn validate(&self, spells: &HashMap<String, Spell>, items: &Items) -> Result<()> {
+        for (name, data) in &self.monsters {
+            let error = |field: &str, message: &str| {
+                Err(anyhow!(
+                    "{}: {}.{}: {}",
+                    MONSTERS_FILE,
+                    name,
+                    field,
+                    message
+                ))
+            };
+            if data.sprite_source.is_empty() {
+                return error("sprite_source", "must not be empty");
+            }
+            if data.level == 0 {
+                return error("level", "must be at least 1");
+            }
+            if data.stats.max_hp <= 0 {
+                return error("stats.max_hp", "must be positive");
+            }
+            for (field, value) in [
+                ("max_mp", data.stats.max_mp),
+                ("attack", data.stats.attack),
+                ("defense", data.stats.defense),
+                ("agility", data.stats.agility),
+            ] {
+                if value < 0 {
+                    return error(&format!("stats.{}", field), "must not be negative");
+                }
+            }
+            for (index, spell) in data.spells.iter().enumerate() {
+                if !spells.contains_key(spell) {
+                    return error(
+                        &format!("spells[{}]", index),
+                        &format!("unknown spell {}", spell),
+                    );
+                }
+            }
+            if let Ai::Healer { below } = data.ai {
+                if !(1..=100).contains(&below) {
+                    return error("ai.below", "must be between 1 and 100");
+                }
+            }
+            if !(0.0..=100.0).contains(&data.cast_chance) {
+                return error("cast_chance", "must be between 0 and 100");
+            }
+            if data.exp < 0 {
+                return error("exp", "must not be negative");
+            }
+            if data.gold < 0 {
+                return error("gold", "must not be negative");
+            }
+            for (index, drop) in data.drops.iter().enumerate() {
+                if !items.contains(&drop.item) {
+                    return error(
+                        &format!("drops[{}].item", index),
+                        &format!("unknown item {}", drop.item),
+                    );
+                }
+                if !(0.0..=100.0).contains(&drop.chance) {
+                    return error(
+                        &format!("drops[{}].chance", index),
+                        "must be between 0 and 100",
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default, Deserialize, Serialize)]
+pub struct Sightings {
+    pub seen: u32,
+    pub defeated: u32,
+}
+
+// What the player knows about each monster, by name.
+#[derive(Default, Deserialize, Serialize)]
+pub struct Bestiary {
+    entries: BTreeMap<String, Sightings>,
+}
+impl Bestiary {
+    pub fn see(&mut self, name: &str) {
+        self.entries.entry(name.to_string()).or_default().seen += 1;
+    }
+
+    pub fn defeat(&mut self, name: &str) {
+        self.entries.entry(name.to_string()).or_default().defeated += 1;
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Sightings> {
+        self.entries.get(name)
+    }
+}
@@ -0,0 +1,252 @@
+use super::flags::Condition;
+use super::inn::InnData;
+use super::shop::ShopData;
+use super::{feet, fit, step, tile_in_front, tile_of, walk_frame, Direction, Occupancy};
+use super::{TILE_HEIGHT, TILE_WIDTH};
+use crate::engine::{Point, RenderLayer, RenderQueue, Rng, SpriteSheetStore};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+const MIN_WAIT: u16 = 30;
+const MAX_WAIT: u16 = 150;
+const BLOCKED_WAIT: u16 = 15;
+
+pub struct Npc {
+    pub name: String,
+    pub dialogue: Option<String>,
+    pub battle: Option<String>,
+    pub shop: Option<ShopData>,
+    pub inn: Option<InnData>,
+    sprite_source: String,
+    position: Point,
+    direction: Direction,
+    behaviour: Behaviour,
+    state: NpcState,
+    frame: u8,
+    rng: Rng,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Behaviour {
+    #[default]
+    Stand,
+    Wander {
+        origin: Point,
+        radius: i16,
+    },
+    Patrol {
+        waypoints: Vec<Point>,
+        #[serde(default)]
+        next: usize,
+    },
+}
+
+// How an NPC is placed in a map file.
+#[derive(Deserialize)]
+pub struct NpcData {
+    pub name: String,
+    pub sprite_source: String,
+    pub tile: Point,
+    #[serde(default)]
+    pub behaviour: Behaviour,
+    pub dialogue: Option<String>,
+    // Talking to the NPC starts this formation's battle instead.
+    pub battle: Option<String>,
+    // Or opens their shop or inn.
+    pub shop: Option<ShopData>,
+    pub inn: Option<InnData>,
+    // Left off the map once this holds, such as a boss after its defeat.
+    pub hidden_if: Option<Condition>,
+}
+
+enum NpcState {
+    Idle { wait: u16 },
+    Moving,
+    Talking,
+}
+
+impl Npc {
+    // `tile` and every tile inside `behaviour` are in tile coordinates; `rng`
+    // should come from the AI stream.
+    pub fn new(
+        name: &str,
+        sprite_source: &str,
+        tile: Point,
+        behaviour: Behaviour,
+        rng: Rng,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            dialogue: None,
+            battle: None,
+            shop: None,
+            inn: None,
+            sprite_source: sprite_source.to_string(),
+            position: Point {
+                x: tile.x * TILE_WIDTH,
+                y: tile.y * TILE_HEIGHT,
+            },
+            direction: Direction::Down,
+            behaviour,
+            state: NpcState::Idle { wait: MIN_WAIT },
+            frame: 0,
+            rng,
+        }
+    }
+
+    pub fn from_data(data: NpcData, rng: Rng) -> Self {
+        let npc = Self::new(
+            &data.name,
+            &data.sprite_source,
+            data.tile,
+            data.behaviour,
+            rng,
+        );
+        let mut npc = match &data.dialogue {
+            Some(dialogue) => npc.with_dialogue(dialogue),
+            None => npc,
+        };
+        npc.battle = data.battle;
+        npc.shop = data.shop;
+        npc.inn = data.inn;
+        npc
+    }
+
+    pub fn with_dialogue(mut self, dialogue: &str) -> Self {
+        self.dialogue = Some(dialogue.to_string());
+        self
+    }
+
+    pub fn position(&self) -> &Point {
+        &self.position
+    }
+
+    pub fn tile(&self) -> Point {
+        tile_of(&self.position)
+    }
+
+    pub fn is_moving(&self) -> bool {
+        matches!(self.state, NpcState::Moving)
+    }
+
+    // Stops wandering and turns to face whoever is talking, who looks in `direction`.
+    pub fn talk_to(&mut self, direction: Direction) {
+        self.direction = direction.opposite();
+        self.frame = 0;
+        self.state = NpcState::Talking;
+    }
+
+    pub fn finish_talking(&mut self) {
+        if let NpcState::Talking = self.state {
+            self.state = NpcState::Idle { wait: MIN_WAIT };
+        }
+    }
+
+    pub fn update(&mut self, occupancy: &Occupancy) {
+        match self.state {
+            NpcState::Talking => {}
+            NpcState::Moving => {
+                self.frame = (self.frame + 1) % 16;
+                step(&mut self.position, self.direction);
+                if fit(&self.position) {
+                    self.frame = 0;
+                    let wait = self.next_wait();
+                    self.state = NpcState::Idle { wait };
+                }
+            }
+            NpcState::Idle { wait } if wait > 0 => {
+                self.state = NpcState::Idle { wait: wait - 1 };
+            }
+            NpcState::Idle { .. } => self.decide(occupancy),
+        }
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        queue: &mut RenderQueue<'a>,
+        sprite_sheet_store: &'a SpriteSheetStore,
+    ) -> Result<()> {
+        let (frame_name, options) = walk_frame(self.direction, self.frame)?;
+        let sprite_sheet = sprite_sheet_store.get(&self.sprite_source)?;
+        let position = &self.position;
+        queue.submit(RenderLayer::Entities, feet(position), move |renderer| {
+            renderer.draw_image_with(sprite_sheet, &frame_name, position, &options)
+        });
+        Ok(())
+    }
+
+    fn decide(&mut self, occupancy: &Occupancy) {
+        let tile = self.tile();
+        let direction = match &mut self.behaviour {
+            Behaviour::Stand => None,
+            Behaviour::Wander { origin, radius } => {
+                let direction = match self.rng.range(0, 5) {
+                    0 => Direction::Left,
+                    1 => Direction::Up,
+                    2 => Direction::Right,
+                    3 => Direction::Down,
+                    _ => {
+                        self.state = NpcState::Idle {
+                            wait: self.rng.range(MIN_WAIT, MAX_WAIT),
+                        };
+                        return;
+                    }
+                };
+                let target = tile_in_front(&self.position, direction);
+                let inside = (target.x - origin.x).abs() <= *radius
+                    && (target.y - origin.y).abs() <= *radius;
+                inside.then_some(direction)
+            }
+            Behaviour::Patrol { waypoints, next } => {
+                if waypoints.is_empty() {
+                    None
+                } else {
+                    if waypoints[*next] == tile {
+                        *next = (*next + 1) % waypoints.len();
+                    }
+                    direction_towards(&tile, &waypoints[*next])
+                }
+            }
+        };
+
+        match direction {
+            Some(direction) => {
+                self.direction = direction;
+                if occupancy.is_free(&tile_in_front(&self.position, direction)) {
+                    self.frame = 1;
+                    step(&mut self.position, direction);
+                    self.state = NpcState::Moving;
+                } else {
+                    self.state = NpcState::Idle { wait: BLOCKED_WAIT };
+                }
+            }
+            None => {
+                let wait = self.next_wait();
+                self.state = NpcState::Idle { wait };
+            }
+        }
+    }
+
+    fn next_wait(&mut self) -> u16 {
+        match self.behaviour {
+            Behaviour::Patrol { .. } => 0,
+            _ => self.rng.range(MIN_WAIT, MAX_WAIT),
+        }
+    }
+}
+
+fn direction_towards(from: &Point, to: &Point) -> Option<Direction> {
+    if to.x < from.x {
+        Some(Direction::Left)
+    } else if to.x > from.x {
+        Some(Direction::Right)
+    } else if to.y < from.y {
+        Some(Direction::Up)
+    } else if to.y > from.y {
+        Some(Direction::Down)
+    } else {
+        None
+    }
+}
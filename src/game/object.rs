@@ -0,0 +1,91 @@
+use super::{TILE_HEIGHT, TILE_WIDTH};
+use crate::engine::{Emitter, Point, Rect, RenderLayer, RenderQueue, SpriteSheetStore};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct FieldObject {
+    pub tile: Point,
+    #[serde(flatten)]
+    pub kind: ObjectKind,
+    #[serde(skip)]
+    pub sparkles: Option<Emitter>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ObjectKind {
+    Chest {
+        item: String,
+        #[serde(default)]
+        opened: bool,
+    },
+    Sign {
+        text: String,
+    },
+    Door {
+        #[serde(default)]
+        open: bool,
+        // The item that unlocks it, used up on the way in.
+        key: Option<String>,
+    },
+}
+
+impl FieldObject {
+    pub fn blocks(&self) -> bool {
+        !matches!(self.kind, ObjectKind::Door { open: true, .. })
+    }
+
+    // Set once a chest is emptied or a door unlocked, so that maps loaded
+    // from a save come back that way.
+    pub fn flag(&self, map: &str) -> String {
+        format!("object:{}:{},{}", map, self.tile.x, self.tile.y)
+    }
+
+    // Puts the object the way its flag says it was left.
+    pub fn restore(&mut self) {
+        match &mut self.kind {
+            ObjectKind::Chest { opened, .. } => *opened = true,
+            ObjectKind::Door { key, .. } => *key = None,
+            ObjectKind::Sign { .. } => {}
+        }
+    }
+
+    pub fn center(&self) -> Point {
+        Point {
+            x: self.tile.x * TILE_WIDTH + TILE_WIDTH / 2,
+            y: self.tile.y * TILE_HEIGHT + TILE_HEIGHT / 2,
+        }
+    }
+
+    // There is no sprite sheet for objects yet, so they are drawn as flat
+    // tiles. Doors are part of the floor and swap their tile when toggled.
+    pub fn draw<'a>(
+        &'a self,
+        queue: &mut RenderQueue<'a>,
+        sprite_sheet_store: &'a SpriteSheetStore,
+    ) -> Result<()> {
+        let sort_key = (self.tile.y + 1) * TILE_HEIGHT;
+        if let Some(sparkles) = &self.sparkles {
+            sparkles.draw(queue, sprite_sheet_store, sort_key + 1)?;
+        }
+        let color = match self.kind {
+            ObjectKind::Chest { opened: false, .. } => "#b5651d",
+            ObjectKind::Chest { opened: true, .. } => "#5c3310",
+            ObjectKind::Sign { .. } => "#d2b48c",
+            ObjectKind::Door { .. } => return Ok(()),
+        };
+        let rect = Rect {
+            x: self.tile.x * TILE_WIDTH + 4,
+            y: self.tile.y * TILE_HEIGHT + 4,
+            w: TILE_WIDTH - 8,
+            h: TILE_HEIGHT - 8,
+        };
+        queue.submit(RenderLayer::Entities, sort_key, move |renderer| {
+            renderer.fill_rect(&rect, color);
+            Ok(())
+        });
+        Ok(())
+    }
+}
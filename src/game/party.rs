@@ -0,0 +1,319 @@
+use super::battle::{Fighter, Spell, Stats};
+use super::equipment::{Equipment, SLOTS};
+use super::item::{Bag, Items, BAG_SLOTS, ITEMS_FILE};
+use crate::engine::Rng;
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+const PARTY_FILE: &str = "party.json";
+// As many as the battle status window has room for.
+pub const MAX_MEMBERS: usize = 4;
+
+// EXP needed in total to reach a level: `base * (level - 1) ^ exponent`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Curve {
+    pub base: f32,
+    pub exponent: f32,
+}
+impl Curve {
+    pub fn total(&self, level: u16) -> i32 {
+        (self.base * ((level.max(1) - 1) as f32).powf(self.exponent)).round() as i32
+    }
+}
+
+// The smallest and largest gain of each stat per level.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Growth {
+    pub max_hp: (i32, i32),
+    pub max_mp: (i32, i32),
+    pub attack: (i32, i32),
+    pub defense: (i32, i32),
+    pub agility: (i32, i32),
+}
+impl Growth {
+    fn roll(&self, rng: &mut Rng) -> Stats {
+        let mut gain = |(min, max): (i32, i32)| min + rng.range(0, (max - min + 1) as u16) as i32;
+        Stats {
+            max_hp: gain(self.max_hp),
+            max_mp: gain(self.max_mp),
+            attack: gain(self.attack),
+            defense: gain(self.defense),
+            agility: gain(self.agility),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Lesson {
+    pub level: u16,
+    pub spell: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Class {
+    pub exp: Curve,
+    pub growth: Growth,
+    #[serde(default)]
+    pub spells: Vec<Lesson>,
+}
+
+// A party member as the game starts with them; HP, MP, spells and EXP follow
+// from the level and class.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Recruit {
+    pub name: String,
+    pub class: String,
+    pub sprite_source: String,
+    pub level: u16,
+    pub stats: Stats,
+    #[serde(default)]
+    pub items: Vec<String>,
+    #[serde(default)]
+    pub equipment: Equipment,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Member {
+    pub class: String,
+    pub exp: i32,
+    #[serde(default)]
+    pub bag: Bag,
+    #[serde(default)]
+    pub equipment: Equipment,
+    #[serde(flatten)]
+    pub fighter: Fighter,
+}
+impl Member {
+    // The stats the member fights with, equipment included.
+    pub fn stats(&self, items: &Items) -> Stats {
+        self.equipment.apply(&self.fighter.stats, items)
+    }
+}
+
+pub struct LevelUp {
+    pub name: String,
+    pub level: u16,
+    pub gain: Stats,
+    pub learned: Vec<String>,
+}
+impl LevelUp {
+    pub fn text(&self) -> String {
+        let gain = &self.gain;
+        let mut lines = vec![
+            format!("{} reached level {}!", self.name, self.level),
+            format!(
+                "HP +{} MP +{} Atk +{} Def +{} Agi +{}",
+                gain.max_hp, gain.max_mp, gain.attack, gain.defense, gain.agility
+            ),
+        ];
+        lines.extend(
+            self.learned
+                .iter()
+                .map(|spell| format!("{} learned {}!", self.name, spell)),
+        );
+        lines.join("\n")
+    }
+}
+
+#[derive(Default, Deserialize)]
+pub struct PartySettings {
+    pub max_level: u16,
+    pub classes: HashMap<String, Class>,
+    // Who the game starts with.
+    pub members: Vec<Recruit>,
+}
+impl PartySettings {
+    pub fn load(spells: &HashMap<String, Spell>, items: &Items) -> Result<Self> {
+        let json = include_bytes!("../asset/party.json");
+        let settings: PartySettings =
+            serde_json::from_slice(json).map_err(|error| anyhow!("{}: {}", PARTY_FILE, error))?;
+        settings.validate(spells, items)?;
+        Ok(settings)
+    }
+
+    pub fn class(&self, member: &Member) -> Option<&Class> {
+        self.classes.get(&member.class)
+    }
+
+    pub fn starting_party(&self, items: &Items) -> Vec<Member> {
+        self.members
+            .iter()
+            .map(|recruit| {
+                let class = &self.classes[&recruit.class];
+                let mut bag = Bag::default();
+                for item in &recruit.items {
+                    bag.add(item, items.stack_limit(item));
+                }
+                Member {
+                    class: recruit.class.clone(),
+                    exp: class.exp.total(recruit.level),
+                    bag,
+                    equipment: recruit.equipment.clone(),
+                    fighter: Fighter {
+                        name: recruit.name.clone(),
+                        sprite_source: recruit.sprite_source.clone(),
+                        level: recruit.level,
+                        stats: recruit.stats.clone(),
+                        hp: recruit.stats.max_hp,
+                        mp: recruit.stats.max_mp,
+                        spells: class
+                            .spells
+                            .iter()
+                            .filter(|lesson| lesson.level <= recruit.level)
+                            .map(|lesson| lesson.spell.clone())
+                            .collect(),
+                    },
+                }
+            })
+            .collect()
+    }
+
+    // EXP still needed for the next level, none at the cap.
+    pub fn to_next(&self, member: &Member) -> Option<i32> {
+        let class = self.class(member)?;
+        let level = member.fighter.level;
+        (level < self.max_level).then(|| (class.exp.total(level + 1) - member.exp).max(0))
+    }
+
+    // Adds `exp` and grows a level for each threshold passed. The gains are
+    // added to the current HP and MP as well.
+    pub fn gain_exp(&self, member: &mut Member, exp: i32, rng: &mut Rng) -> Vec<LevelUp> {
+        member.exp += exp;
+        let mut level_ups = Vec::new();
+        let Some(class) = self.classes.get(&member.class) else {
+            return level_ups;
+        };
+        let fighter = &mut member.fighter;
+        while fighter.level < self.max_level && member.exp >= class.exp.total(fighter.level + 1) {
+            fighter.level += 1;
+            let gain = class.growth.roll(rng);
+            let stats = &mut fighter.stats;
+            stats.max_hp += gain.max_hp;
+            stats.max_mp += gain.max_mp;
+            stats.attack += gain.attack;
+            stats.defense += gain.defense;
+            stats.agility += gain.agility;
+            if fighter.is_alive() {
+                fighter.hp += gain.max_hp;
+            }
+            fighter.mp += gain.max_mp;
+            let learned: Vec<String> = class
+                .spells
+                .iter()
+                .filter(|lesson| lesson.level == fighter.level)
+                .map(|lesson| lesson.spell.clone())
+                .filter(|spell| !fighter.spells.contains(spell))
+                .collect();
+            fighter.spells.extend(learned.iter().cloned());
+            level_ups.push(LevelUp {
+                name: fighter.name.clone(),
+                level: fighter.level,
+                gain,
+                learned,
+            });
+        }
+        level_ups
+    }
+
+    fn validate(&self, spells: &HashMap<String, Spell>, items: &Items) -> Result<()> {
+        let error =
+            |field: String, message: String| Err(anyhow!("{}: {}: {}", PARTY_FILE, field, message));
+        if self.max_level < 2 {
+            return error("max_level".to_string(), "must be at least 2".to_string());
+        }
+        for (name, class) in &self.classes {
+            let field = |field: &str| format!("classes.{}.{}", name, field);
+            if class.exp.base <= 0.0 || class.exp.exponent <= 0.0 {
+                return error(field("exp"), "must grow with the level".to_string());
+            }
+            let growth = &class.growth;
+            for (stat, (min, max)) in [
+                ("max_hp", growth.max_hp),
+                ("max_mp", growth.max_mp),
+                ("attack", growth.attack),
+                ("defense", growth.defense),
+                ("agility", growth.agility),
+            ] {
+                if min < 0 || max < min {
+                    return error(
+                        field(&format!("growth.{}", stat)),
+                        format!("{} to {} is not a range of gains", min, max),
+                    );
+                }
+            }
+            for (index, lesson) in class.spells.iter().enumerate() {
+                if !spells.contains_key(&lesson.spell) {
+                    return error(
+                        field(&format!("spells[{}].spell", index)),
+                        format!("unknown spell {}", lesson.spell),
+                    );
+                }
+            }
+        }
+        let count = self.members.len();
+        if count == 0 || count > MAX_MEMBERS {
+            return error(
+                "members".to_string(),
+                format!("must have 1 to {} members, not {}", MAX_MEMBERS, count),
+            );
+        }
+        for (index, recruit) in self.members.iter().enumerate() {
+            let field = |field: &str| format!("members[{}].{}", index, field);
+            if !self.classes.contains_key(&recruit.class) {
+                return error(field("class"), format!("unknown class {}", recruit.class));
+            }
+            if recruit.level == 0 || recruit.level > self.max_level {
+                return error(
+                    field("level"),
+                    format!("must be between 1 and {}", self.max_level),
+                );
+            }
+            if recruit.stats.max_hp <= 0 {
+                return error(field("stats.max_hp"), "must be positive".to_string());
+            }
+            for (item_index, item) in recruit.items.iter().enumerate() {
+                if !items.contains(item) {
+                    return error(
+                        field(&format!("items[{}]", item_index)),
+                        format!("unknown item {}", item),
+                    );
+                }
+            }
+            if recruit.items.len() > BAG_SLOTS * items.stack_limit as usize {
+                return error(field("items"), "do not fit in the bag".to_string());
+            }
+            for slot in SLOTS {
+                let Some(item) = recruit.equipment.get(slot) else {
+                    continue;
+                };
+                let field = field(&format!("equipment.{}", slot.name().to_lowercase()));
+                let Some(data) = items.get(item) else {
+                    return error(field, format!("unknown item {}", item));
+                };
+                if data.kind.slot() != Some(slot) {
+                    return error(field, format!("{} is not worn there", item));
+                }
+                if !data.equippable_by(&recruit.class) {
+                    return error(field, format!("{} cannot wear {}", recruit.class, item));
+                }
+            }
+        }
+        // Checked here since the classes are only known once the party loads.
+        for (name, data) in items.iter() {
+            for (index, class) in data.classes.iter().enumerate() {
+                if !self.classes.contains_key(class) {
+                    return Err(anyhow!(
+                        "{}: {}.classes[{}]: unknown class {}",
+                        ITEMS_FILE,
+                        name,
+                        index,
+                        class
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,10 +1,10 @@
+use super::{feet, fit, step, tile_in_front, walk_frame, Direction, Occupancy};
 use super::{TILE_HEIGHT, TILE_WIDTH};
 use crate::engine::{
-    KeyState, Point, Renderer, SpriteSheetStore, KEY_CODE_ARROW_DOWN, KEY_CODE_ARROW_LEFT,
-    KEY_CODE_ARROW_RIGHT, KEY_CODE_ARROW_UP,
+    Action, DrawOptions, KeyState, Point, RenderLayer, RenderQueue, SpriteSheetStore,
 };
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::marker::PhantomData;
 
@@ -22,27 +22,71 @@ impl Player {
 pub enum PlayerStateMachine {
     Stopped(PlayerState<Stopped>),
     Moving(PlayerState<Moving>),
+    Interacting(PlayerState<Interacting>),
 }
 impl PlayerStateMachine {
     pub fn new(context: PlayerStateContext) -> Self {
         PlayerStateMachine::Stopped(PlayerState::<Stopped>::new(context))
     }
-    pub fn update(self, key_state: &KeyState) -> Self {
+    pub fn update(self, key_state: &KeyState, occupancy: &Occupancy) -> Self {
         match self {
-            PlayerStateMachine::Stopped(state) => state.update(key_state).into(),
+            PlayerStateMachine::Stopped(state) => state.update(key_state, occupancy).into(),
             PlayerStateMachine::Moving(state) => state.update().into(),
+            PlayerStateMachine::Interacting(state) => PlayerStateMachine::Interacting(state),
         }
     }
-    pub fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
+    pub fn finish_interaction(self) -> Self {
         match self {
-            PlayerStateMachine::Stopped(state) => state.draw(renderer, sprite_sheet_store),
-            PlayerStateMachine::Moving(state) => state.draw(renderer, sprite_sheet_store),
+            PlayerStateMachine::Interacting(state) => PlayerStateMachine::Stopped(state.finish()),
+            state => state,
+        }
+    }
+    // Puts the player on `tile` of a new map, standing still.
+    pub fn warp(self, tile: &Point, direction: Direction) -> Self {
+        let mut context = match self {
+            PlayerStateMachine::Stopped(state) => state.context,
+            PlayerStateMachine::Moving(state) => state.context,
+            PlayerStateMachine::Interacting(state) => state.context,
+        };
+        context.position = Point {
+            x: tile.x * TILE_WIDTH,
+            y: tile.y * TILE_HEIGHT,
+        };
+        context.direction = direction;
+        context.reset_frame();
+        PlayerStateMachine::new(context)
+    }
+    pub fn is_interacting(&self) -> bool {
+        matches!(self, PlayerStateMachine::Interacting(_))
+    }
+    pub fn is_moving(&self) -> bool {
+        matches!(self, PlayerStateMachine::Moving(_))
+    }
+    // `layers` are sheets drawn over the body, like the leader's equipment.
+    pub fn draw<'a>(
+        &'a self,
+        queue: &mut RenderQueue<'a>,
+        sprite_sheet_store: &'a SpriteSheetStore,
+        layers: &[&str],
+    ) -> Result<()> {
+        match self {
+            PlayerStateMachine::Stopped(state) => state.draw(queue, sprite_sheet_store, layers),
+            PlayerStateMachine::Moving(state) => state.draw(queue, sprite_sheet_store, layers),
+            PlayerStateMachine::Interacting(state) => state.draw(queue, sprite_sheet_store, layers),
+        }
+    }
+    pub fn context(&self) -> &PlayerStateContext {
+        match self {
+            PlayerStateMachine::Stopped(state) => &state.context,
+            PlayerStateMachine::Moving(state) => &state.context,
+            PlayerStateMachine::Interacting(state) => &state.context,
         }
     }
 }
 
 pub struct Stopped;
 pub struct Moving;
+pub struct Interacting;
 #[derive(Deserialize, Serialize)]
 pub struct PlayerState<S> {
     #[serde(flatten)]
@@ -50,10 +94,22 @@ pub struct PlayerState<S> {
     state: PhantomData<S>,
 }
 impl<S> PlayerState<S> {
-    fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
-        let frame_name = self.context.frame_name()?;
-        let sprite_sheet = sprite_sheet_store.get(&self.context.sprite_source)?;
-        renderer.draw_image(sprite_sheet, &frame_name, &self.context.position)
+    fn draw<'a>(
+        &'a self,
+        queue: &mut RenderQueue<'a>,
+        sprite_sheet_store: &'a SpriteSheetStore,
+        layers: &[&str],
+    ) -> Result<()> {
+        let (frame_name, options) = self.context.frame()?;
+        let mut sprite_sheets = vec![sprite_sheet_store.get(&self.context.sprite_source)?];
+        for layer in layers {
+            sprite_sheets.push(sprite_sheet_store.get(layer)?);
+        }
+        let position = &self.context.position;
+        queue.submit(RenderLayer::Entities, feet(position), move |renderer| {
+            renderer.draw_layers(&sprite_sheets, &frame_name, position, &options)
+        });
+        Ok(())
     }
 }
 
@@ -64,29 +120,20 @@ impl PlayerState<Stopped> {
             state: PhantomData::<Stopped>,
         }
     }
-    fn update(mut self, key_state: &KeyState) -> PlayerStoppedEndState {
+    fn update(mut self, key_state: &KeyState, occupancy: &Occupancy) -> PlayerStoppedEndState {
         self.context.reset_frame();
-        match self.context.direction {
-            Direction::Left => {
-                if key_state.is_pressed(KEY_CODE_ARROW_LEFT) {
-                    self.context.move_();
-                }
-            }
-            Direction::Up => {
-                if key_state.is_pressed(KEY_CODE_ARROW_UP) {
-                    self.context.move_();
-                }
-            }
-            Direction::Right => {
-                if key_state.is_pressed(KEY_CODE_ARROW_RIGHT) {
-                    self.context.move_();
-                }
-            }
-            Direction::Down => {
-                if key_state.is_pressed(KEY_CODE_ARROW_DOWN) {
-                    self.context.move_();
-                }
-            }
+        if key_state.is_action_just_pressed(Action::Confirm)
+            || key_state.is_action_just_pressed(Action::Cancel)
+        {
+            return PlayerStoppedEndState::Interact(PlayerState::<Interacting> {
+                context: self.context,
+                state: PhantomData::<Interacting>,
+            });
+        }
+        if key_state.is_action_pressed(self.context.direction.action())
+            && occupancy.is_free(&self.context.tile_in_front())
+        {
+            self.context.move_();
         }
         if !self.context.fit() {
             self.context.increment_frame();
@@ -96,13 +143,13 @@ impl PlayerState<Stopped> {
             });
         }
 
-        if key_state.is_pressed(KEY_CODE_ARROW_LEFT) {
+        if key_state.is_action_pressed(Action::Left) {
             self.context.change_direction(Direction::Left)
-        } else if key_state.is_pressed(KEY_CODE_ARROW_UP) {
+        } else if key_state.is_action_pressed(Action::Up) {
             self.context.change_direction(Direction::Up);
-        } else if key_state.is_pressed(KEY_CODE_ARROW_RIGHT) {
+        } else if key_state.is_action_pressed(Action::Right) {
             self.context.change_direction(Direction::Right);
-        } else if key_state.is_pressed(KEY_CODE_ARROW_DOWN) {
+        } else if key_state.is_action_pressed(Action::Down) {
             self.context.change_direction(Direction::Down);
         }
         PlayerStoppedEndState::Continue(self)
@@ -112,6 +159,7 @@ impl PlayerState<Stopped> {
 enum PlayerStoppedEndState {
     Continue(PlayerState<Stopped>),
     Complete(PlayerState<Moving>),
+    Interact(PlayerState<Interacting>),
 }
 
 impl PlayerState<Moving> {
@@ -119,6 +167,7 @@ impl PlayerState<Moving> {
         self.context.increment_frame();
         self.context.move_();
         if self.context.fit() {
+            self.context.steps += 1;
             return PlayerMovingEndState::Complete(PlayerState::<Stopped> {
                 context: self.context,
                 state: PhantomData::<Stopped>,
@@ -133,11 +182,21 @@ enum PlayerMovingEndState {
     Complete(PlayerState<Stopped>),
 }
 
+impl PlayerState<Interacting> {
+    fn finish(self) -> PlayerState<Stopped> {
+        PlayerState::<Stopped> {
+            context: self.context,
+            state: PhantomData::<Stopped>,
+        }
+    }
+}
+
 impl From<PlayerStoppedEndState> for PlayerStateMachine {
     fn from(state: PlayerStoppedEndState) -> Self {
         match state {
             PlayerStoppedEndState::Complete(state) => PlayerStateMachine::Moving(state),
             PlayerStoppedEndState::Continue(state) => PlayerStateMachine::Stopped(state),
+            PlayerStoppedEndState::Interact(state) => PlayerStateMachine::Interacting(state),
         }
     }
 }
@@ -155,16 +214,12 @@ pub struct PlayerStateContext {
     sprite_source: String,
     position: Point,
     direction: Direction,
+    // Counts every tile walked, for random encounters.
+    #[serde(default)]
+    steps: u32,
     #[serde(skip)]
     frame: u8,
 }
-#[derive(Deserialize, Serialize)]
-enum Direction {
-    Left,
-    Up,
-    Right,
-    Down,
-}
 impl PlayerStateContext {
     pub fn new(sprite_source: &str) -> Self {
         Self {
@@ -172,21 +227,34 @@ impl PlayerStateContext {
             frame: 0,
             position: Point { x: 0, y: 0 },
             direction: Direction::Down,
+            steps: 0,
         }
     }
+    // Carries on counting from a save.
+    pub fn with_steps(mut self, steps: u32) -> Self {
+        self.steps = steps;
+        self
+    }
+    pub fn position(&self) -> &Point {
+        &self.position
+    }
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+    pub fn tile_in_front(&self) -> Point {
+        tile_in_front(&self.position, self.direction)
+    }
     fn move_(&mut self) {
-        match self.direction {
-            Direction::Left => self.position.x -= 4,
-            Direction::Up => self.position.y -= 4,
-            Direction::Down => self.position.y += 4,
-            Direction::Right => self.position.x += 4,
-        }
+        step(&mut self.position, self.direction);
     }
     fn change_direction(&mut self, direction: Direction) {
         self.direction = direction;
     }
     fn fit(&self) -> bool {
-        self.position.x % TILE_WIDTH == 0 && self.position.y % TILE_HEIGHT == 0
+        fit(&self.position)
     }
     fn reset_frame(&mut self) {
         self.frame = 0;
@@ -194,19 +262,7 @@ impl PlayerStateContext {
     fn increment_frame(&mut self) {
         self.frame = (self.frame + 1) % 16;
     }
-    fn frame_name(&self) -> Result<String> {
-        let frame_name = match self.direction {
-            Direction::Left => "left",
-            Direction::Up => "up",
-            Direction::Down => "down",
-            Direction::Right => "right",
-        };
-        let frame: u8 = match self.frame / 4 {
-            0 | 2 => 2,
-            1 => 3,
-            3 => 1,
-            _ => return Err(anyhow!("invalid frame logic")),
-        };
-        Ok(format!("{}0{}.png", frame_name, frame))
+    fn frame(&self) -> Result<(String, DrawOptions<'static>)> {
+        walk_frame(self.direction, self.frame)
     }
 }
@@ -0,0 +1,90 @@
+use super::state::GameState;
+use super::Direction;
+use crate::engine::{storage, Point};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+const SAVE_KEY: &str = "rq.save";
+
+// Where the party stood and everything it had, as written by an inn. Maps
+// are loaded afresh; what changed on them is kept in the state's flags.
+#[derive(Deserialize, Serialize)]
+pub struct Save<S> {
+    pub map: String,
+    pub tile: Point,
+    pub facing: Direction,
+    // Steps walked so far, which the encounter grace is counted against.
+    #[serde(default)]
+    pub steps: u32,
+    pub state: S,
+}
+
+pub fn write(save: &Save<&GameState>) -> Result<()> {
+    let json = serde_json::to_string(save)?;
+    storage::store(SAVE_KEY, &json)
+}
+
+pub fn read() -> Result<Option<Save<GameState>>> {
+    let Some(json) = storage::load(SAVE_KEY)? else {
+        return Ok(None);
+    };
+    parse(&json)
+        .map(Some)
+        .map_err(|error| anyhow!("{}: {}", SAVE_KEY, error))
+}
+
+fn parse(json: &str) -> serde_json::Result<Save<GameState>> {
+    let mut save: Save<GameState> = serde_json::from_str(json)?;
+    // Saves written before steps were kept start counting again from 0.
+    save.state.last_battle = save.state.last_battle.min(save.steps);
+    Ok(save)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(save: &Save<&GameState>) -> Save<GameState> {
+        parse(&serde_json::to_string(save).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn steps_survive_a_reload() {
+        let mut state = GameState::new(1);
+        state.last_battle = 125;
+        let loaded = round_trip(&Save {
+            map: "cave".to_string(),
+            tile: Point { x: 3, y: 4 },
+            facing: Direction::Left,
+            steps: 130,
+            state: &state,
+        });
+        assert_eq!(loaded.map, "cave");
+        assert_eq!(loaded.tile, Point { x: 3, y: 4 });
+        assert_eq!(loaded.steps, 130);
+        assert_eq!(loaded.state.last_battle, 125);
+        assert!(loaded.state.in_grace(loaded.steps, 10));
+        assert!(!loaded.state.in_grace(loaded.steps + 5, 10));
+    }
+
+    #[test]
+    fn saves_without_steps_still_allow_encounters() {
+        let mut state = GameState::new(1);
+        state.last_battle = 500;
+        let mut json = serde_json::to_value(Save {
+            map: "field".to_string(),
+            tile: Point { x: 0, y: 0 },
+            facing: Direction::Down,
+            steps: 0,
+            state: &state,
+        })
+        .unwrap();
+        json.as_object_mut().unwrap().remove("steps");
+        let loaded = parse(&json.to_string()).unwrap();
+        assert_eq!(loaded.steps, 0);
+        assert_eq!(loaded.state.last_battle, 0);
+        assert!(loaded.state.in_grace(9, 10));
+        assert!(!loaded.state.in_grace(10, 10));
+    }
+}
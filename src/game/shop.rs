@@ -0,0 +1,406 @@
+use super::interaction::{InteractionHandler, InteractionStatus};
+use super::item::{ItemData, ItemKind, Items};
+use super::state::GameState;
+use crate::engine::{
+    Action, Align, KeyState, ListItem, Rect, Renderer, SpriteSheetStore, Ui, UiEvent, WidgetId,
+    LINE_HEIGHT, PADDING, TEXT_COLOR,
+};
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use std::rc::Rc;
+
+const BUY: usize = 0;
+const SELL: usize = 1;
+const LEAVE: usize = 2;
+const LIST_ROWS: i16 = 8;
+const CANNOT_EQUIP_COLOR: &str = "#808080";
+
+// A shop kept by an NPC, as given in the map file.
+#[derive(Clone, Deserialize)]
+pub struct ShopData {
+    pub greeting: String,
+    // What the shop sells, in order.
+    pub goods: Vec<String>,
+    // The kinds of item it buys back; any item with a price when empty.
+    #[serde(default)]
+    pub buys: Vec<ItemKind>,
+    // The share of an item's price paid for it.
+    pub sell_ratio: f32,
+}
+impl ShopData {
+    fn validate(&self, keeper: &str, items: &Items) -> Result<()> {
+        let error = |field: String, message: String| {
+            Err(anyhow!("shop of {}: {}: {}", keeper, field, message))
+        };
+        for (index, item) in self.goods.iter().enumerate() {
+            let field = format!("goods[{}]", index);
+            match items.get(item) {
+                None => return error(field, format!("unknown item {}", item)),
+                Some(ItemData { price: None, .. }) => {
+                    return error(field, format!("{} has no price", item))
+                }
+                Some(_) => {}
+            }
+        }
+        if !(0.0..=1.0).contains(&self.sell_ratio) {
+            return error(
+                "sell_ratio".to_string(),
+                "must be between 0 and 1".to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    // What the shop pays for `item`, if it takes it at all.
+    fn offer(&self, item: &str, items: &Items) -> Option<i32> {
+        let data = items.get(item)?;
+        if !self.buys.is_empty() && !self.buys.contains(&data.kind) {
+            return None;
+        }
+        Some((data.price? as f32 * self.sell_ratio).floor() as i32)
+    }
+}
+
+enum Deal {
+    Buy,
+    Sell,
+}
+
+// Buying goods for any member's bag and selling from one, with a window
+// showing who could wear a piece of equipment before it is bought.
+pub struct Shop {
+    keeper: String,
+    data: ShopData,
+    items: Rc<Items>,
+    ui: Ui,
+    commands: WidgetId,
+    gold: WidgetId,
+    goods_window: WidgetId,
+    goods: WidgetId,
+    members_window: WidgetId,
+    members: WidgetId,
+    fit: WidgetId,
+    wearers: Vec<WidgetId>,
+    info: WidgetId,
+    deal: Option<Deal>,
+    seller: usize,
+    // Shown in place of the description until a button is pressed.
+    message: bool,
+}
+impl Shop {
+    pub fn new(keeper: &str, data: &ShopData, items: Rc<Items>, state: &GameState) -> Result<Self> {
+        data.validate(keeper, &items)?;
+        let count = state.party.len().max(1) as i16;
+        let mut ui = Ui::new();
+        let window = |ui: &mut Ui, x: i16, y: i16, w: i16, rows: i16| {
+            ui.window(
+                None,
+                Rect {
+                    x,
+                    y,
+                    w,
+                    h: LINE_HEIGHT * rows + PADDING * 2,
+                },
+            )
+        };
+        let inside = |w: i16, rows: i16| Rect {
+            x: PADDING,
+            y: PADDING,
+            w: w - PADDING * 2,
+            h: LINE_HEIGHT * rows,
+        };
+        let commands_window = window(&mut ui, 16, 16, 160, 3);
+        let commands = ui.list(
+            Some(commands_window),
+            inside(160, 3),
+            vec![
+                ListItem::new("Buy"),
+                ListItem::new("Sell"),
+                ListItem::new("Leave"),
+            ],
+            1,
+        );
+        let gold_window = window(&mut ui, 184, 16, 280, 1);
+        let gold = ui.label(Some(gold_window), inside(280, 1), "");
+        ui.label_mut(gold)?.align = Align::Right;
+        let goods_window = window(&mut ui, 184, 72, 280, LIST_ROWS);
+        let goods = ui.list(Some(goods_window), inside(280, LIST_ROWS), Vec::new(), 1);
+        ui.set_visible(goods_window, false);
+        let members_window = window(&mut ui, 16, 120, 160, count);
+        let members = ui.list(
+            Some(members_window),
+            inside(160, count),
+            state
+                .party
+                .iter()
+                .map(|member| ListItem::new(&member.fighter.name))
+                .collect(),
+            1,
+        );
+        ui.set_visible(members_window, false);
+
+        // Everyone who could wear the piece under the cursor is lit up.
+        let fit = window(&mut ui, 16, 248, 160, count + 1);
+        let row = |index: i16| Rect {
+            y: PADDING + LINE_HEIGHT * index,
+            ..inside(160, 1)
+        };
+        ui.label(Some(fit), row(0), "Can equip:");
+        let wearers = state
+            .party
+            .iter()
+            .enumerate()
+            .map(|(index, member)| ui.label(Some(fit), row(index as i16 + 1), &member.fighter.name))
+            .collect();
+        ui.set_visible(fit, false);
+
+        let info_window = window(&mut ui, 16, 392, 448, 2);
+        let info = ui.label(Some(info_window), inside(448, 2), "");
+        ui.focus(commands);
+        let mut shop = Self {
+            keeper: keeper.to_string(),
+            data: data.clone(),
+            items,
+            ui,
+            commands,
+            gold,
+            goods_window,
+            goods,
+            members_window,
+            members,
+            fit,
+            wearers,
+            info,
+            deal: None,
+            seller: 0,
+            message: false,
+        };
+        shop.show_gold(state)?;
+        Ok(shop)
+    }
+
+    fn show_gold(&mut self, state: &GameState) -> Result<()> {
+        self.ui.set_text(self.gold, &format!("{} G", state.gold))
+    }
+
+    fn show_goods(&mut self) -> Result<()> {
+        let goods = self
+            .data
+            .goods
+            .iter()
+            .map(|item| {
+                let price = self.items.get(item).and_then(|data| data.price);
+                ListItem::new(&format!("{}  {} G", item, price.unwrap_or(0)))
+            })
+            .collect();
+        self.ui.set_items(self.goods, goods)?;
+        self.ui.set_visible(self.goods_window, true);
+        Ok(())
+    }
+
+    // The seller's bag, priced at what the shop pays; the rest is greyed out.
+    fn show_bag(&mut self, state: &GameState) -> Result<()> {
+        let stacks = &state.party[self.seller].bag.stacks;
+        let items = stacks
+            .iter()
+            .map(|stack| match self.data.offer(&stack.item, &self.items) {
+                Some(offer) => {
+                    ListItem::new(&format!("{} x{}  {} G", stack.item, stack.count, offer))
+                }
+                None => ListItem::disabled(&format!("{} x{}", stack.item, stack.count)),
+            })
+            .collect();
+        let cursor = self.ui.list_mut(self.goods)?.cursor;
+        self.ui.set_items(self.goods, items)?;
+        let list = self.ui.list_mut(self.goods)?;
+        list.cursor = cursor.min(list.items.len().saturating_sub(1));
+        self.ui.set_visible(self.goods_window, true);
+        Ok(())
+    }
+
+    // The item under the goods cursor, whichever way the deal goes.
+    fn selected(&mut self, state: &GameState) -> Result<Option<String>> {
+        let cursor = self.ui.list_mut(self.goods)?.cursor;
+        Ok(match self.deal {
+            Some(Deal::Buy) => self.data.goods.get(cursor).cloned(),
+            Some(Deal::Sell) => state.party[self.seller]
+                .bag
+                .stacks
+                .get(cursor)
+                .map(|stack| stack.item.clone()),
+            None => None,
+        })
+    }
+
+    fn say(&mut self, text: &str) -> Result<()> {
+        self.ui.set_text(self.info, text)?;
+        self.message = true;
+        Ok(())
+    }
+
+    fn close_goods(&mut self) {
+        self.ui.set_visible(self.goods_window, false);
+        self.ui.set_visible(self.members_window, false);
+        self.ui.set_visible(self.fit, false);
+        while self.ui.focused() != Some(self.commands) && self.ui.blur().is_some() {}
+        self.deal = None;
+    }
+
+    fn buy(&self, item: &str, buyer: usize, state: &mut GameState) -> String {
+        let price = self
+            .items
+            .get(item)
+            .and_then(|data| data.price)
+            .unwrap_or(0);
+        let member = &mut state.party[buyer];
+        if state.gold < price {
+            return format!("{}: You don't have enough gold for that.", self.keeper);
+        }
+        if !member.bag.add(item, self.items.stack_limit(item)) {
+            return format!("{} has no room for the {}.", member.fighter.name, item);
+        }
+        state.gold -= price;
+        format!(
+            "{}: Thank you! {} takes the {}.",
+            self.keeper, member.fighter.name, item
+        )
+    }
+
+    fn sell(&self, item: &str, state: &mut GameState) -> String {
+        let Some(offer) = self.data.offer(item, &self.items) else {
+            return String::new();
+        };
+        state.party[self.seller].bag.remove(item);
+        state.gold += offer;
+        format!(
+            "{}: I'll give you {} gold for the {}.",
+            self.keeper, offer, item
+        )
+    }
+}
+
+impl InteractionHandler for Shop {
+    fn update(&mut self, key_state: &KeyState, state: &mut GameState) -> Result<InteractionStatus> {
+        if self.message {
+            let pressed = key_state.is_action_just_pressed(Action::Confirm)
+                || key_state.is_action_just_pressed(Action::Cancel);
+            if pressed {
+                self.message = false;
+            }
+            return Ok(InteractionStatus::Continue);
+        }
+        let event = self.ui.update(key_state);
+        let item = self.selected(state)?;
+        match event {
+            Some(UiEvent::Selected(id, BUY)) if id == self.commands => {
+                self.deal = Some(Deal::Buy);
+                self.show_goods()?;
+                self.ui.focus(self.goods);
+            }
+            Some(UiEvent::Selected(id, SELL)) if id == self.commands => {
+                self.deal = Some(Deal::Sell);
+                self.ui.set_visible(self.members_window, true);
+                self.ui.focus(self.members);
+            }
+            Some(UiEvent::Selected(id, LEAVE)) if id == self.commands => {
+                return Ok(InteractionStatus::Finished)
+            }
+            Some(UiEvent::Cancelled(id)) if id == self.commands => {
+                return Ok(InteractionStatus::Finished)
+            }
+            // Buying: the goods first, then who carries them.
+            Some(UiEvent::Selected(id, _)) if id == self.goods => match self.deal {
+                Some(Deal::Buy) => {
+                    self.ui.set_visible(self.members_window, true);
+                    self.ui.focus(self.members);
+                }
+                Some(Deal::Sell) => {
+                    if let Some(item) = item {
+                        let text = self.sell(&item, state);
+                        self.show_gold(state)?;
+                        self.show_bag(state)?;
+                        if state.party[self.seller].bag.stacks.is_empty() {
+                            self.ui.set_visible(self.goods_window, false);
+                            self.ui.blur();
+                        }
+                        self.say(&text)?;
+                    }
+                }
+                None => {}
+            },
+            Some(UiEvent::Cancelled(id)) if id == self.goods => match self.deal {
+                Some(Deal::Sell) => {
+                    self.ui.set_visible(self.goods_window, false);
+                    self.ui.blur();
+                }
+                _ => self.close_goods(),
+            },
+            // Selling: whose bag first, then what from it.
+            Some(UiEvent::Selected(id, index)) if id == self.members => match self.deal {
+                Some(Deal::Buy) => {
+                    if let Some(item) = item {
+                        let text = self.buy(&item, index, state);
+                        self.show_gold(state)?;
+                        self.ui.set_visible(self.members_window, false);
+                        self.ui.blur();
+                        self.say(&text)?;
+                    }
+                }
+                Some(Deal::Sell) => {
+                    self.seller = index;
+                    self.ui.set_items(self.goods, Vec::new())?;
+                    if state.party[index].bag.stacks.is_empty() {
+                        let name = state.party[index].fighter.name.clone();
+                        self.say(&format!("{} has nothing to sell.", name))?;
+                    } else {
+                        self.show_bag(state)?;
+                        self.ui.focus(self.goods);
+                    }
+                }
+                None => {}
+            },
+            Some(UiEvent::Cancelled(id)) if id == self.members => match self.deal {
+                Some(Deal::Buy) => {
+                    self.ui.set_visible(self.members_window, false);
+                    self.ui.blur();
+                }
+                _ => self.close_goods(),
+            },
+            _ => {}
+        }
+
+        let item = self.selected(state)?;
+        let data = item.as_deref().and_then(|item| self.items.get(item));
+        let equipment = data.is_some_and(|data| data.kind.slot().is_some());
+        self.ui
+            .set_visible(self.fit, equipment && matches!(self.deal, Some(Deal::Buy)));
+        if equipment {
+            for (id, member) in self.wearers.iter().zip(&state.party) {
+                let wears = data.is_some_and(|data| data.equippable_by(&member.class));
+                self.ui.label_mut(*id)?.color = if wears {
+                    TEXT_COLOR
+                } else {
+                    CANNOT_EQUIP_COLOR
+                }
+                .to_string();
+            }
+        }
+        if !self.message {
+            let text = match self.ui.focused() {
+                Some(id) if id == self.goods => data.map(|data| data.description.clone()),
+                Some(id) if id == self.members => Some(match self.deal {
+                    Some(Deal::Buy) => "Who will carry it?".to_string(),
+                    _ => "Whose things will you sell?".to_string(),
+                }),
+                _ => Some(format!("{}: {}", self.keeper, self.data.greeting)),
+            };
+            self.ui.set_text(self.info, text.as_deref().unwrap_or(""))?;
+        }
+        Ok(InteractionStatus::Continue)
+    }
+
+    fn draw(&self, renderer: &Renderer, sprite_sheet_store: &SpriteSheetStore) -> Result<()> {
+        self.ui.draw(renderer, sprite_sheet_store)
+    }
+}
@@ -0,0 +1,37 @@
+use super::flags::Flags;
+use super::monster::Bestiary;
+use super::party::Member;
+use crate::engine::Random;
+
+use serde::{Deserialize, Serialize};
+
+// Progress that outlives a single interaction and belongs in a save.
+#[derive(Deserialize, Serialize)]
+pub struct GameState {
+    pub flags: Flags,
+    pub random: Random,
+    pub party: Vec<Member>,
+    #[serde(default)]
+    pub gold: i32,
+    #[serde(default)]
+    pub bestiary: Bestiary,
+    // The player's step count when the last battle ended.
+    pub last_battle: u32,
+}
+impl GameState {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            flags: Flags::new(),
+            random: Random::new(seed),
+            party: Vec::new(),
+            gold: 0,
+            bestiary: Bestiary::default(),
+            last_battle: 0,
+        }
+    }
+
+    // Whether a battle ended too few steps ago for another to start.
+    pub fn in_grace(&self, steps: u32, grace: u32) -> bool {
+        steps < self.last_battle + grace
+    }
+}
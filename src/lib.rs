@@ -3,7 +3,7 @@ mod browser;
 mod engine;
 mod game;
 
-use crate::engine::GameLoop;
+use crate::engine::{GameLoop, RenderBackend, ScreenSettings};
 use crate::game::RQ;
 use wasm_bindgen::prelude::*;
 
@@ -13,10 +13,31 @@ pub fn main_js() -> Result<(), JsValue> {
     console_error_panic_hook::set_once();
 
     browser::spawn_local(async move {
-        let game = RQ::new();
-        GameLoop::start(game)
-            .await
-            .expect("error starting GameLoop");
+        // `?seed=1234` replays a run, dungeon floors and all.
+        let seed = browser::query_parameter("seed")
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(|| browser::now().map_or(1, |now| (now * 1000.0) as u32));
+        // The last save is picked up unless a run is replayed or `?new=1`
+        // starts over.
+        let resume =
+            browser::query_parameter("seed").is_none() && browser::query_parameter("new").is_none();
+        let game = RQ::new(seed, resume);
+        // `?renderer=canvas2d` skips WebGL2, for comparing the two.
+        let backend = match browser::query_parameter("renderer").as_deref() {
+            Some("canvas2d") => RenderBackend::Canvas2d,
+            _ => RenderBackend::WebGl2,
+        };
+        GameLoop::start(
+            game,
+            ScreenSettings {
+                width: 480,
+                height: 480,
+                letterbox: true,
+                backend,
+            },
+        )
+        .await
+        .expect("error starting GameLoop");
     });
 
     Ok(())